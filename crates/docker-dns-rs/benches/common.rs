@@ -0,0 +1,100 @@
+//! Runs the daemon the benchmarks measure, and queries it.
+
+use std::net::{Ipv4Addr, SocketAddr};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+use hickory_server::proto::op::{Message, Query};
+use hickory_server::proto::rr::RecordType;
+use tokio::net::UdpSocket;
+use tokio::time::timeout;
+
+/// How long each measurement runs.
+pub const DURATION: Duration = Duration::from_secs(5);
+
+/// Concurrent clients, enough to keep every core busy.
+pub const CLIENTS: usize = 64;
+
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The daemon, without a Docker daemon to connect to, killed when dropped.
+pub struct Daemon {
+    child: Child,
+    dns: SocketAddr,
+}
+
+impl Daemon {
+    /// Starts the daemon serving `static.docker.` on a free port, with `args` added.
+    pub async fn start(args: &[&str]) -> Self {
+        let dns = free_address();
+
+        let child = Command::new(env!("CARGO_BIN_EXE_docker-dns-rs"))
+            .args(["--dns-bind", &dns.to_string()])
+            .args(["--docker", "unix:///nonexistent"])
+            .args(["--record", "static.docker:10.0.0.1"])
+            .args(args)
+            .env("RUST_LOG", "error")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        let daemon = Self { child, dns };
+
+        let client = connect(dns).await;
+        let query = query_for("static.docker.");
+        let start = Instant::now();
+
+        while !ask(&client, &query).await {
+            assert!(start.elapsed() < STARTUP_TIMEOUT, "The daemon didn't start");
+        }
+
+        daemon
+    }
+
+    /// Where it serves DNS, over UDP and TCP.
+    pub const fn dns(&self) -> SocketAddr {
+        self.dns
+    }
+}
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        let _r = self.child.kill();
+        let _r = self.child.wait();
+    }
+}
+
+/// An address nothing is bound to, for the daemon to take.
+pub fn free_address() -> SocketAddr {
+    std::net::UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))
+        .unwrap()
+        .local_addr()
+        .unwrap()
+}
+
+pub fn query_for(name: &str) -> Vec<u8> {
+    let mut query = Message::query();
+    query.add_query(Query::query(name.parse().unwrap(), RecordType::A));
+
+    query.to_vec().unwrap()
+}
+
+pub async fn connect(address: SocketAddr) -> UdpSocket {
+    let client = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    client.connect(address).await.unwrap();
+
+    client
+}
+
+/// Sends `query`, returns whether it was answered. A dropped datagram is a lost query, not a
+/// failure.
+pub async fn ask(client: &UdpSocket, query: &[u8]) -> bool {
+    let mut buffer = [0_u8; 512];
+
+    client.send(query).await.is_ok()
+        && matches!(
+            timeout(Duration::from_millis(100), client.recv(&mut buffer)).await,
+            Ok(Ok(_))
+        )
+}
@@ -0,0 +1,159 @@
+//! Compares query latency on an idle table with one that is constantly being changed, through
+//! manual records added and removed over the admin API.
+//!
+//! Run with `cargo bench --bench query_latency_during_churn`.
+
+mod common;
+
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use http_body_util::Full;
+use hyper::body::Bytes;
+use hyper::client::conn::http1::SendRequest;
+use hyper::header::{AUTHORIZATION, CONTENT_TYPE};
+use hyper::{Method, Request};
+use hyper_util::rt::TokioIo;
+use tokio::net::TcpStream;
+use tokio_util::sync::CancellationToken;
+
+use crate::common::{CLIENTS, DURATION, Daemon, ask, connect, free_address, query_for};
+
+const TOKEN: &str = "benchmark";
+
+struct AdminClient {
+    sender: SendRequest<Full<Bytes>>,
+}
+
+impl AdminClient {
+    async fn connect(address: SocketAddr) -> Self {
+        let stream = TcpStream::connect(address).await.unwrap();
+
+        let (sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+            .await
+            .unwrap();
+        tokio::spawn(connection);
+
+        Self { sender }
+    }
+
+    async fn manual_record(&mut self, method: Method, name: &str, address: &str) {
+        let request = Request::builder()
+            .method(method)
+            .uri("/records/manual")
+            .header("host", "localhost")
+            .header(AUTHORIZATION, format!("Bearer {}", TOKEN))
+            .header(CONTENT_TYPE, "application/json")
+            .body(Full::new(Bytes::from(format!(
+                r#"{{"name":"{}","address":"{}"}}"#,
+                name, address
+            ))))
+            .unwrap();
+
+        let response = self.sender.send_request(request).await.unwrap();
+
+        assert!(response.status().is_success(), "{}", response.status());
+    }
+}
+
+/// Queries a manual record, optionally while another task keeps adding and removing records.
+/// Returns the sorted latencies.
+async fn measure_query_latency(churn: bool) -> Vec<Duration> {
+    let admin = free_address();
+    let daemon = Daemon::start(&["--admin-bind", &admin.to_string(), "--admin-token", TOKEN]).await;
+
+    let mut admin_client = AdminClient::connect(admin).await;
+    admin_client
+        .manual_record(Method::POST, "web.docker.", "10.0.0.1")
+        .await;
+
+    let cancellation_token = CancellationToken::new();
+
+    let churner = churn.then(|| {
+        let cancellation_token = cancellation_token.clone();
+
+        tokio::spawn(async move {
+            let mut records = 0_u64;
+
+            for i in (0_u8..=255).cycle() {
+                if cancellation_token.is_cancelled() {
+                    break;
+                }
+
+                let name = format!("churn-{}.docker.", i);
+                let address = format!("10.0.1.{}", i);
+
+                admin_client
+                    .manual_record(Method::POST, &name, &address)
+                    .await;
+                admin_client
+                    .manual_record(Method::DELETE, &name, &address)
+                    .await;
+
+                records += 1;
+            }
+
+            records
+        })
+    });
+
+    let query = query_for("web.docker.");
+    let start = Instant::now();
+
+    let readers = std::iter::repeat_with(|| {
+        let query = query.clone();
+        let address = daemon.dns();
+
+        tokio::spawn(async move {
+            let client = connect(address).await;
+
+            let mut latencies = vec![];
+
+            while start.elapsed() < DURATION {
+                let query_start = Instant::now();
+
+                if ask(&client, &query).await {
+                    latencies.push(query_start.elapsed());
+                }
+            }
+
+            latencies
+        })
+    })
+    .take(CLIENTS)
+    .collect::<Vec<_>>();
+
+    let mut latencies = vec![];
+
+    for reader in readers {
+        latencies.extend(reader.await.unwrap());
+    }
+
+    cancellation_token.cancel();
+
+    if let Some(churner) = churner {
+        println!("{} records churned", churner.await.unwrap());
+    }
+
+    latencies.sort_unstable();
+
+    latencies
+}
+
+#[tokio::main]
+async fn main() {
+    for churn in [false, true] {
+        let latencies = measure_query_latency(churn).await;
+
+        let percentile = |p: usize| latencies[(latencies.len() - 1) * p / 100];
+
+        println!(
+            "churn: {}, {} queries, p50: {:?}, p99: {:?}, max: {:?}",
+            churn,
+            latencies.len(),
+            percentile(50),
+            percentile(99),
+            percentile(100),
+        );
+    }
+}
@@ -0,0 +1,69 @@
+//! Compares a single UDP socket with one `SO_REUSEPORT` socket per core.
+//!
+//! Run with `cargo bench --bench udp_throughput`.
+
+mod common;
+
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread::available_parallelism;
+use std::time::Instant;
+
+use crate::common::{CLIENTS, DURATION, Daemon, ask, connect, query_for};
+
+async fn measure_udp_throughput(address: SocketAddr) -> f64 {
+    let query = Arc::new(query_for("static.docker."));
+
+    let answered = Arc::new(AtomicU64::new(0));
+    let start = Instant::now();
+
+    let clients = std::iter::repeat_with(|| {
+        let query = Arc::clone(&query);
+        let answered = Arc::clone(&answered);
+
+        tokio::spawn(async move {
+            let client = connect(address).await;
+
+            while start.elapsed() < DURATION {
+                if ask(&client, &query).await {
+                    answered.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        })
+    })
+    .take(CLIENTS)
+    .collect::<Vec<_>>();
+
+    for client in clients {
+        client.await.unwrap();
+    }
+
+    let elapsed = start.elapsed();
+
+    #[expect(
+        clippy::as_conversions,
+        clippy::cast_precision_loss,
+        reason = "Approximate is fine for a benchmark"
+    )]
+    let answered = answered.load(Ordering::Relaxed) as f64;
+
+    answered / elapsed.as_secs_f64()
+}
+
+#[tokio::main]
+async fn main() {
+    let cores = available_parallelism().unwrap();
+
+    for udp_sockets in [NonZeroUsize::MIN, cores] {
+        let daemon = Daemon::start(&["--udp-sockets", &udp_sockets.to_string()]).await;
+
+        let queries_per_second = measure_udp_throughput(daemon.dns()).await;
+
+        println!(
+            "{} UDP socket(s): {:.0} queries/s",
+            udp_sockets, queries_per_second
+        );
+    }
+}
@@ -0,0 +1,557 @@
+use std::convert::Infallible;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use http_body_util::Full;
+use hyper::body::{Bytes, Incoming};
+use hyper::header::{ALLOW, CONTENT_TYPE, HeaderValue};
+use hyper::service::service_fn;
+use hyper::{Method, Response, StatusCode};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto::Builder;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream, UnixListener, UnixStream};
+use tokio::task::JoinSet;
+use tokio::time::{MissedTickBehavior, interval};
+use tokio_util::sync::CancellationToken;
+use tracing::{Level, event};
+
+use crate::admin::manual::{MANUAL_RECORDS_PATH, change_manual_record, manual_records};
+use crate::config::RawRecord;
+use crate::docker::daemons::Daemons;
+use crate::docker::status::DaemonStatus;
+
+mod manual;
+
+pub const CONTAINERS_PATH: &str = "/containers";
+pub const EXPORT_PATH: &str = "/export";
+pub const NETWORKS_PATH: &str = "/networks";
+pub const RECORDS_PATH: &str = "/records";
+pub const STATUS_PATH: &str = "/status";
+pub const ZONE_PATH: &str = "/zone";
+
+const JSON_CONTENT_TYPE: &str = "application/json";
+
+/// How often expired manual records are removed.
+const EXPIRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What the admin API reads from. Only manual records can be changed through it.
+pub struct AdminState {
+    daemons: Arc<Daemons>,
+    records: Vec<RawRecord>,
+    /// Bearer token that changing manual records requires, they can't be changed without one.
+    token: Option<Box<str>>,
+}
+
+impl AdminState {
+    pub fn new(daemons: Arc<Daemons>, records: Vec<RawRecord>, token: Option<Box<str>>) -> Self {
+        Self {
+            daemons,
+            records,
+            token,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StaticRecord {
+    pub name: String,
+    pub address: IpAddr,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ZoneRecord {
+    pub name: String,
+    pub r#type: String,
+    pub ttl: u32,
+    pub data: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Status {
+    /// In the order the daemons were configured.
+    pub daemons: Vec<DaemonStatus>,
+}
+
+/// A zone in the RFC 1035 master file format.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedZone {
+    pub origin: String,
+    /// What the zone is saved as by `export --output-dir`.
+    pub file_name: String,
+    pub contents: String,
+}
+
+/// Where the admin API is served, over TCP, on the local control socket, or both.
+pub struct AdminListeners {
+    pub tcp: Option<TcpListener>,
+    /// Removed again when the server stops.
+    pub control_socket: Option<(UnixListener, PathBuf)>,
+}
+
+async fn accept_tcp(listener: Option<&TcpListener>) -> io::Result<(TcpStream, SocketAddr)> {
+    match listener {
+        Some(listener) => listener.accept().await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn accept_unix(listener: Option<&UnixListener>) -> io::Result<UnixStream> {
+    match listener {
+        Some(listener) => listener.accept().await.map(|(stream, _)| stream),
+        None => std::future::pending().await,
+    }
+}
+
+pub async fn set_up_admin_server(
+    listeners: AdminListeners,
+    state: AdminState,
+    cancellation_token: CancellationToken,
+) {
+    let state = Arc::new(state);
+
+    let mut connections = JoinSet::new();
+
+    let mut expiry = interval(EXPIRY_INTERVAL);
+    expiry.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    let tcp = listeners.tcp.as_ref();
+    let unix = listeners
+        .control_socket
+        .as_ref()
+        .map(|&(ref listener, _)| listener);
+
+    loop {
+        tokio::select! {
+            biased;
+            () = cancellation_token.cancelled() => {
+                event!(Level::INFO, "Admin API Server cancelled externally");
+                break;
+            },
+            _ = expiry.tick() => {
+                state
+                    .daemons
+                    .authority_wrapper()
+                    .expire_manual_records(SystemTime::now())
+                    .await;
+            },
+            result = accept_tcp(tcp) => match result {
+                Ok((stream, src)) => {
+                    connections.spawn(serve_connection(stream, Some(src), Arc::clone(&state)));
+                },
+                Err(error) => {
+                    event!(Level::WARN, ?error, "Failed to accept admin API connection");
+                },
+            },
+            result = accept_unix(unix) => match result {
+                Ok(stream) => {
+                    connections.spawn(serve_connection(stream, None, Arc::clone(&state)));
+                },
+                Err(error) => {
+                    event!(Level::WARN, ?error, "Failed to accept control socket connection");
+                },
+            },
+        }
+
+        // reap finished connections so the set doesn't grow unbounded
+        while connections.try_join_next().is_some() {}
+    }
+
+    connections.shutdown().await;
+
+    if let Some((_, ref path)) = listeners.control_socket
+        && let Err(error) = tokio::fs::remove_file(path).await
+    {
+        event!(
+            Level::WARN,
+            ?error,
+            path = %path.display(),
+            "Failed to remove control socket, its directory has to be writable by the user we run as"
+        );
+    }
+}
+
+/// `src` is absent for the control socket, its clients have no address.
+async fn serve_connection<S>(stream: S, src: Option<SocketAddr>, state: Arc<AdminState>)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let service = service_fn(move |request| handle_http_request(request, Arc::clone(&state)));
+
+    if let Err(error) = Builder::new(TokioExecutor::new())
+        .serve_connection(TokioIo::new(stream), service)
+        .await
+    {
+        event!(
+            Level::DEBUG,
+            ?error,
+            ?src,
+            "Admin API connection closed with error"
+        );
+    }
+}
+
+fn status_response(status: StatusCode) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::default());
+    *response.status_mut() = status;
+
+    response
+}
+
+fn method_not_allowed(allow: &'static str) -> Response<Full<Bytes>> {
+    let mut response = status_response(StatusCode::METHOD_NOT_ALLOWED);
+    response
+        .headers_mut()
+        .insert(ALLOW, HeaderValue::from_static(allow));
+
+    response
+}
+
+fn json_response<T: Serialize>(value: &T) -> Response<Full<Bytes>> {
+    match serde_json::to_vec(value) {
+        Ok(body) => {
+            let mut response = Response::new(Full::new(Bytes::from(body)));
+            response
+                .headers_mut()
+                .insert(CONTENT_TYPE, HeaderValue::from_static(JSON_CONTENT_TYPE));
+
+            response
+        },
+        Err(error) => {
+            event!(
+                Level::ERROR,
+                ?error,
+                "Failed to serialize admin API response"
+            );
+
+            status_response(StatusCode::INTERNAL_SERVER_ERROR)
+        },
+    }
+}
+
+async fn handle_http_request(
+    request: hyper::Request<Incoming>,
+    state: Arc<AdminState>,
+) -> Result<Response<Full<Bytes>>, Infallible> {
+    if request.uri().path() == MANUAL_RECORDS_PATH && request.method() != Method::GET {
+        return Ok(change_manual_record(request, &state).await);
+    }
+
+    if request.method() != Method::GET {
+        return Ok(method_not_allowed("GET"));
+    }
+
+    let response = match request.uri().path() {
+        MANUAL_RECORDS_PATH => json_response(&manual_records(&state).await),
+        CONTAINERS_PATH => json_response(&state.daemons.containers().await),
+        NETWORKS_PATH => json_response(&state.daemons.networks().await),
+        RECORDS_PATH => json_response(
+            &state
+                .records
+                .iter()
+                .map(|record| StaticRecord {
+                    name: record.name.to_string(),
+                    address: record.addr,
+                })
+                .collect::<Vec<_>>(),
+        ),
+        ZONE_PATH => json_response(
+            &state
+                .daemons
+                .authority_wrapper()
+                .forward_records()
+                .await
+                .into_iter()
+                .map(|record| ZoneRecord {
+                    name: record.name.to_string(),
+                    r#type: record.record_type().to_string(),
+                    ttl: record.ttl,
+                    data: record.data.to_string(),
+                })
+                .collect::<Vec<_>>(),
+        ),
+        STATUS_PATH => json_response(&Status {
+            daemons: state.daemons.statuses(),
+        }),
+        EXPORT_PATH => json_response(
+            &state
+                .daemons
+                .authority_wrapper()
+                .zone_files()
+                .await
+                .into_iter()
+                .map(|zone_file| ExportedZone {
+                    origin: zone_file.origin.to_string(),
+                    file_name: zone_file.file_name(),
+                    contents: zone_file.contents,
+                })
+                .collect::<Vec<_>>(),
+        ),
+        _ => status_response(StatusCode::NOT_FOUND),
+    };
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+    use std::sync::Arc;
+
+    use http_body_util::{BodyExt as _, Full};
+    use hyper::body::Bytes;
+    use hyper::header::{AUTHORIZATION, CONTENT_TYPE};
+    use hyper::{Method, StatusCode};
+    use hyper_util::rt::TokioIo;
+    use pretty_assertions::assert_eq;
+    use serde_json::{Value as JsonValue, json};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_util::sync::CancellationToken;
+
+    use crate::admin::{AdminListeners, AdminState, JSON_CONTENT_TYPE, set_up_admin_server};
+    use crate::config::RawRecord;
+    use crate::docker::daemons::Daemons;
+    use crate::table::{AuthorityWrapper, ChangeSet};
+
+    const TOKEN: &str = "secret";
+
+    async fn start_server() -> (SocketAddr, Arc<Daemons>, CancellationToken) {
+        let daemons = Arc::new(Daemons::new(
+            AuthorityWrapper::new("docker.".parse().unwrap()),
+            "docker.".parse().unwrap(),
+            false,
+            [Box::from("local")],
+        ));
+
+        let records = vec![RawRecord {
+            name: "static.docker.".parse().unwrap(),
+            addr: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+        }];
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let cancellation_token = CancellationToken::new();
+
+        tokio::spawn(set_up_admin_server(
+            AdminListeners {
+                tcp: Some(listener),
+                control_socket: None,
+            },
+            AdminState::new(Arc::clone(&daemons), records, Some(Box::from(TOKEN))),
+            cancellation_token.clone(),
+        ));
+
+        (address, daemons, cancellation_token)
+    }
+
+    async fn send(address: SocketAddr, method: Method, path: &str) -> (StatusCode, Bytes) {
+        send_with_body(address, method, path, None, "").await
+    }
+
+    async fn send_with_body(
+        address: SocketAddr,
+        method: Method,
+        path: &str,
+        token: Option<&str>,
+        body: &str,
+    ) -> (StatusCode, Bytes) {
+        let stream = TcpStream::connect(address).await.unwrap();
+
+        let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+            .await
+            .unwrap();
+        tokio::spawn(connection);
+
+        let mut request = hyper::Request::builder()
+            .method(method)
+            .uri(path)
+            .header("host", "localhost");
+
+        if let Some(token) = token {
+            request = request.header(AUTHORIZATION, format!("Bearer {}", token));
+        }
+
+        let request = request
+            .body(Full::new(Bytes::from(body.to_owned())))
+            .unwrap();
+
+        let response = sender.send_request(request).await.unwrap();
+        let status = response.status();
+
+        if status == StatusCode::OK {
+            assert_eq!(
+                response.headers().get(CONTENT_TYPE).unwrap(),
+                JSON_CONTENT_TYPE
+            );
+        }
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+
+        (status, body)
+    }
+
+    async fn get_json(address: SocketAddr, path: &str) -> JsonValue {
+        let (status, body) = send(address, Method::GET, path).await;
+
+        assert_eq!(status, StatusCode::OK);
+
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn lists_static_records_and_zone() {
+        let (address, daemons, cancellation_token) = start_server().await;
+
+        let mut change_set = ChangeSet::default();
+        change_set.add(
+            &"web.docker.".parse().unwrap(),
+            IpAddr::V4(Ipv4Addr::new(172, 17, 0, 2)),
+        );
+        daemons.authority_wrapper().apply(change_set).await;
+
+        assert_eq!(
+            get_json(address, "/records").await,
+            json!([{ "name": "static.docker.", "address": "10.0.0.1" }])
+        );
+
+        let zone = get_json(address, "/zone").await;
+
+        assert_eq!(
+            zone.as_array()
+                .unwrap()
+                .iter()
+                .map(|record| (record["name"].clone(), record["type"].clone()))
+                .collect::<Vec<_>>(),
+            [
+                (json!("docker."), json!("SOA")),
+                (json!("web.docker."), json!("A")),
+            ]
+        );
+        assert_eq!(zone[1]["data"], json!("172.17.0.2"));
+
+        assert_eq!(
+            get_json(address, "/export").await,
+            json!([{
+                "origin": "docker.",
+                "file_name": "docker.zone",
+                "contents": "$ORIGIN docker.\n\
+                             docker. 3600 IN SOA docker. docker. 0 0 0 0 0\n\
+                             web.docker. 5 IN A 172.17.0.2\n",
+            }])
+        );
+
+        assert_eq!(get_json(address, "/containers").await, json!([]));
+        assert_eq!(get_json(address, "/networks").await, json!([]));
+        assert_eq!(
+            get_json(address, "/status").await,
+            json!({ "daemons": [{ "daemon": "local", "state": "connecting" }] })
+        );
+
+        cancellation_token.cancel();
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_paths_and_methods() {
+        let (address, _daemons, cancellation_token) = start_server().await;
+
+        assert_eq!(
+            send(address, Method::GET, "/unknown").await.0,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            send(address, Method::POST, "/containers").await.0,
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+
+        cancellation_token.cancel();
+    }
+
+    #[tokio::test]
+    async fn manual_records_need_the_token() {
+        let (address, _daemons, cancellation_token) = start_server().await;
+
+        let body = r#"{ "name": "pinned.docker.", "address": "10.0.0.9", "expires_in": 600 }"#;
+
+        assert_eq!(
+            send_with_body(address, Method::POST, "/records/manual", None, body)
+                .await
+                .0,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            send_with_body(
+                address,
+                Method::POST,
+                "/records/manual",
+                Some("wrong"),
+                body
+            )
+            .await
+            .0,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(get_json(address, "/records/manual").await, json!([]));
+
+        assert_eq!(
+            send_with_body(address, Method::POST, "/records/manual", Some(TOKEN), body)
+                .await
+                .0,
+            StatusCode::NO_CONTENT
+        );
+
+        let manual = get_json(address, "/records/manual").await;
+
+        assert_eq!(manual[0]["name"], json!("pinned.docker."));
+        assert_eq!(manual[0]["address"], json!("10.0.0.9"));
+        assert!(manual[0]["expires"].is_string());
+
+        let zone = get_json(address, "/zone").await;
+
+        assert_eq!(zone[1]["name"], json!("pinned.docker."));
+        assert_eq!(zone[1]["data"], json!("10.0.0.9"));
+
+        let body = r#"{ "name": "pinned.docker.", "address": "10.0.0.9" }"#;
+
+        assert_eq!(
+            send_with_body(
+                address,
+                Method::DELETE,
+                "/records/manual",
+                Some(TOKEN),
+                body
+            )
+            .await
+            .0,
+            StatusCode::NO_CONTENT
+        );
+        assert_eq!(
+            send_with_body(
+                address,
+                Method::DELETE,
+                "/records/manual",
+                Some(TOKEN),
+                body
+            )
+            .await
+            .0,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(get_json(address, "/records/manual").await, json!([]));
+
+        let body = r#"{ "name": "pinned.docker.", "address": "10.0.0.9", "expires_in": 18446744073709551615 }"#;
+
+        assert_eq!(
+            send_with_body(address, Method::POST, "/records/manual", Some(TOKEN), body)
+                .await
+                .0,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(get_json(address, "/records/manual").await, json!([]));
+
+        cancellation_token.cancel();
+    }
+}
@@ -0,0 +1,185 @@
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime};
+
+use hickory_server::proto::rr::Name;
+use http_body_util::{BodyExt as _, Full, Limited};
+use hyper::body::{Bytes, Incoming};
+use hyper::header::{AUTHORIZATION, HeaderValue, WWW_AUTHENTICATE};
+use hyper::{Method, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use tracing::{Level, event};
+
+use crate::admin::{AdminState, method_not_allowed, status_response};
+use crate::table::ManualRecord;
+
+pub const MANUAL_RECORDS_PATH: &str = "/records/manual";
+
+/// A request body is one small JSON object.
+const MAX_BODY_SIZE: usize = 4096;
+
+/// The body of `POST` and `DELETE`, `expires_in` is ignored by the latter.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ManualRecordRequest {
+    name: String,
+    address: IpAddr,
+    /// Seconds until the record is removed, it stays until it's deleted when absent.
+    expires_in: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct ManualRecordSummary {
+    name: String,
+    address: IpAddr,
+    /// RFC 3339, in UTC.
+    expires: Option<String>,
+}
+
+pub async fn manual_records(state: &AdminState) -> Vec<ManualRecordSummary> {
+    state
+        .daemons
+        .authority_wrapper()
+        .manual_records()
+        .await
+        .into_iter()
+        .map(|record| ManualRecordSummary {
+            name: record.name.to_string(),
+            address: record.address,
+            expires: record
+                .expires
+                .and_then(|expires| OffsetDateTime::from(expires).format(&Rfc3339).ok()),
+        })
+        .collect()
+}
+
+fn error_response(status: StatusCode, message: String) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::new(Bytes::from(message)));
+    *response.status_mut() = status;
+
+    response
+}
+
+/// `seconds` from now, as long as that's a date we can show.
+fn expiry(seconds: u64) -> Option<SystemTime> {
+    let expires = SystemTime::now().checked_add(Duration::from_secs(seconds))?;
+
+    // RFC 3339 only has four digits for the year
+    let unix_timestamp = expires
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .ok()?
+        .as_secs();
+    OffsetDateTime::from_unix_timestamp(i64::try_from(unix_timestamp).ok()?)
+        .ok()
+        .filter(|expires| expires.year() <= 9999)?;
+
+    Some(expires)
+}
+
+/// Compares in constant time, so response times don't tell how much of a guess was right.
+fn is_authorized(request: &hyper::Request<Incoming>, token: &str) -> bool {
+    let Some(provided) = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    else {
+        return false;
+    };
+
+    provided.len() == token.len()
+        && provided
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |difference, (left, right)| difference | (left ^ right))
+            == 0
+}
+
+async fn read_request(request: hyper::Request<Incoming>) -> Result<ManualRecordRequest, String> {
+    let body = Limited::new(request.into_body(), MAX_BODY_SIZE)
+        .collect()
+        .await
+        .map_err(|error| error.to_string())?
+        .to_bytes();
+
+    serde_json::from_slice(&body).map_err(|error| error.to_string())
+}
+
+/// Handles `POST` and `DELETE`, which need the bearer token.
+pub async fn change_manual_record(
+    request: hyper::Request<Incoming>,
+    state: &AdminState,
+) -> Response<Full<Bytes>> {
+    let method = request.method().clone();
+
+    if method != Method::POST && method != Method::DELETE {
+        return method_not_allowed("GET, POST, DELETE");
+    }
+
+    let Some(ref token) = state.token else {
+        return error_response(
+            StatusCode::FORBIDDEN,
+            "Manual records are disabled, no admin token is configured".to_owned(),
+        );
+    };
+
+    if !is_authorized(&request, token) {
+        let mut response = status_response(StatusCode::UNAUTHORIZED);
+        response
+            .headers_mut()
+            .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+
+        return response;
+    }
+
+    let body = match read_request(request).await {
+        Ok(body) => body,
+        Err(message) => return error_response(StatusCode::BAD_REQUEST, message),
+    };
+
+    let mut name = match body.name.parse::<Name>() {
+        Ok(name) => name,
+        Err(error) => return error_response(StatusCode::BAD_REQUEST, error.to_string()),
+    };
+    name.set_fqdn(true);
+
+    let authority_wrapper = state.daemons.authority_wrapper();
+
+    if method == Method::DELETE {
+        return if authority_wrapper
+            .remove_manual_record(&name, body.address)
+            .await
+        {
+            status_response(StatusCode::NO_CONTENT)
+        } else {
+            status_response(StatusCode::NOT_FOUND)
+        };
+    }
+
+    let expires = match body.expires_in.map(expiry) {
+        None => None,
+        Some(Some(expires)) => Some(expires),
+        Some(None) => {
+            return error_response(
+                StatusCode::BAD_REQUEST,
+                "expires_in is too far in the future".to_owned(),
+            );
+        },
+    };
+
+    let record = ManualRecord {
+        name,
+        address: body.address,
+        expires,
+    };
+
+    match authority_wrapper.add_manual_record(record).await {
+        Ok(()) => status_response(StatusCode::NO_CONTENT),
+        Err(error) => {
+            event!(Level::DEBUG, ?error, "Rejected manual record");
+
+            error_response(StatusCode::BAD_REQUEST, error.to_string())
+        },
+    }
+}
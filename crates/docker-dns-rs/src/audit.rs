@@ -0,0 +1,258 @@
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+use color_eyre::eyre;
+use color_eyre::eyre::WrapErr as _;
+use hickory_server::proto::rr::Name;
+use serde::Serialize;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncWriteExt as _, BufWriter};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio_util::sync::CancellationToken;
+use tracing::{Level, event};
+
+/// What caused a change: the daemon and container it's for, and the Docker event,
+/// e.g. `container:start`. Manual and static records have neither daemon nor container.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Trigger {
+    #[serde(skip_serializing_if = "str::is_empty")]
+    pub daemon: Box<str>,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    pub container_id: Box<str>,
+    pub event: Box<str>,
+}
+
+impl Trigger {
+    pub fn new(daemon: &str, container_id: &str, event: &str) -> Self {
+        Self {
+            daemon: Box::from(daemon),
+            container_id: Box::from(container_id),
+            event: Box::from(event),
+        }
+    }
+
+    /// A change to the manual or static records, `event` is e.g. `admin:add`, `expired` or
+    /// `static`.
+    pub fn admin(event: &str) -> Self {
+        Self::new("", "", event)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Add,
+    Remove,
+    Rename,
+}
+
+/// The addresses a name resolved to.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RecordState {
+    pub name: String,
+    pub addresses: Vec<IpAddr>,
+}
+
+impl RecordState {
+    pub fn new(name: &Name, addresses: Vec<IpAddr>) -> Self {
+        Self {
+            name: name.to_string(),
+            addresses,
+        }
+    }
+}
+
+/// One line of the audit log.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct AuditEntry {
+    /// RFC 3339, in UTC.
+    pub timestamp: String,
+    pub action: Action,
+    /// The address that was added or removed, absent for renames.
+    pub address: Option<IpAddr>,
+    #[serde(flatten)]
+    pub trigger: Option<Trigger>,
+    pub before: RecordState,
+    pub after: RecordState,
+}
+
+impl AuditEntry {
+    pub fn new(
+        action: Action,
+        address: Option<IpAddr>,
+        trigger: Option<Trigger>,
+        before: RecordState,
+        after: RecordState,
+    ) -> Self {
+        let timestamp = OffsetDateTime::now_utc()
+            .format(&Rfc3339)
+            .expect("Formatting the current time never fails");
+
+        Self {
+            timestamp,
+            action,
+            address,
+            trigger,
+            before,
+            after,
+        }
+    }
+}
+
+/// Hands audit entries to the writer task.
+///
+/// Unbounded, so recording never waits while holding the zones lock, and nothing is dropped.
+#[derive(Clone)]
+pub struct AuditLog {
+    sender: UnboundedSender<AuditEntry>,
+}
+
+impl AuditLog {
+    pub fn new() -> (Self, UnboundedReceiver<AuditEntry>) {
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+
+        (Self { sender }, receiver)
+    }
+
+    pub fn record(&self, entry: AuditEntry) {
+        if let Err(error) = self.sender.send(entry) {
+            event!(Level::WARN, entry = ?error.0, "Audit log writer stopped, entry lost");
+        }
+    }
+}
+
+pub struct AuditWriter {
+    file: File,
+}
+
+impl AuditWriter {
+    /// Opens the file for appending right away, so a bad path fails at startup, and while we still
+    /// have our privileges.
+    pub async fn open(path: PathBuf) -> Result<Self, eyre::Report> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await
+            .wrap_err_with(|| format!("Failed to open audit log {}", path.display()))?;
+
+        Ok(Self { file })
+    }
+
+    pub async fn run(
+        self,
+        receiver: UnboundedReceiver<AuditEntry>,
+        cancellation_token: CancellationToken,
+    ) {
+        if let Err(error) = write_entries(self.file, receiver, &cancellation_token).await {
+            event!(Level::ERROR, ?error, "Failed to write audit log");
+        }
+    }
+}
+
+/// Writes entries as JSON lines until we're cancelled, then writes what was already queued.
+async fn write_entries(
+    file: File,
+    mut receiver: UnboundedReceiver<AuditEntry>,
+    cancellation_token: &CancellationToken,
+) -> Result<(), eyre::Report> {
+    let mut writer = BufWriter::new(file);
+
+    loop {
+        let entry = tokio::select! {
+            biased;
+            () = cancellation_token.cancelled() => break,
+            entry = receiver.recv() => match entry {
+                Some(entry) => entry,
+                None => break,
+            },
+        };
+
+        write_entry(&mut writer, &entry).await?;
+
+        // a change set can yield many entries, write those at once
+        if receiver.is_empty() {
+            writer.flush().await?;
+        }
+    }
+
+    while let Ok(entry) = receiver.try_recv() {
+        write_entry(&mut writer, &entry).await?;
+    }
+
+    writer.flush().await?;
+
+    Ok(())
+}
+
+async fn write_entry(writer: &mut BufWriter<File>, entry: &AuditEntry) -> Result<(), eyre::Report> {
+    let mut line = serde_json::to_vec(entry)?;
+    line.push(b'\n');
+
+    writer.write_all(&line).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use tokio_util::sync::CancellationToken;
+
+    use crate::audit::{Action, AuditEntry, AuditLog, AuditWriter, RecordState, Trigger};
+
+    #[tokio::test]
+    async fn entries_are_appended_as_json_lines() {
+        let path =
+            std::env::temp_dir().join(format!("docker-dns-rs-{}-audit.log", std::process::id()));
+
+        tokio::fs::write(&path, "existing\n").await.unwrap();
+
+        let (audit_log, receiver) = AuditLog::new();
+        let writer = AuditWriter::open(path.clone()).await.unwrap();
+
+        let name = "web.docker.".parse().unwrap();
+        let address = IpAddr::V4(Ipv4Addr::new(172, 17, 0, 2));
+
+        let mut entry = AuditEntry::new(
+            Action::Add,
+            Some(address),
+            Some(Trigger::new("local", "abc123", "container:start")),
+            RecordState::new(&name, vec![]),
+            RecordState::new(&name, vec![address]),
+        );
+        entry.timestamp = "2026-01-01T00:00:00Z".to_owned();
+
+        audit_log.record(entry);
+
+        let cancellation_token = CancellationToken::new();
+        cancellation_token.cancel();
+        writer.run(receiver, cancellation_token).await;
+
+        let contents = tokio::fs::read_to_string(&path).await.unwrap();
+        tokio::fs::remove_file(&path).await.unwrap();
+
+        let mut lines = contents.lines();
+
+        assert_eq!(lines.next(), Some("existing"));
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(lines.next().unwrap()).unwrap(),
+            json!({
+                "timestamp": "2026-01-01T00:00:00Z",
+                "action": "add",
+                "address": "172.17.0.2",
+                "daemon": "local",
+                "container_id": "abc123",
+                "event": "container:start",
+                "before": { "name": "web.docker.", "addresses": [] },
+                "after": { "name": "web.docker.", "addresses": ["172.17.0.2"] },
+            })
+        );
+        assert_eq!(lines.next(), None);
+    }
+}
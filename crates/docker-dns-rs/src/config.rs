@@ -1,17 +1,28 @@
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::num::NonZeroUsize;
 use std::path::PathBuf;
 use std::str::FromStr as _;
 use std::time::Duration;
 
-use clap::Parser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use color_eyre::eyre;
+use hashbrown::HashSet;
 use hickory_server::proto::ProtoError;
 use hickory_server::proto::rr::Name;
 use tracing::{Level, event};
 use twistlock::config::Endpoint;
 
+use crate::sockets::systemd::ListenFd;
+
 const DEFAULT_DOCKER_HOST: &str = "/var/run/docker.sock";
-const DNS_BINDADDR: SocketAddr = SocketAddr::new(std::net::IpAddr::V4(Ipv4Addr::UNSPECIFIED), 53);
+const DNS_BINDADDR: &str = "0.0.0.0:53";
+
+/// A Docker daemon, and the name its records are tagged with.
+#[derive(Clone, Debug)]
+pub struct DaemonEndpoint {
+    pub name: Box<str>,
+    pub endpoint: Endpoint,
+}
 
 #[derive(Clone, Debug)]
 pub struct RawRecord {
@@ -19,10 +30,60 @@ pub struct RawRecord {
     pub addr: IpAddr,
 }
 
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum LogFormat {
+    /// Human-readable, one line per event.
+    Text,
+    /// One JSON object per line, with the event's fields and its spans as keys.
+    Json,
+}
+
+/// Clients of a running instance, they talk to it over its control socket.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// List the containers, their names and addresses.
+    List(ClientArgs),
+    /// Show the records a name resolves to.
+    Lookup {
+        name: String,
+        #[command(flatten)]
+        client_args: ClientArgs,
+    },
+    /// List the zones the instance serves.
+    Zones(ClientArgs),
+    /// Show whether Docker is connected.
+    Status(ClientArgs),
+    /// Print every zone in the RFC 1035 master file format.
+    Export {
+        #[arg(
+            long,
+            help = "Write each zone to <origin>zone in this directory instead of printing them"
+        )]
+        output_dir: Option<PathBuf>,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct ClientArgs {
+    #[arg(long, help = "Print JSON instead of a table")]
+    pub json: bool,
+}
+
 #[derive(Parser, Debug)]
 pub struct RawConfig {
-    #[arg(env, default_value = DEFAULT_DOCKER_HOST, value_parser = parse_docker_host, help = "Path to docker TCP/UNIX socket", long="docker")]
-    pub docker_host: Endpoint,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[arg(
+        env = "DOCKER_HOST",
+        default_value = DEFAULT_DOCKER_HOST,
+        help = "Docker TCP/UNIX socket, optionally named as `name=socket`, the name defaults to the socket. Separated by commas or repeated flags, the records of every daemon are served together",
+        long = "docker",
+        value_parser = parse_docker_host,
+        value_delimiter = ',',
+        action = clap::ArgAction::Append,
+    )]
+    pub docker_hosts: Vec<DaemonEndpoint>,
 
     #[arg(
         env,
@@ -46,12 +107,145 @@ pub struct RawConfig {
 
     #[arg(
         env,
-        default_value_t = DNS_BINDADDR,
+        default_value = DNS_BINDADDR,
+        long,
+        help = "Bind address for DNS server, separated by commas or repeated flags. Each address gets a UDP socket and a TCP listener",
+        value_delimiter = ',',
+        action = clap::ArgAction::Append,
+    )]
+    pub dns_bind: Vec<SocketAddr>,
+
+    #[arg(
+        env,
+        default_value = "1",
+        long,
+        help = "Number of UDP sockets per DNS bind address. More than 1 uses SO_REUSEPORT, each socket is served by its own task"
+    )]
+    pub udp_sockets: NonZeroUsize,
+
+    #[arg(
+        env,
+        long,
+        help = "Bind address for the DNS-over-HTTPS (RFC 8484) server. Not needed when systemd passes a socket named `doh`",
+        requires_all = ["doh_cert", "doh_key"],
+    )]
+    pub doh_bind: Option<SocketAddr>,
+
+    #[arg(
+        env,
+        long,
+        help = "Bind address for the read-only admin HTTP API, which lists containers, networks and records as JSON. Unauthenticated, so bind it to a trusted interface"
+    )]
+    pub admin_bind: Option<SocketAddr>,
+
+    #[arg(
+        env,
+        long,
+        hide_env_values = true,
+        help = "Bearer token the admin API requires for adding and removing manual records. Without it they can't be changed"
+    )]
+    pub admin_token: Option<String>,
+
+    #[arg(
+        env,
+        long,
+        global = true,
+        help = "Unix socket that serves the admin API locally. The server listens on it, and the `list`, `lookup` and `zones` subcommands connect to it. Only the user we run as can connect. Put it in a directory that user can write to, e.g. /run/docker-dns-rs, so it's removed at shutdown"
+    )]
+    pub control_socket: Option<PathBuf>,
+
+    #[arg(
+        env,
+        long,
+        help = "PEM encoded certificate chain for the DNS-over-HTTPS server, enables DNS-over-HTTPS",
+        requires = "doh_key"
+    )]
+    pub doh_cert: Option<PathBuf>,
+
+    #[arg(
+        env,
+        long,
+        help = "PEM encoded private key for the DNS-over-HTTPS server",
+        requires = "doh_cert"
+    )]
+    pub doh_key: Option<PathBuf>,
+
+    #[arg(
+        env,
+        long,
+        help = "Send dnstap logs of client queries and responses to this Frame Streams Unix socket",
+        conflicts_with = "dnstap_file"
+    )]
+    pub dnstap_socket: Option<PathBuf>,
+
+    #[arg(
+        env,
+        long,
+        help = "Write dnstap logs of client queries and responses to this file, in Frame Streams format. The file of the previous run is moved to `<file>.1`, replacing the one from before"
+    )]
+    pub dnstap_file: Option<PathBuf>,
+
+    #[arg(
+        env,
+        long,
+        help = "Append a JSON line to this file for every record that is added, removed or renamed"
+    )]
+    pub audit_log: Option<PathBuf>,
+
+    #[arg(
+        env,
+        long,
+        help = "Keep this hosts file in sync with the records, for tools that ignore DNS. Lines above the generated section are kept. Written after privileges are dropped, through a temporary file in the same directory, which has to be writable by the user we run as (/etc usually is not)"
+    )]
+    pub hosts_file: Option<PathBuf>,
+
+    #[arg(
+        env,
+        long,
+        help = "Save the containers and networks to this file, and serve them from it right away on the next start, while Docker is asked in the background. Written after privileges are dropped, through a temporary file in the same directory"
+    )]
+    pub state_file: Option<PathBuf>,
+
+    #[arg(
+        env,
         long,
-        help = "Bind address for DNS server",
+        help = "Serve IPv4 subnets smaller than a /24 as RFC 2317 classless reverse zones, with CNAMEs in the enclosing /24 zone. Without it they are split into a zone per address"
     )]
-    pub dns_bind: SocketAddr,
+    pub rfc2317: bool,
 
+    #[arg(
+        env,
+        long,
+        value_enum,
+        default_value_t = LogFormat::Text,
+        help = "Log output format"
+    )]
+    pub log_format: LogFormat,
+
+    #[cfg(feature = "otlp")]
+    #[arg(
+        env,
+        long,
+        help = "Export traces over OTLP/HTTP to this URL, e.g. `http://localhost:4318/v1/traces`"
+    )]
+    pub otlp_endpoint: Option<String>,
+
+    #[arg(
+        env = "RUN_AS_USER",
+        long,
+        help = "User (name or id) to switch to after binding the DNS sockets"
+    )]
+    pub user: Option<String>,
+
+    #[arg(
+        env = "RUN_AS_GROUP",
+        long,
+        requires = "user",
+        help = "Group (name or id) the user switches to, defaults to the user's primary group, required for a numeric user without a passwd entry"
+    )]
+    pub group: Option<String>,
+
+    /// Used for every daemon connected to over TCP.
     #[clap(long, env = "CA")]
     pub cacert: Option<PathBuf>,
 
@@ -69,13 +263,74 @@ pub struct RawConfig {
         value_parser = parse_duration
     )]
     pub timeout: Duration,
+
+    #[arg(
+        env,
+        long,
+        default_value = "30",
+        help = "How often, in seconds, Swarm services and tasks are listed again. Swarm doesn't send events for tasks on other nodes",
+        value_parser = parse_interval
+    )]
+    pub swarm_interval: Duration,
+
+    #[arg(
+        env,
+        long,
+        default_value = "60",
+        help = "How long, in seconds, the records of a daemon that went away are still served. After that they're withdrawn, until it's back. 0 withdraws them right away",
+        value_parser = parse_duration
+    )]
+    pub disconnect_grace: Duration,
 }
 
 impl RawConfig {
     pub fn print(&self) {
-        event!(Level::INFO, docker_host = %self.docker_host, "Daemon");
+        for docker_host in &self.docker_hosts {
+            event!(Level::INFO, daemon = %docker_host.name, docker_host = %docker_host.endpoint, "Daemon");
+        }
+
         event!(Level::INFO, domain = %self.domain, "Domain");
-        event!(Level::INFO, dns_bind = %self.dns_bind, "DNS Bind Address");
+        for dns_bind in &self.dns_bind {
+            event!(Level::INFO, %dns_bind, "DNS Bind Address");
+        }
+
+        event!(Level::INFO, udp_sockets = %self.udp_sockets, "UDP sockets per bind address");
+
+        if let Some(doh_bind) = self.doh_bind {
+            event!(Level::INFO, %doh_bind, "DNS-over-HTTPS Bind Address");
+        }
+
+        if let Some(admin_bind) = self.admin_bind {
+            event!(Level::INFO, %admin_bind, "Admin API Bind Address");
+        }
+
+        if let Some(ref control_socket) = self.control_socket {
+            event!(Level::INFO, control_socket = %control_socket.display(), "Control socket");
+        }
+
+        if let Some(ref dnstap_socket) = self.dnstap_socket {
+            event!(Level::INFO, dnstap_socket = %dnstap_socket.display(), "dnstap socket");
+        }
+
+        if let Some(ref dnstap_file) = self.dnstap_file {
+            event!(Level::INFO, dnstap_file = %dnstap_file.display(), "dnstap file");
+        }
+
+        if let Some(ref audit_log) = self.audit_log {
+            event!(Level::INFO, audit_log = %audit_log.display(), "Audit log");
+        }
+
+        if let Some(ref hosts_file) = self.hosts_file {
+            event!(Level::INFO, hosts_file = %hosts_file.display(), "Hosts file");
+        }
+
+        if let Some(ref state_file) = self.state_file {
+            event!(Level::INFO, state_file = %state_file.display(), "State file");
+        }
+
+        if self.rfc2317 {
+            event!(Level::INFO, "RFC 2317 classless reverse zones enabled");
+        }
 
         for r in &self.records {
             event!(Level::INFO, forward = %r.name, reverse = %r.addr, "Static record");
@@ -83,8 +338,17 @@ impl RawConfig {
     }
 }
 
-fn parse_docker_host(value: &str) -> Result<Endpoint, String> {
-    Endpoint::from_str(value)
+fn parse_docker_host(value: &str) -> Result<DaemonEndpoint, String> {
+    let (name, endpoint) = value.split_once('=').unwrap_or((value, value));
+
+    if name.is_empty() {
+        return Err(format!("Daemon name cannot be empty, got `{}`", value));
+    }
+
+    Ok(DaemonEndpoint {
+        name: Box::from(name),
+        endpoint: Endpoint::from_str(endpoint)?,
+    })
 }
 
 fn parse_duration(value: &str) -> Result<Duration, String> {
@@ -95,6 +359,19 @@ fn parse_duration(value: &str) -> Result<Duration, String> {
     Ok(Duration::from_secs(seconds))
 }
 
+fn parse_interval(value: &str) -> Result<Duration, String> {
+    let interval = parse_duration(value)?;
+
+    if interval.is_zero() {
+        return Err(format!(
+            "Interval must be at least 1 second, got `{}`",
+            value
+        ));
+    }
+
+    Ok(interval)
+}
+
 fn parse_domain(raw_domain: &str) -> Result<Name, String> {
     match raw_domain.parse::<Name>() {
         Ok(mut domain) => {
@@ -135,40 +412,152 @@ fn parse_record(value: &str) -> Result<RawRecord, String> {
     Ok(RawRecord { name, addr })
 }
 
+/// How to connect to one daemon.
 pub struct DockerConfig {
+    pub daemon: Box<str>,
     pub docker_host: Endpoint,
     pub cacert: Option<PathBuf>,
     pub client_key: Option<PathBuf>,
     pub client_cert: Option<PathBuf>,
     pub timeout: Duration,
+    pub swarm_interval: Duration,
+    pub disconnect_grace: Duration,
+}
+
+pub struct DohConfig {
+    /// Absent when the listener comes from systemd.
+    pub bind: Option<SocketAddr>,
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+pub enum DnstapOutput {
+    /// Bidirectional Frame Streams, with a handshake.
+    Socket(PathBuf),
+    /// Unidirectional Frame Streams.
+    File(PathBuf),
+}
+
+pub struct ListenerConfig {
+    pub dns_binds: Vec<SocketAddr>,
+    pub udp_sockets: NonZeroUsize,
+    pub doh_config: Option<DohConfig>,
+    pub dnstap_output: Option<DnstapOutput>,
+    pub admin_bind: Option<SocketAddr>,
+    pub admin_token: Option<Box<str>>,
+    pub control_socket: Option<PathBuf>,
+    /// Sockets passed by systemd, used instead of `dns_binds` when there are any.
+    pub listen_fds: Vec<ListenFd>,
 }
 
 pub struct AppConfig {
-    pub docker_config: DockerConfig,
+    pub docker_configs: Vec<DockerConfig>,
     pub domain: Name,
-    pub dns_bind: SocketAddr,
+    pub listener_config: ListenerConfig,
     pub records: Vec<RawRecord>,
+    pub rfc2317: bool,
+    pub audit_log: Option<PathBuf>,
+    pub hosts_file: Option<PathBuf>,
+    pub state_file: Option<PathBuf>,
+    pub user: Option<String>,
+    pub group: Option<String>,
 }
 
 impl AppConfig {
-    pub fn build() -> Result<AppConfig, eyre::Report> {
-        let raw_config = RawConfig::try_parse()?;
-
+    /// Takes the already parsed `RawConfig`, as the log format is needed before tracing is set up.
+    ///
+    /// Fails when two daemons have the same name, their records couldn't be told apart.
+    pub fn build(
+        raw_config: RawConfig,
+        listen_fds: Vec<ListenFd>,
+    ) -> Result<AppConfig, eyre::Report> {
         raw_config.print();
 
-        let docker_config = DockerConfig {
-            docker_host: raw_config.docker_host,
-            cacert: raw_config.cacert,
-            client_key: raw_config.client_key,
-            client_cert: raw_config.client_cert,
-            timeout: raw_config.timeout,
+        let mut names = HashSet::new();
+
+        if let Some(duplicate) = raw_config
+            .docker_hosts
+            .iter()
+            .find(|docker_host| !names.insert(docker_host.name.clone()))
+        {
+            return Err(eyre::Report::msg(format!(
+                "Daemon name `{}` is used more than once",
+                duplicate.name
+            )));
+        }
+
+        let docker_configs = raw_config
+            .docker_hosts
+            .into_iter()
+            .map(|docker_host| DockerConfig {
+                daemon: docker_host.name,
+                docker_host: docker_host.endpoint,
+                cacert: raw_config.cacert.clone(),
+                client_key: raw_config.client_key.clone(),
+                client_cert: raw_config.client_cert.clone(),
+                timeout: raw_config.timeout,
+                swarm_interval: raw_config.swarm_interval,
+                disconnect_grace: raw_config.disconnect_grace,
+            })
+            .collect();
+
+        // `requires` guarantees both are present when either is
+        let doh_config = match (raw_config.doh_cert, raw_config.doh_key) {
+            (Some(cert), Some(key)) => Some(DohConfig {
+                bind: raw_config.doh_bind,
+                cert,
+                key,
+            }),
+            _ => None,
+        };
+
+        // `conflicts_with` guarantees at most one is present
+        let dnstap_output = match (raw_config.dnstap_socket, raw_config.dnstap_file) {
+            (Some(socket), _) => Some(DnstapOutput::Socket(socket)),
+            (None, Some(file)) => Some(DnstapOutput::File(file)),
+            (None, None) => None,
         };
 
         Ok(AppConfig {
-            docker_config,
+            docker_configs,
             domain: raw_config.domain,
-            dns_bind: raw_config.dns_bind,
+            listener_config: ListenerConfig {
+                dns_binds: raw_config.dns_bind,
+                udp_sockets: raw_config.udp_sockets,
+                doh_config,
+                dnstap_output,
+                admin_bind: raw_config.admin_bind,
+                admin_token: raw_config.admin_token.map(String::into_boxed_str),
+                control_socket: raw_config.control_socket,
+                listen_fds,
+            },
             records: raw_config.records,
+            rfc2317: raw_config.rfc2317,
+            audit_log: raw_config.audit_log,
+            hosts_file: raw_config.hosts_file,
+            state_file: raw_config.state_file,
+            user: raw_config.user,
+            group: raw_config.group,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser as _;
+    use clap::error::ErrorKind;
+    use pretty_assertions::assert_eq;
+
+    use crate::config::RawConfig;
+
+    #[test]
+    fn group_requires_user() {
+        let error = RawConfig::try_parse_from(["docker-dns-rs", "--group", "4242"]).unwrap_err();
+
+        assert_eq!(error.kind(), ErrorKind::MissingRequiredArgument);
+
+        let _raw_config =
+            RawConfig::try_parse_from(["docker-dns-rs", "--user", "4242", "--group", "4242"])
+                .unwrap();
+    }
+}
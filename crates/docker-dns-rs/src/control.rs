@@ -0,0 +1,461 @@
+use std::net::IpAddr;
+use std::path::Path;
+
+use color_eyre::eyre;
+use color_eyre::eyre::{OptionExt as _, WrapErr as _};
+use hickory_server::proto::rr::{LowerName, Name};
+use http_body_util::{BodyExt as _, Empty};
+use hyper::StatusCode;
+use hyper::body::Bytes;
+use hyper::header::HOST;
+use hyper_util::rt::TokioIo;
+use itertools::Itertools as _;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::from_slice as from_json_slice;
+use tokio::net::UnixStream;
+use tracing::{Level, event};
+
+use crate::admin::{
+    CONTAINERS_PATH, EXPORT_PATH, ExportedZone, NETWORKS_PATH, RECORDS_PATH, STATUS_PATH,
+    StaticRecord, Status, ZONE_PATH, ZoneRecord,
+};
+use crate::config::Command;
+use crate::dns_listener::INTERCEPT_TTL;
+use crate::docker::monitor::{ContainerSummary, NetworkSummary};
+use crate::docker::status::DockerStatus;
+use crate::utils::task::spawn_with_name;
+
+/// A zone the instance serves, the forward zone has no network.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct ZoneSummary {
+    zone: String,
+    /// The daemon the network is on.
+    daemon: Option<String>,
+    network: Option<String>,
+    network_id: Option<String>,
+}
+
+/// Asks the instance behind the control socket, and prints what it says.
+pub async fn run(command: Command, control_socket: Option<&Path>) -> Result<(), eyre::Report> {
+    let control_socket =
+        control_socket.ok_or_eyre("No control socket, pass --control-socket or CONTROL_SOCKET")?;
+
+    println!("{}", render(command, control_socket).await?);
+
+    Ok(())
+}
+
+async fn render(command: Command, control_socket: &Path) -> Result<String, eyre::Report> {
+    match command {
+        Command::List(client_args) => {
+            let containers = get::<Vec<ContainerSummary>>(control_socket, CONTAINERS_PATH).await?;
+
+            if client_args.json {
+                to_json(&containers)
+            } else {
+                Ok(containers_table(&containers))
+            }
+        },
+        Command::Lookup { name, client_args } => {
+            let records = lookup(control_socket, &name).await?;
+
+            if client_args.json {
+                to_json(&records)
+            } else {
+                Ok(records_table(&records))
+            }
+        },
+        Command::Zones(client_args) => {
+            let zones = zones(control_socket).await?;
+
+            if client_args.json {
+                to_json(&zones)
+            } else {
+                Ok(zones_table(&zones))
+            }
+        },
+        Command::Status(client_args) => {
+            let status = get::<Status>(control_socket, STATUS_PATH).await?;
+
+            if client_args.json {
+                to_json(&status)
+            } else {
+                Ok(status_table(&status))
+            }
+        },
+        Command::Export { output_dir } => {
+            let zones = get::<Vec<ExportedZone>>(control_socket, EXPORT_PATH).await?;
+
+            match output_dir {
+                Some(output_dir) => export_to(&output_dir, &zones).await,
+                None => Ok(zones
+                    .iter()
+                    .map(|zone| zone.contents.trim_end())
+                    .join("\n\n")),
+            }
+        },
+    }
+}
+
+/// Writes each zone to a file of its own, and lists the files.
+async fn export_to(output_dir: &Path, zones: &[ExportedZone]) -> Result<String, eyre::Report> {
+    let mut written = Vec::with_capacity(zones.len());
+
+    for zone in zones {
+        let path = output_dir.join(&zone.file_name);
+
+        tokio::fs::write(&path, &zone.contents)
+            .await
+            .wrap_err_with(|| format!("Failed to write {}", path.display()))?;
+
+        written.push(format!("{} -> {}", zone.origin, path.display()));
+    }
+
+    Ok(written.join("\n"))
+}
+
+/// `GET`s `path` from the admin API, over the control socket.
+async fn get<T: DeserializeOwned>(control_socket: &Path, path: &str) -> Result<T, eyre::Report> {
+    let stream = UnixStream::connect(control_socket)
+        .await
+        .wrap_err_with(|| {
+            format!(
+                "Failed to connect to control socket {}",
+                control_socket.display()
+            )
+        })?;
+
+    let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+        .await
+        .wrap_err("Failed to talk to the control socket")?;
+
+    spawn_with_name("control connection", async move {
+        if let Err(error) = connection.await {
+            event!(Level::DEBUG, ?error, "Control connection failed");
+        }
+    });
+
+    let request = hyper::Request::get(path)
+        .header(HOST, "localhost")
+        .body(Empty::<Bytes>::new())?;
+
+    let response = sender.send_request(request).await?;
+
+    if response.status() != StatusCode::OK {
+        return Err(eyre::Report::msg(format!(
+            "{path} answered {}",
+            response.status()
+        )));
+    }
+
+    let body = response.into_body().collect().await?.to_bytes();
+
+    from_json_slice(&body).wrap_err_with(|| format!("{path} answered with unexpected JSON"))
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, eyre::Report> {
+    Ok(serde_json::to_string_pretty(value)?)
+}
+
+/// The records `name` resolves to. Like the DNS listener, the static records hide the zone.
+async fn lookup(control_socket: &Path, name: &str) -> Result<Vec<ZoneRecord>, eyre::Report> {
+    let mut name = name.parse::<Name>()?;
+    name.set_fqdn(true);
+    let name = LowerName::from(name);
+
+    let matches = |other: &str| {
+        other
+            .parse::<Name>()
+            .is_ok_and(|other| LowerName::from(other) == name)
+    };
+
+    let static_records = get::<Vec<StaticRecord>>(control_socket, RECORDS_PATH)
+        .await?
+        .into_iter()
+        .filter(|record| matches(&record.name))
+        .map(|record| ZoneRecord {
+            name: record.name,
+            r#type: match record.address {
+                IpAddr::V4(_) => "A",
+                IpAddr::V6(_) => "AAAA",
+            }
+            .to_owned(),
+            ttl: INTERCEPT_TTL,
+            data: record.address.to_string(),
+        })
+        .collect::<Vec<_>>();
+
+    if !static_records.is_empty() {
+        return Ok(static_records);
+    }
+
+    Ok(get::<Vec<ZoneRecord>>(control_socket, ZONE_PATH)
+        .await?
+        .into_iter()
+        .filter(|record| matches(&record.name))
+        .collect())
+}
+
+/// The forward zone, from its SOA record, and the reverse zones of the networks.
+async fn zones(control_socket: &Path) -> Result<Vec<ZoneSummary>, eyre::Report> {
+    let forward_zones = get::<Vec<ZoneRecord>>(control_socket, ZONE_PATH)
+        .await?
+        .into_iter()
+        .filter(|record| record.r#type == "SOA")
+        .map(|record| ZoneSummary {
+            zone: record.name,
+            daemon: None,
+            network: None,
+            network_id: None,
+        });
+
+    let networks = get::<Vec<NetworkSummary>>(control_socket, NETWORKS_PATH).await?;
+
+    let reverse_zones = networks.into_iter().flat_map(|network| {
+        network
+            .reverse_zones
+            .into_iter()
+            .filter_map(move |reverse_zone| {
+                Some(ZoneSummary {
+                    zone: reverse_zone.zone?,
+                    daemon: Some(network.daemon.clone()),
+                    network: Some(reverse_zone.network.to_string()),
+                    network_id: Some(network.id.clone()),
+                })
+            })
+    });
+
+    Ok(forward_zones.chain(reverse_zones).collect())
+}
+
+fn containers_table(containers: &[ContainerSummary]) -> String {
+    let rows = containers.iter().flat_map(|container| {
+        let id = container.id.chars().take(12).collect::<String>();
+        let names = container.names.join(", ");
+
+        if container.networks.is_empty() {
+            return vec![vec![
+                container.daemon.clone(),
+                id,
+                names,
+                String::new(),
+                String::new(),
+            ]];
+        }
+
+        container
+            .networks
+            .iter()
+            .map(|(network, addresses)| {
+                vec![
+                    container.daemon.clone(),
+                    id.clone(),
+                    names.clone(),
+                    network.clone(),
+                    addresses.iter().join(", "),
+                ]
+            })
+            .collect()
+    });
+
+    format_table(
+        &["DAEMON", "CONTAINER", "NAMES", "NETWORK", "ADDRESSES"],
+        rows,
+    )
+}
+
+fn records_table(records: &[ZoneRecord]) -> String {
+    let rows = records.iter().map(|record| {
+        vec![
+            record.name.clone(),
+            record.r#type.clone(),
+            record.ttl.to_string(),
+            record.data.clone(),
+        ]
+    });
+
+    format_table(&["NAME", "TYPE", "TTL", "DATA"], rows)
+}
+
+fn status_table(status: &Status) -> String {
+    let rows = status.daemons.iter().map(|daemon| {
+        let (state, since, error) = match daemon.status {
+            DockerStatus::Connecting => ("connecting", String::new(), String::new()),
+            DockerStatus::Connected { ref since } => ("connected", since.clone(), String::new()),
+            DockerStatus::Degraded {
+                ref error,
+                ref since,
+            } => ("degraded", since.clone(), error.clone()),
+        };
+
+        vec![daemon.daemon.clone(), state.to_owned(), since, error]
+    });
+
+    format_table(&["DAEMON", "DOCKER", "SINCE", "ERROR"], rows)
+}
+
+fn zones_table(zones: &[ZoneSummary]) -> String {
+    let rows = zones.iter().map(|zone| {
+        vec![
+            zone.zone.clone(),
+            zone.daemon.clone().unwrap_or_default(),
+            zone.network.clone().unwrap_or_default(),
+            zone.network_id
+                .as_deref()
+                .map(|id| id.chars().take(12).collect())
+                .unwrap_or_default(),
+        ]
+    });
+
+    format_table(&["ZONE", "DAEMON", "NETWORK", "NETWORK ID"], rows)
+}
+
+/// Left aligned columns, two spaces apart, like `docker ps`.
+fn format_table<I: IntoIterator<Item = Vec<String>>>(header: &[&str], rows: I) -> String {
+    let rows = rows.into_iter().collect::<Vec<_>>();
+
+    let mut widths = header.iter().map(|title| title.len()).collect::<Vec<_>>();
+
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let format_row = |cells: Vec<&str>| {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .join("  ")
+            .trim_end()
+            .to_owned()
+    };
+
+    std::iter::once(format_row(header.to_vec()))
+        .chain(
+            rows.iter()
+                .map(|row| format_row(row.iter().map(String::as_str).collect())),
+        )
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::sync::Arc;
+
+    use pretty_assertions::assert_eq;
+    use tokio_util::sync::CancellationToken;
+
+    use crate::admin::{AdminListeners, AdminState, set_up_admin_server};
+    use crate::config::{ClientArgs, Command, RawRecord};
+    use crate::control::{format_table, render};
+    use crate::docker::daemons::Daemons;
+    use crate::sockets::bind_unix;
+    use crate::table::AuthorityWrapper;
+
+    #[test]
+    fn columns_are_padded_to_the_widest_cell() {
+        let table = format_table(
+            &["NAME", "TYPE"],
+            [
+                vec!["web.docker.".to_owned(), "A".to_owned()],
+                vec!["db.docker.".to_owned(), "AAAA".to_owned()],
+            ],
+        );
+
+        assert_eq!(
+            table,
+            "NAME         TYPE\nweb.docker.  A\ndb.docker.   AAAA"
+        );
+    }
+
+    #[tokio::test]
+    async fn subcommands_query_the_control_socket() {
+        let daemons = Arc::new(Daemons::new(
+            AuthorityWrapper::new("docker.".parse().unwrap()),
+            "docker.".parse().unwrap(),
+            false,
+            [Box::from("local"), Box::from("build1")],
+        ));
+
+        let records = vec![RawRecord {
+            name: "static.docker.".parse().unwrap(),
+            addr: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+        }];
+
+        let path =
+            std::env::temp_dir().join(format!("docker-dns-rs-{}-control.sock", std::process::id()));
+
+        let cancellation_token = CancellationToken::new();
+
+        let server = tokio::spawn(set_up_admin_server(
+            AdminListeners {
+                tcp: None,
+                control_socket: Some((bind_unix(&path).unwrap(), path.clone())),
+            },
+            AdminState::new(daemons, records, None),
+            cancellation_token.clone(),
+        ));
+
+        let lookup = render(
+            Command::Lookup {
+                name: "STATIC.docker".to_owned(),
+                client_args: ClientArgs { json: false },
+            },
+            &path,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            lookup,
+            "NAME            TYPE  TTL  DATA\nstatic.docker.  A     5    10.0.0.1"
+        );
+
+        let zones = render(Command::Zones(ClientArgs { json: true }), &path)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&zones).unwrap(),
+            serde_json::json!([{
+                "zone": "docker.",
+                "daemon": null,
+                "network": null,
+                "network_id": null,
+            }])
+        );
+
+        let list = render(Command::List(ClientArgs { json: false }), &path)
+            .await
+            .unwrap();
+
+        assert_eq!(list, "DAEMON  CONTAINER  NAMES  NETWORK  ADDRESSES");
+
+        let status = render(Command::Status(ClientArgs { json: false }), &path)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            status,
+            "DAEMON  DOCKER      SINCE  ERROR\nlocal   connecting\nbuild1  connecting"
+        );
+
+        let export = render(Command::Export { output_dir: None }, &path)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            export,
+            "$ORIGIN docker.\ndocker. 3600 IN SOA docker. docker. 0 0 0 0 0"
+        );
+
+        cancellation_token.cancel();
+        server.await.unwrap();
+
+        assert!(!path.exists());
+    }
+}
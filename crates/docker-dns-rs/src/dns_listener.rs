@@ -1,27 +1,33 @@
-use std::collections::BTreeMap;
 use std::hash::{Hash, Hasher};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
+use arc_swap::ArcSwap;
 use async_trait::async_trait;
-use color_eyre::eyre;
 use hashbrown::{HashMap, HashSet};
 use hickory_net::NetError;
 use hickory_net::proto::op::{HeaderCounts, Metadata};
 use hickory_net::runtime::{Time, TokioTime};
 use hickory_server::Server;
 use hickory_server::proto::op::{Header, ResponseCode};
-use hickory_server::proto::rr::rdata::{PTR, SOA};
-use hickory_server::proto::rr::{LowerName, Name, RData, Record, RecordSet, RecordType, RrKey};
+use hickory_server::proto::rr::{LowerName, Name, RData, Record, RecordType};
 use hickory_server::server::{Request, RequestHandler, ResponseHandler, ResponseInfo};
-use hickory_server::store::in_memory::InMemoryZoneHandler;
-use hickory_server::zone_handler::{AxfrPolicy, Catalog, MessageResponseBuilder, ZoneType};
+use hickory_server::zone_handler::{Catalog, MessageResponseBuilder};
 use tokio::net::{TcpListener, UdpSocket};
-use tokio::sync::RwLock;
 use tokio_util::sync::CancellationToken;
-use tracing::{Level, event};
+use tracing::{Level, Span, event, field, instrument};
 
 use crate::config::RawRecord;
+use crate::dns_listener::tap::TappedResponseHandler;
+use crate::dnstap::{DnstapLogger, TappedQuery};
+use crate::table::soa_record;
+
+pub mod doh;
+pub mod response_buffer;
+mod tap;
+
+/// The TTL of answers for the static `--record`s.
+pub const INTERCEPT_TTL: u32 = 5;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 struct HashedRData(RData);
@@ -32,28 +38,33 @@ impl Hash for HashedRData {
 
         #[expect(
             clippy::wildcard_enum_match_arm,
-            reason = "We're only interested in A, AAAA and PTR"
+            reason = "We're only interested in A and AAAA"
         )]
         match &self.0 {
             &RData::A(ref a) => a.hash(state),
             &RData::AAAA(ref aaaa) => aaaa.hash(state),
-            &RData::PTR(ref ptr) => ptr.hash(state),
             other => unreachable!("unexpected RData variant in intercept map: {:?}", other),
         }
     }
 }
 
+#[derive(Clone)]
 pub struct DnsRequestHandler {
-    catalog: Arc<RwLock<Catalog>>,
-    intercepts: HashMap<LowerName, HashSet<HashedRData>>,
+    catalog: Arc<ArcSwap<Catalog>>,
+    intercepts: Arc<HashMap<LowerName, HashSet<HashedRData>>>,
+    dnstap: Option<DnstapLogger>,
 }
 
 impl DnsRequestHandler {
-    pub fn new(catalog: Arc<RwLock<Catalog>>, intercepts: Vec<RawRecord>) -> Self {
+    pub fn new(
+        catalog: Arc<ArcSwap<Catalog>>,
+        intercepts: Vec<RawRecord>,
+        dnstap: Option<DnstapLogger>,
+    ) -> Self {
         let mut map: HashMap<LowerName, HashSet<HashedRData>> = HashMap::new();
 
         for intercept in intercepts {
-            // Forward: A or AAAA
+            // A or AAAA, the PTR record lives in a reverse zone, see `AuthorityWrapper::add_static_records`
             let forward = HashedRData(RData::from(intercept.addr));
 
             if !map
@@ -68,39 +79,40 @@ impl DnsRequestHandler {
                     "duplicate --record entry ignored"
                 );
             }
-
-            // Reverse: PTR
-            let ptr_name = Name::from(intercept.addr);
-            let ptr = HashedRData(RData::PTR(PTR(intercept.name)));
-            map.entry(LowerName::from(&ptr_name))
-                .or_default()
-                .insert(ptr);
         }
+
         Self {
             catalog,
-            intercepts: map,
+            intercepts: Arc::new(map),
+            dnstap,
         }
     }
-}
 
-#[async_trait]
-impl RequestHandler for DnsRequestHandler {
-    async fn handle_request<R: ResponseHandler, T: Time>(
+    async fn answer<R: ResponseHandler>(
         &self,
         request: &Request,
         mut response_handle: R,
     ) -> ResponseInfo {
+        // Take our own reference so a newer snapshot can be published while we answer.
+        let catalog = self.catalog.load_full();
+
         if let Ok(request_info) = request.request_info() {
             let qname = request_info.query.name();
             let qtype = request_info.query.query_type();
 
+            Span::current()
+                .record("qname", field::display(qname))
+                .record("qtype", field::display(qtype));
+
             event!(Level::TRACE, %qname, %qtype, "DNS lookup");
 
             if let Some(rdatas) = self.intercepts.get(qname) {
                 let answers: Vec<Record> = rdatas
                     .iter()
                     .filter(|r| qtype == r.0.record_type() || qtype == RecordType::ANY)
-                    .map(|r| Record::from_rdata(Name::from(qname.clone()), 5, r.0.clone()))
+                    .map(|r| {
+                        Record::from_rdata(Name::from(qname.clone()), INTERCEPT_TTL, r.0.clone())
+                    })
                     .collect();
 
                 let answers_fmt = std::fmt::from_fn(|f| {
@@ -119,15 +131,30 @@ impl RequestHandler for DnsRequestHandler {
 
                 event!(Level::DEBUG, %qname, %qtype, answers = %answers_fmt, "DNS lookup intercept match");
 
+                // static records can be outside of our zones, we only speak with authority
+                // for the ones inside
+                let zone_origin = catalog
+                    .find(qname)
+                    .and_then(|handlers| handlers.first())
+                    .map(|handler| Name::from(handler.origin()));
+
                 let builder = MessageResponseBuilder::from_message_request(request);
                 let mut metadata = Metadata::response_from_request(request_info.metadata);
-                metadata.authoritative = true;
+                metadata.authoritative = zone_origin.is_some();
+
+                // the name exists, so no records of the requested type is NODATA (RFC 2308):
+                // NOERROR with the zone's SOA, which tells resolvers how long to cache that
+                let soa = if answers.is_empty() {
+                    zone_origin.map(soa_record)
+                } else {
+                    None
+                };
 
                 let response = builder.build(
                     metadata,
                     answers.iter(),
                     std::iter::empty(),
-                    std::iter::empty(),
+                    soa.iter(),
                     std::iter::empty(),
                 );
 
@@ -158,17 +185,48 @@ impl RequestHandler for DnsRequestHandler {
         }
 
         // fall back to the catalog that contains the dynamically registered containers
-        self.catalog
-            .read()
-            .await
+        catalog
             .handle_request::<_, TokioTime>(request, response_handle)
             .await
     }
 }
 
+#[async_trait]
+impl RequestHandler for DnsRequestHandler {
+    #[instrument(skip_all, fields(src = %request.src(), protocol = %request.protocol(), qname, qtype, rcode))]
+    async fn handle_request<R: ResponseHandler, T: Time>(
+        &self,
+        request: &Request,
+        response_handle: R,
+    ) -> ResponseInfo {
+        let response_info = if let Some(ref dnstap) = self.dnstap {
+            let query = TappedQuery::new(request, SystemTime::now());
+
+            dnstap.client_query(&query);
+
+            self.answer(
+                request,
+                TappedResponseHandler::new(
+                    response_handle,
+                    dnstap.clone(),
+                    query,
+                    request.metadata,
+                ),
+            )
+            .await
+        } else {
+            self.answer(request, response_handle).await
+        };
+
+        Span::current().record("rcode", field::debug(response_info.response_code));
+
+        response_info
+    }
+}
+
 pub async fn set_up_dns_server<H>(
-    tcp_listener: TcpListener,
-    udp_socket: UdpSocket,
+    tcp_listeners: Vec<TcpListener>,
+    udp_sockets: Vec<UdpSocket>,
     handler: H,
     cancellation_token: CancellationToken,
 ) where
@@ -179,8 +237,13 @@ pub async fn set_up_dns_server<H>(
 
     let mut dns_listener = Server::new(handler);
 
-    dns_listener.register_socket(udp_socket);
-    dns_listener.register_listener(tcp_listener, Duration::from_secs(1), RESPONSE_BUFFER_SIZE);
+    for udp_socket in udp_sockets {
+        dns_listener.register_socket(udp_socket);
+    }
+
+    for tcp_listener in tcp_listeners {
+        dns_listener.register_listener(tcp_listener, Duration::from_secs(1), RESPONSE_BUFFER_SIZE);
+    }
 
     tokio::select! {
            biased;
@@ -197,37 +260,6 @@ pub async fn set_up_dns_server<H>(
     }
 }
 
-pub async fn set_up_authority(domain: Name) -> Result<InMemoryZoneHandler, eyre::Report> {
-    let tree = BTreeMap::<RrKey, RecordSet>::from([(
-        RrKey::new(
-            domain.clone().into(),
-            hickory_server::proto::rr::RecordType::SOA,
-        ),
-        Record::from_rdata(
-            domain.clone(),
-            3600,
-            RData::SOA(SOA::new(domain.clone(), domain.clone(), 0, 0, 0, 0, 0)),
-        )
-        .into(),
-    )]);
-
-    let imo = InMemoryZoneHandler::new(domain, tree, ZoneType::Primary, AxfrPolicy::Deny)
-        .map_err(eyre::Report::msg)?;
-
-    Ok(imo)
-}
-
-pub fn set_up_catalog<I: Into<LowerName>>(
-    domain: I,
-    authority: Arc<InMemoryZoneHandler>,
-) -> Catalog {
-    let mut catalog = Catalog::new();
-
-    catalog.upsert(domain.into(), vec![authority]);
-
-    catalog
-}
-
 fn handle_server_shutdown(server_shutdown_result: Result<(), NetError>) {
     if let Err(error) = server_shutdown_result {
         event!(
@@ -239,3 +271,231 @@ fn handle_server_shutdown(server_shutdown_result: Result<(), NetError>) {
         event!(Level::INFO, "DNS server shut down gracefully");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+    use hickory_net::runtime::TokioTime;
+    use hickory_net::xfer::Protocol;
+    use hickory_server::proto::op::{Message, Query, ResponseCode};
+    use hickory_server::proto::rr::rdata::PTR;
+    use hickory_server::proto::rr::{RData, Record, RecordType};
+    use hickory_server::server::{Request, RequestHandler as _};
+    use pretty_assertions::assert_eq;
+
+    use crate::config::RawRecord;
+    use crate::dns_listener::DnsRequestHandler;
+    use crate::dns_listener::response_buffer::BufferedResponseHandler;
+    use crate::dnstap::DnstapLogger;
+    use crate::table::AuthorityWrapper;
+
+    async fn query(handler: &DnsRequestHandler, name: &str, record_type: RecordType) -> Message {
+        let mut message = Message::query();
+        message.add_query(Query::query(name.parse().unwrap(), record_type));
+
+        let request = Request::from_bytes(
+            message.to_vec().unwrap(),
+            SocketAddr::from((Ipv4Addr::LOCALHOST, 53)),
+            Protocol::Udp,
+        )
+        .unwrap();
+
+        let response_handle = BufferedResponseHandler::default();
+
+        handler
+            .handle_request::<_, TokioTime>(&request, response_handle.clone())
+            .await;
+
+        Message::from_vec(&response_handle.take().unwrap()).unwrap()
+    }
+
+    fn intercept_handler(records: &[(&str, IpAddr)]) -> DnsRequestHandler {
+        let authority_wrapper = AuthorityWrapper::new("docker.".parse().unwrap());
+
+        DnsRequestHandler::new(
+            authority_wrapper.catalog(),
+            records
+                .iter()
+                .map(|&(name, addr)| RawRecord {
+                    name: name.parse().unwrap(),
+                    addr,
+                })
+                .collect(),
+            None,
+        )
+    }
+
+    fn record_types(records: &[Record]) -> Vec<RecordType> {
+        records.iter().map(Record::record_type).collect()
+    }
+
+    #[tokio::test]
+    async fn intercept_in_our_domain_is_authoritative() {
+        let handler =
+            intercept_handler(&[("static.docker.", IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)))]);
+
+        let response = query(&handler, "static.docker.", RecordType::A).await;
+
+        assert!(response.metadata.authoritative);
+        assert_eq!(record_types(&response.answers), vec![RecordType::A]);
+
+        // NODATA
+        let response = query(&handler, "static.docker.", RecordType::MX).await;
+
+        assert!(response.metadata.authoritative);
+        assert_eq!(response.metadata.response_code, ResponseCode::NoError);
+        assert_eq!(record_types(&response.answers), vec![]);
+        assert_eq!(record_types(&response.authorities), vec![RecordType::SOA]);
+    }
+
+    #[tokio::test]
+    async fn intercept_outside_our_domain_is_not_authoritative() {
+        let handler = intercept_handler(&[("static.lan.", IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)))]);
+
+        let response = query(&handler, "static.lan.", RecordType::A).await;
+
+        assert!(!response.metadata.authoritative);
+        assert_eq!(record_types(&response.answers), vec![RecordType::A]);
+
+        let response = query(&handler, "static.lan.", RecordType::MX).await;
+
+        assert!(!response.metadata.authoritative);
+        assert_eq!(response.metadata.response_code, ResponseCode::NoError);
+        assert_eq!(record_types(&response.answers), vec![]);
+        assert_eq!(record_types(&response.authorities), vec![]);
+    }
+
+    #[tokio::test]
+    async fn intercept_any() {
+        let handler = intercept_handler(&[
+            ("static.docker.", IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))),
+            (
+                "static.docker.",
+                IpAddr::V6(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1)),
+            ),
+        ]);
+
+        let response = query(&handler, "static.docker.", RecordType::ANY).await;
+
+        let mut answers = record_types(&response.answers);
+        answers.sort_unstable();
+
+        assert!(response.metadata.authoritative);
+        assert_eq!(answers, vec![RecordType::A, RecordType::AAAA]);
+        assert_eq!(record_types(&response.authorities), vec![]);
+    }
+
+    #[tokio::test]
+    async fn static_record_ptr_is_in_reverse_zone() {
+        let records = vec![RawRecord {
+            name: "static.docker.".parse().unwrap(),
+            addr: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+        }];
+
+        let authority_wrapper = AuthorityWrapper::new("docker.".parse().unwrap());
+        authority_wrapper.add_static_records(&records).await;
+
+        let handler = DnsRequestHandler::new(authority_wrapper.catalog(), records, None);
+
+        let response = query(&handler, "1.0.0.10.in-addr.arpa.", RecordType::PTR).await;
+
+        assert!(response.metadata.authoritative);
+        assert_eq!(
+            response
+                .answers
+                .iter()
+                .map(|record| record.data.clone())
+                .collect::<Vec<_>>(),
+            vec![RData::PTR(PTR("static.docker.".parse().unwrap()))]
+        );
+
+        // a neighbour gets a negative answer backed by the zone's SOA
+        let response = query(&handler, "2.0.0.10.in-addr.arpa.", RecordType::PTR).await;
+
+        assert_eq!(response.metadata.response_code, ResponseCode::NXDomain);
+        assert_eq!(record_types(&response.authorities), vec![RecordType::SOA]);
+    }
+
+    #[tokio::test]
+    async fn dnstap_logs_query_and_response() {
+        let records = vec![RawRecord {
+            name: "static.docker.".parse().unwrap(),
+            addr: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+        }];
+
+        let authority_wrapper = AuthorityWrapper::new("docker.".parse().unwrap());
+        let (dnstap, mut receiver) = DnstapLogger::new();
+
+        let handler = DnsRequestHandler::new(authority_wrapper.catalog(), records, Some(dnstap));
+
+        let response = query(&handler, "static.docker.", RecordType::A).await;
+
+        let logged_query = receiver.try_recv().unwrap();
+        let logged_response = receiver.try_recv().unwrap();
+
+        receiver.try_recv().unwrap_err();
+
+        let request = Message::from_vec(&logged_query.query_message.unwrap()).unwrap();
+
+        assert_eq!(
+            request.queries,
+            vec![Query::query(
+                "static.docker.".parse().unwrap(),
+                RecordType::A
+            )]
+        );
+        assert_eq!(logged_query.query_address, Some(vec![127, 0, 0, 1]));
+        assert_eq!(logged_query.response_message, None);
+
+        // the client gets what we logged
+        let logged = Message::from_vec(&logged_response.response_message.unwrap()).unwrap();
+
+        assert_eq!(logged, response);
+        assert_eq!(logged.metadata.response_code, ResponseCode::NoError);
+        assert_eq!(record_types(&logged.answers), vec![RecordType::A]);
+
+        assert_eq!(logged_response.query_time_sec, logged_query.query_time_sec);
+        assert_eq!(
+            logged_response.query_time_nsec,
+            logged_query.query_time_nsec
+        );
+        assert!(logged_response.response_time_sec >= logged_response.query_time_sec);
+
+        // and the same for a response from the catalog
+        let response = query(&handler, "missing.docker.", RecordType::A).await;
+
+        let _query = receiver.try_recv().unwrap();
+        let logged = receiver.try_recv().unwrap().response_message.unwrap();
+
+        assert_eq!(response.metadata.response_code, ResponseCode::NXDomain);
+        assert_eq!(Message::from_vec(&logged).unwrap(), response);
+    }
+    #[tokio::test]
+    async fn dnstap_logs_a_truncated_response_as_sent() {
+        // more than fit in the 4096 bytes a UDP response without EDNS is limited to
+        let records = (0..300_u16)
+            .map(|host| RawRecord {
+                name: "many.docker.".parse().unwrap(),
+                addr: IpAddr::V4(Ipv4Addr::from(0x0a00_0000 | u32::from(host))),
+            })
+            .collect();
+
+        let authority_wrapper = AuthorityWrapper::new("docker.".parse().unwrap());
+        let (dnstap, mut receiver) = DnstapLogger::new();
+
+        let handler = DnsRequestHandler::new(authority_wrapper.catalog(), records, Some(dnstap));
+
+        let response = query(&handler, "many.docker.", RecordType::A).await;
+
+        let _query = receiver.try_recv().unwrap();
+        let logged = receiver.try_recv().unwrap().response_message.unwrap();
+
+        // the client gets what we logged, truncated
+        assert_eq!(Message::from_vec(&logged).unwrap(), response);
+        assert!(logged.len() <= 4096);
+
+        assert!(response.metadata.truncation);
+        assert!(response.answers.len() < 300);
+    }
+}
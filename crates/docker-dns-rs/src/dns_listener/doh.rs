@@ -0,0 +1,542 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64::Engine as _;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use color_eyre::eyre;
+use hickory_net::runtime::TokioTime;
+use hickory_net::xfer::Protocol;
+use hickory_server::proto::op::Message;
+use hickory_server::proto::rr::RData;
+use hickory_server::server::{Request, RequestHandler as _};
+use http_body_util::{BodyExt as _, Full, Limited};
+use hyper::body::{Bytes, Incoming};
+use hyper::header::{ALLOW, CACHE_CONTROL, CONTENT_TYPE, HeaderValue};
+use hyper::service::service_fn;
+use hyper::{Method, Response, StatusCode};
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto::Builder;
+use rustls::ServerConfig;
+use rustls::crypto::aws_lc_rs;
+use rustls::pki_types::pem::PemObject as _;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinSet;
+use tokio::time::timeout;
+use tokio_rustls::TlsAcceptor;
+use tokio_util::sync::CancellationToken;
+use tracing::{Level, event};
+
+use crate::dns_listener::DnsRequestHandler;
+use crate::dns_listener::response_buffer::BufferedResponseHandler;
+
+/// The path RFC 8484 uses in its examples, and what most clients default to.
+const DNS_QUERY_PATH: &str = "/dns-query";
+const DNS_MESSAGE_CONTENT_TYPE: &str = "application/dns-message";
+
+/// A DNS message over TCP can't exceed this, no reason to accept more over HTTP.
+const MAX_MESSAGE_SIZE: usize = 0xFFFF;
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// hyper starts it while waiting for the next request, so it's also how long an idle HTTP/1.1
+/// connection is kept open.
+const HEADER_READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// HTTP/2 connections are pinged this often, and closed when a ping isn't answered in time.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(30);
+const KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Connections accepted beyond this are closed right away.
+const MAX_CONNECTIONS: usize = 1024;
+
+pub fn load_tls_config(cert: &Path, key: &Path) -> Result<Arc<ServerConfig>, eyre::Report> {
+    let certs = CertificateDer::pem_file_iter(cert)?.collect::<Result<Vec<_>, _>>()?;
+    let key = PrivateKeyDer::from_pem_file(key)?;
+
+    build_tls_config(certs, key)
+}
+
+fn build_tls_config(
+    certs: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+) -> Result<Arc<ServerConfig>, eyre::Report> {
+    let mut config = ServerConfig::builder_with_provider(Arc::new(aws_lc_rs::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(Arc::new(config))
+}
+
+fn build_http_builder() -> Builder<TokioExecutor> {
+    let mut builder = Builder::new(TokioExecutor::new());
+
+    builder
+        .http1()
+        .timer(TokioTimer::new())
+        .header_read_timeout(HEADER_READ_TIMEOUT);
+    builder
+        .http2()
+        .timer(TokioTimer::new())
+        .keep_alive_interval(KEEP_ALIVE_INTERVAL)
+        .keep_alive_timeout(KEEP_ALIVE_TIMEOUT);
+
+    builder
+}
+
+pub async fn set_up_doh_server(
+    listener: TcpListener,
+    tls_config: Arc<ServerConfig>,
+    handler: DnsRequestHandler,
+    cancellation_token: CancellationToken,
+) {
+    let acceptor = TlsAcceptor::from(tls_config);
+    let builder = build_http_builder();
+    let handler = Arc::new(handler);
+
+    let mut connections = JoinSet::new();
+
+    loop {
+        let (stream, src) = tokio::select! {
+            biased;
+            () = cancellation_token.cancelled() => {
+                event!(Level::INFO, "DNS-over-HTTPS Server cancelled externally");
+                break;
+            },
+            result = listener.accept() => match result {
+                Ok(accepted) => accepted,
+                Err(error) => {
+                    event!(Level::WARN, ?error, "Failed to accept DNS-over-HTTPS connection");
+                    continue;
+                },
+            },
+        };
+
+        // reap finished connections so the set doesn't grow unbounded
+        while connections.try_join_next().is_some() {}
+
+        if connections.len() >= MAX_CONNECTIONS {
+            event!(
+                Level::WARN,
+                %src,
+                "Too many DNS-over-HTTPS connections, closing the new one"
+            );
+            continue;
+        }
+
+        connections.spawn(serve_connection(
+            stream,
+            src,
+            acceptor.clone(),
+            builder.clone(),
+            Arc::clone(&handler),
+        ));
+    }
+
+    // open connections are dropped, there is no state to flush
+    connections.shutdown().await;
+}
+
+async fn serve_connection(
+    stream: TcpStream,
+    src: SocketAddr,
+    acceptor: TlsAcceptor,
+    builder: Builder<TokioExecutor>,
+    handler: Arc<DnsRequestHandler>,
+) {
+    let tls_stream = match timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+        Ok(Ok(tls_stream)) => tls_stream,
+        Ok(Err(error)) => {
+            event!(Level::DEBUG, ?error, %src, "DNS-over-HTTPS TLS handshake failed");
+            return;
+        },
+        Err(_elapsed) => {
+            event!(Level::DEBUG, %src, "DNS-over-HTTPS TLS handshake timed out");
+            return;
+        },
+    };
+
+    let service =
+        service_fn(move |request| handle_http_request(request, src, Arc::clone(&handler)));
+
+    if let Err(error) = builder
+        .serve_connection(TokioIo::new(tls_stream), service)
+        .await
+    {
+        event!(Level::DEBUG, ?error, %src, "DNS-over-HTTPS connection closed with error");
+    }
+}
+
+fn status_response(status: StatusCode) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::default());
+    *response.status_mut() = status;
+
+    response
+}
+
+/// How long the response may be cached, RFC 8484, section 5.1: no longer than its shortest TTL.
+/// A negative answer is cached for the smaller of the SOA's TTL and its MINIMUM, RFC 2308.
+fn max_age(body: &[u8]) -> u32 {
+    Message::from_vec(body).map_or(0, |message| {
+        message
+            .answers
+            .iter()
+            .chain(&message.authorities)
+            .map(|record| {
+                if let RData::SOA(ref soa) = record.data {
+                    record.ttl.min(soa.minimum)
+                } else {
+                    record.ttl
+                }
+            })
+            .min()
+            .unwrap_or(0)
+    })
+}
+
+/// Extracts the `dns` parameter of a GET request, as defined in RFC 8484, section 4.1.
+fn message_from_query(query: Option<&str>) -> Option<Vec<u8>> {
+    let encoded = query?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("dns="))?;
+
+    URL_SAFE_NO_PAD.decode(encoded).ok()
+}
+
+/// Extracts the body of a POST request, as defined in RFC 8484, section 4.1.
+async fn message_from_body(request: hyper::Request<Incoming>) -> Result<Vec<u8>, StatusCode> {
+    let content_type = request.headers().get(CONTENT_TYPE);
+
+    if content_type.is_none_or(|content_type| content_type != DNS_MESSAGE_CONTENT_TYPE) {
+        return Err(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    match Limited::new(request.into_body(), MAX_MESSAGE_SIZE)
+        .collect()
+        .await
+    {
+        Ok(collected) => Ok(collected.to_bytes().to_vec()),
+        Err(error) => {
+            event!(
+                Level::DEBUG,
+                ?error,
+                "Failed to read DNS-over-HTTPS request body"
+            );
+
+            Err(StatusCode::PAYLOAD_TOO_LARGE)
+        },
+    }
+}
+
+async fn handle_http_request(
+    request: hyper::Request<Incoming>,
+    src: SocketAddr,
+    handler: Arc<DnsRequestHandler>,
+) -> Result<Response<Full<Bytes>>, Infallible> {
+    if request.uri().path() != DNS_QUERY_PATH {
+        return Ok(status_response(StatusCode::NOT_FOUND));
+    }
+
+    let raw = if request.method() == Method::GET {
+        let Some(raw) = message_from_query(request.uri().query()) else {
+            return Ok(status_response(StatusCode::BAD_REQUEST));
+        };
+
+        raw
+    } else if request.method() == Method::POST {
+        match message_from_body(request).await {
+            Ok(raw) => raw,
+            Err(status) => return Ok(status_response(status)),
+        }
+    } else {
+        let mut response = status_response(StatusCode::METHOD_NOT_ALLOWED);
+        response
+            .headers_mut()
+            .insert(ALLOW, HeaderValue::from_static("GET, POST"));
+
+        return Ok(response);
+    };
+
+    let dns_request = match Request::from_bytes(raw, src, Protocol::Https) {
+        Ok(dns_request) => dns_request,
+        Err(error) => {
+            event!(Level::DEBUG, ?error, %src, "Invalid DNS-over-HTTPS message");
+
+            return Ok(status_response(StatusCode::BAD_REQUEST));
+        },
+    };
+
+    let response_handle = BufferedResponseHandler::default();
+
+    handler
+        .handle_request::<_, TokioTime>(&dns_request, response_handle.clone())
+        .await;
+
+    let Some(body) = response_handle.take() else {
+        event!(Level::ERROR, %src, "DNS handler did not produce a response");
+
+        return Ok(status_response(StatusCode::INTERNAL_SERVER_ERROR));
+    };
+
+    let cache_control = HeaderValue::try_from(format!("max-age={}", max_age(&body)))
+        .expect("A number is a valid header value");
+
+    let mut response = Response::new(Full::new(Bytes::from(body)));
+    response.headers_mut().insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static(DNS_MESSAGE_CONTENT_TYPE),
+    );
+    response.headers_mut().insert(CACHE_CONTROL, cache_control);
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::sync::Arc;
+
+    use base64::Engine as _;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use hickory_server::proto::op::{Message, MessageType, OpCode, Query, ResponseCode};
+    use hickory_server::proto::rr::rdata::SOA;
+    use hickory_server::proto::rr::{Name, RData, Record, RecordType};
+    use http_body_util::{BodyExt as _, Full};
+    use hyper::body::Bytes;
+    use hyper::header::CONTENT_TYPE;
+    use hyper::{Method, StatusCode};
+    use hyper_util::rt::TokioIo;
+    use pretty_assertions::assert_eq;
+    use rustls::crypto::aws_lc_rs;
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName};
+    use rustls::{ClientConfig, RootCertStore};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_rustls::TlsConnector;
+    use tokio_util::sync::CancellationToken;
+
+    use crate::config::RawRecord;
+    use crate::dns_listener::DnsRequestHandler;
+    use crate::dns_listener::doh::{
+        DNS_MESSAGE_CONTENT_TYPE, build_tls_config, max_age, set_up_doh_server,
+    };
+    use crate::table::{AuthorityWrapper, soa_record};
+
+    struct TestServer {
+        address: std::net::SocketAddr,
+        connector: TlsConnector,
+        cancellation_token: CancellationToken,
+    }
+
+    async fn start_server() -> TestServer {
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+        let cert = CertificateDer::from(certified.cert.der().to_vec());
+        let key = PrivateKeyDer::from(PrivatePkcs8KeyDer::from(
+            certified.signing_key.serialize_der(),
+        ));
+
+        let authority_wrapper = AuthorityWrapper::new("docker.".parse().unwrap());
+        let handler = DnsRequestHandler::new(
+            authority_wrapper.catalog(),
+            vec![RawRecord {
+                name: "static.docker.".parse().unwrap(),
+                addr: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            }],
+            None,
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let cancellation_token = CancellationToken::new();
+
+        tokio::spawn(set_up_doh_server(
+            listener,
+            build_tls_config(vec![cert.clone()], key).unwrap(),
+            handler,
+            cancellation_token.clone(),
+        ));
+
+        let mut roots = RootCertStore::empty();
+        roots.add(cert).unwrap();
+
+        let mut client_config =
+            ClientConfig::builder_with_provider(Arc::new(aws_lc_rs::default_provider()))
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+        client_config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+        TestServer {
+            address,
+            connector: TlsConnector::from(Arc::new(client_config)),
+            cancellation_token,
+        }
+    }
+
+    async fn send(
+        server: &TestServer,
+        request: hyper::Request<Full<Bytes>>,
+    ) -> (StatusCode, Bytes) {
+        let stream = TcpStream::connect(server.address).await.unwrap();
+        let tls_stream = server
+            .connector
+            .connect(ServerName::try_from("localhost").unwrap(), stream)
+            .await
+            .unwrap();
+
+        let (mut sender, connection) =
+            hyper::client::conn::http1::handshake(TokioIo::new(tls_stream))
+                .await
+                .unwrap();
+        tokio::spawn(connection);
+
+        let response = sender.send_request(request).await.unwrap();
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+
+        (status, body)
+    }
+
+    fn query_for(name: &str) -> Vec<u8> {
+        let mut message = Message::query();
+        message.add_query(Query::query(name.parse().unwrap(), RecordType::A));
+        message.metadata.id = 0;
+
+        message.to_vec().unwrap()
+    }
+
+    fn assert_static_answer(body: &[u8]) {
+        let response = Message::from_vec(body).unwrap();
+
+        assert_eq!(
+            response
+                .answers
+                .iter()
+                .map(|record| record.data.clone())
+                .collect::<Vec<_>>(),
+            vec![RData::A(Ipv4Addr::new(10, 0, 0, 1).into())]
+        );
+    }
+
+    #[test]
+    fn max_age_is_the_shortest_ttl() {
+        let name = "static.docker.".parse::<Name>().unwrap();
+        let address = RData::A(Ipv4Addr::new(10, 0, 0, 1).into());
+
+        let mut message = Message::new(0, MessageType::Response, OpCode::Query);
+
+        assert_eq!(max_age(&message.to_vec().unwrap()), 0);
+
+        message
+            .add_answer(Record::from_rdata(name.clone(), 60, address.clone()))
+            .add_answer(Record::from_rdata(name, 30, address));
+
+        assert_eq!(max_age(&message.to_vec().unwrap()), 30);
+    }
+
+    #[test]
+    fn max_age_of_a_negative_answer_is_the_soa_minimum() {
+        let origin = "docker.".parse::<Name>().unwrap();
+
+        let mut message = Message::new(0, MessageType::Response, OpCode::Query);
+        message.metadata.response_code = ResponseCode::NXDomain;
+        message.add_authority(soa_record(origin.clone()));
+
+        // ours has a MINIMUM of 0, a missing container isn't remembered
+        assert_eq!(max_age(&message.to_vec().unwrap()), 0);
+
+        let mut message = Message::new(0, MessageType::Response, OpCode::Query);
+        message.add_authority(Record::from_rdata(
+            origin.clone(),
+            3600,
+            RData::SOA(SOA::new(origin.clone(), origin, 0, 0, 0, 0, 300)),
+        ));
+
+        assert_eq!(max_age(&message.to_vec().unwrap()), 300);
+    }
+
+    #[tokio::test]
+    async fn get_request() {
+        let server = start_server().await;
+
+        let request = hyper::Request::builder()
+            .method(Method::GET)
+            .uri(format!(
+                "/dns-query?dns={}",
+                URL_SAFE_NO_PAD.encode(query_for("static.docker."))
+            ))
+            .header("host", "localhost")
+            .body(Full::default())
+            .unwrap();
+
+        let (status, body) = send(&server, request).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_static_answer(&body);
+
+        server.cancellation_token.cancel();
+    }
+
+    #[tokio::test]
+    async fn post_request() {
+        let server = start_server().await;
+
+        let request = hyper::Request::builder()
+            .method(Method::POST)
+            .uri("/dns-query")
+            .header("host", "localhost")
+            .header(CONTENT_TYPE, DNS_MESSAGE_CONTENT_TYPE)
+            .body(Full::new(Bytes::from(query_for("static.docker."))))
+            .unwrap();
+
+        let (status, body) = send(&server, request).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_static_answer(&body);
+
+        server.cancellation_token.cancel();
+    }
+
+    #[tokio::test]
+    async fn post_request_wrong_content_type() {
+        let server = start_server().await;
+
+        let request = hyper::Request::builder()
+            .method(Method::POST)
+            .uri("/dns-query")
+            .header("host", "localhost")
+            .header(CONTENT_TYPE, "text/plain")
+            .body(Full::new(Bytes::from(query_for("static.docker."))))
+            .unwrap();
+
+        let (status, _) = send(&server, request).await;
+
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        server.cancellation_token.cancel();
+    }
+
+    #[tokio::test]
+    async fn unknown_path() {
+        let server = start_server().await;
+
+        let request = hyper::Request::builder()
+            .method(Method::GET)
+            .uri("/resolve")
+            .header("host", "localhost")
+            .body(Full::default())
+            .unwrap();
+
+        let (status, _) = send(&server, request).await;
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        server.cancellation_token.cancel();
+    }
+}
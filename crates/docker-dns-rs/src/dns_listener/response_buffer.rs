@@ -0,0 +1,50 @@
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use hickory_net::NetError;
+use hickory_server::proto::rr::Record;
+use hickory_server::proto::serialize::binary::BinEncoder;
+use hickory_server::server::{ResponseHandler, ResponseInfo};
+use hickory_server::zone_handler::MessageResponse;
+
+/// A `ResponseHandler` that keeps the encoded response in memory instead of writing it to a socket.
+///
+/// Used by transports that aren't driven by hickory's `Server`, where the response needs to be
+/// wrapped (e.g. in an HTTP body) before it is sent.
+#[derive(Clone, Default)]
+pub struct BufferedResponseHandler {
+    buffer: Arc<Mutex<Option<Vec<u8>>>>,
+}
+
+impl BufferedResponseHandler {
+    /// Takes the encoded response, if one was sent.
+    pub fn take(&self) -> Option<Vec<u8>> {
+        self.buffer
+            .lock()
+            .expect("Response buffer lock poisoned")
+            .take()
+    }
+}
+
+#[async_trait]
+impl ResponseHandler for BufferedResponseHandler {
+    async fn send_response<'a>(
+        &mut self,
+        response: MessageResponse<
+            '_,
+            'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+        >,
+    ) -> Result<ResponseInfo, NetError> {
+        let mut bytes = Vec::with_capacity(512);
+
+        let info = response.destructive_emit(&mut BinEncoder::new(&mut bytes))?;
+
+        *self.buffer.lock().expect("Response buffer lock poisoned") = Some(bytes);
+
+        Ok(info)
+    }
+}
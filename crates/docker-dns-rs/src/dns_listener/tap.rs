@@ -0,0 +1,134 @@
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use hickory_net::NetError;
+use hickory_net::udp::MAX_RECEIVE_BUFFER_SIZE;
+use hickory_net::xfer::Protocol;
+use hickory_server::proto::op::{Edns, Message, Metadata, ResponseCode};
+use hickory_server::proto::rr::Record;
+use hickory_server::proto::serialize::binary::{BinDecoder, BinEncoder};
+use hickory_server::server::{ResponseHandler, ResponseInfo};
+use hickory_server::zone_handler::{MessageResponse, MessageResponseBuilder, Queries};
+use tracing::{Level, event};
+
+use crate::dnstap::{DnstapLogger, TappedQuery};
+
+/// The size of a DNS header, the question section follows it.
+const HEADER_LEN: usize = 12;
+
+/// The size hickory's `ResponseHandle` limits a response to, so what we log is what's sent: UDP
+/// responses are truncated to the client's EDNS payload size.
+fn max_size(protocol: Protocol, edns: Option<&Edns>) -> u16 {
+    if protocol == Protocol::Udp {
+        edns.map_or_else(
+            || u16::try_from(MAX_RECEIVE_BUFFER_SIZE).unwrap_or(u16::MAX),
+            Edns::max_payload,
+        )
+    } else {
+        u16::MAX
+    }
+}
+
+/// Parses an encoded response back, to pass it on.
+fn parse(bytes: &[u8]) -> Result<(Message, Queries), NetError> {
+    let message = Message::from_vec(bytes)?;
+
+    // `Queries` keeps the question section as it was sent, so read it from the original bytes
+    let mut decoder = BinDecoder::new(bytes.get(HEADER_LEN..).unwrap_or_default());
+    let queries = Queries::read(&mut decoder, message.queries.len())?;
+
+    Ok((message, queries))
+}
+
+/// A `ResponseHandler` that logs the response to dnstap before passing it on.
+///
+/// `MessageResponse` can only be consumed by encoding it, so we encode it as the transport would,
+/// log the bytes, and rebuild it from those for the wrapped handler. When that fails, the client
+/// still gets an answer, a SERVFAIL, and only the dnstap frame is skipped.
+#[derive(Clone)]
+pub struct TappedResponseHandler<R> {
+    inner: R,
+    dnstap: DnstapLogger,
+    query: TappedQuery,
+    request: Metadata,
+}
+
+impl<R> TappedResponseHandler<R> {
+    pub fn new(inner: R, dnstap: DnstapLogger, query: TappedQuery, request: Metadata) -> Self {
+        Self {
+            inner,
+            dnstap,
+            query,
+            request,
+        }
+    }
+}
+
+impl<R: ResponseHandler> TappedResponseHandler<R> {
+    /// What hickory answers when it can't encode a response.
+    async fn serve_failed(&mut self) -> Result<ResponseInfo, NetError> {
+        let queries = Queries::read(&mut BinDecoder::new(&[]), 0)?;
+
+        let response = MessageResponseBuilder::new(&queries, None)
+            .error_msg(&self.request, ResponseCode::ServFail);
+
+        self.inner.send_response(response).await
+    }
+}
+
+#[async_trait]
+impl<R: ResponseHandler> ResponseHandler for TappedResponseHandler<R> {
+    async fn send_response<'a>(
+        &mut self,
+        response: MessageResponse<
+            '_,
+            'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+        >,
+    ) -> Result<ResponseInfo, NetError> {
+        let mut bytes = Vec::with_capacity(512);
+
+        let mut encoder = BinEncoder::new(&mut bytes);
+        encoder.set_max_size(max_size(self.query.protocol(), response.edns()));
+
+        let emitted = response.destructive_emit(&mut encoder);
+
+        let response_time = SystemTime::now();
+
+        let parsed = emitted
+            .map_err(NetError::from)
+            .and_then(|_info| parse(&bytes));
+
+        let (message, queries) = match parsed {
+            Ok(parsed) => parsed,
+            Err(error) => {
+                event!(
+                    Level::WARN,
+                    ?error,
+                    "Failed to encode the response for dnstap, answering SERVFAIL"
+                );
+
+                return self.serve_failed().await;
+            },
+        };
+
+        // a truncated response fits, so the wrapped handler sends these same bytes
+        let rebuilt = MessageResponseBuilder::new(&queries, message.edns.as_ref()).build(
+            message.metadata,
+            message.answers.iter(),
+            message.authorities.iter(),
+            std::iter::empty(),
+            message.additionals.iter(),
+        );
+
+        let info = self.inner.send_response(rebuilt).await;
+
+        self.dnstap
+            .client_response(&self.query, bytes, response_time);
+
+        info
+    }
+}
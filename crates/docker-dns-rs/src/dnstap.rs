@@ -0,0 +1,516 @@
+use std::io::ErrorKind;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use color_eyre::eyre;
+use color_eyre::eyre::WrapErr as _;
+use hickory_net::xfer::Protocol;
+use hickory_server::server::Request;
+use prost::Message as _;
+use tokio::fs::File;
+use tokio::io::{AsyncWrite, AsyncWriteExt as _, BufWriter};
+use tokio::net::UnixStream;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+use tracing::{Level, event};
+
+use crate::config::DnstapOutput;
+use crate::dnstap::frame_stream::{ControlType, expect_control, write_control, write_data};
+use crate::dnstap::message::{
+    Dnstap, DnstapType, Message, MessageType, SocketFamily, SocketProtocol,
+};
+
+mod frame_stream;
+mod message;
+
+/// How many messages can wait for the writer before we start dropping them.
+const QUEUE_SIZE: usize = 4096;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// How long we wait for the collector to acknowledge our `STOP`.
+const FINISH_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Hands dnstap messages to the writer task.
+///
+/// Logging never blocks a DNS response: when the writer can't keep up, messages are dropped.
+#[derive(Clone)]
+pub struct DnstapLogger {
+    sender: Sender<Message>,
+}
+
+impl DnstapLogger {
+    pub fn new() -> (Self, Receiver<Message>) {
+        let (sender, receiver) = tokio::sync::mpsc::channel(QUEUE_SIZE);
+
+        (Self { sender }, receiver)
+    }
+
+    pub fn client_query(&self, query: &TappedQuery) {
+        self.send(query.message(MessageType::ClientQuery));
+    }
+
+    pub fn client_response(
+        &self,
+        query: &TappedQuery,
+        response: Vec<u8>,
+        response_time: SystemTime,
+    ) {
+        // the query time, which we also send, is what lets collectors compute the latency
+        let mut message = query.message(MessageType::ClientResponse);
+
+        let (seconds, nanoseconds) = unix_time(response_time);
+        message.response_time_sec = Some(seconds);
+        message.response_time_nsec = Some(nanoseconds);
+        message.response_message = Some(response);
+
+        self.send(message);
+    }
+
+    fn send(&self, message: Message) {
+        match self.sender.try_send(message) {
+            Ok(()) => {},
+            Err(TrySendError::Full(_)) => {
+                event!(Level::DEBUG, "dnstap queue is full, dropping message");
+            },
+            Err(TrySendError::Closed(_)) => {
+                event!(Level::TRACE, "dnstap writer stopped, dropping message");
+            },
+        }
+    }
+}
+
+/// What we need to keep of a request to log it, and later its response.
+#[derive(Clone)]
+pub struct TappedQuery {
+    src: SocketAddr,
+    protocol: Protocol,
+    message: Arc<[u8]>,
+    time: SystemTime,
+}
+
+impl TappedQuery {
+    pub fn new(request: &Request, time: SystemTime) -> Self {
+        Self {
+            src: request.src(),
+            protocol: request.protocol(),
+            message: Arc::from(request.as_slice()),
+            time,
+        }
+    }
+
+    pub const fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    fn message(&self, message_type: MessageType) -> Message {
+        let (family, address) = match self.src.ip() {
+            IpAddr::V4(ip) => (SocketFamily::Inet, ip.octets().to_vec()),
+            IpAddr::V6(ip) => (SocketFamily::Inet6, ip.octets().to_vec()),
+        };
+
+        let (seconds, nanoseconds) = unix_time(self.time);
+
+        Message {
+            r#type: message_type.into(),
+            socket_family: Some(family.into()),
+            socket_protocol: socket_protocol(self.protocol).map(Into::into),
+            query_address: Some(address),
+            query_port: Some(u32::from(self.src.port())),
+            query_time_sec: Some(seconds),
+            query_time_nsec: Some(nanoseconds),
+            query_message: Some(self.message.to_vec()),
+            ..Message::default()
+        }
+    }
+}
+
+fn socket_protocol(protocol: Protocol) -> Option<SocketProtocol> {
+    match protocol {
+        Protocol::Udp => Some(SocketProtocol::Udp),
+        Protocol::Tcp => Some(SocketProtocol::Tcp),
+        Protocol::Tls => Some(SocketProtocol::Dot),
+        Protocol::Https => Some(SocketProtocol::Doh),
+        _ => None,
+    }
+}
+
+fn unix_time(time: SystemTime) -> (u64, u32) {
+    let since_epoch = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+
+    (since_epoch.as_secs(), since_epoch.subsec_nanos())
+}
+
+/// Where the previous capture is moved, `dnstap.fstrm` becomes `dnstap.fstrm.1`.
+fn previous_capture(path: &Path) -> PathBuf {
+    path.with_added_extension("1")
+}
+
+pub enum DnstapWriter {
+    File(File),
+    Socket(PathBuf),
+}
+
+impl DnstapWriter {
+    /// Opens the file right away, so a bad path fails at startup, and while we still have our privileges.
+    /// A capture from a previous run is moved aside, replacing the one from before.
+    ///
+    /// Sockets are connected by `run`, and reconnected when the collector goes away.
+    pub async fn open(output: DnstapOutput) -> Result<Self, eyre::Report> {
+        match output {
+            DnstapOutput::File(path) => {
+                let previous = previous_capture(&path);
+
+                match tokio::fs::rename(&path, &previous).await {
+                    Ok(()) => {
+                        event!(
+                            Level::INFO,
+                            previous = %previous.display(),
+                            "Moved the previous dnstap file aside"
+                        );
+                    },
+                    Err(error) if error.kind() == ErrorKind::NotFound => {},
+                    Err(error) => {
+                        return Err(error).wrap_err_with(|| {
+                            format!("Failed to move dnstap file {} aside", path.display())
+                        });
+                    },
+                }
+
+                let file = File::create(&path)
+                    .await
+                    .wrap_err_with(|| format!("Failed to create dnstap file {}", path.display()))?;
+
+                Ok(Self::File(file))
+            },
+            DnstapOutput::Socket(path) => Ok(Self::Socket(path)),
+        }
+    }
+
+    pub async fn run(self, mut receiver: Receiver<Message>, cancellation_token: CancellationToken) {
+        match self {
+            Self::File(file) => {
+                let mut writer = BufWriter::new(file);
+
+                if let Err(error) =
+                    write_file(&mut writer, &mut receiver, &cancellation_token).await
+                {
+                    event!(Level::ERROR, ?error, "Failed to write dnstap file");
+                }
+            },
+            Self::Socket(path) => loop {
+                match write_socket(&path, &mut receiver, &cancellation_token).await {
+                    Ok(()) => return,
+                    Err(error) => {
+                        event!(
+                            Level::WARN,
+                            ?error,
+                            path = %path.display(),
+                            "dnstap socket failed, reconnecting"
+                        );
+                    },
+                }
+
+                tokio::select! {
+                    biased;
+                    () = cancellation_token.cancelled() => return,
+                    () = tokio::time::sleep(RECONNECT_DELAY) => {},
+                }
+            },
+        }
+    }
+}
+
+/// Unidirectional: `START`, data frames, `STOP`.
+async fn write_file<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    receiver: &mut Receiver<Message>,
+    cancellation_token: &CancellationToken,
+) -> Result<(), eyre::Report> {
+    write_control(writer, ControlType::Start).await?;
+
+    write_messages(writer, receiver, cancellation_token).await?;
+
+    write_control(writer, ControlType::Stop).await
+}
+
+/// Bidirectional: `READY`, the collector's `ACCEPT`, then like a file, and the collector's `FINISH`.
+async fn write_socket(
+    path: &Path,
+    receiver: &mut Receiver<Message>,
+    cancellation_token: &CancellationToken,
+) -> Result<(), eyre::Report> {
+    let mut stream = UnixStream::connect(path).await?;
+    let (mut reader, writer) = stream.split();
+    let mut writer = BufWriter::new(writer);
+
+    write_control(&mut writer, ControlType::Ready).await?;
+    expect_control(&mut reader, ControlType::Accept).await?;
+
+    event!(Level::INFO, path = %path.display(), "Connected to dnstap collector");
+
+    write_file(&mut writer, receiver, cancellation_token).await?;
+
+    let Ok(finished) = timeout(
+        FINISH_TIMEOUT,
+        expect_control(&mut reader, ControlType::Finish),
+    )
+    .await
+    else {
+        event!(Level::DEBUG, "dnstap collector did not acknowledge STOP");
+
+        return Ok(());
+    };
+
+    finished
+}
+
+/// Writes messages until we're cancelled, then writes what was already queued.
+async fn write_messages<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    receiver: &mut Receiver<Message>,
+    cancellation_token: &CancellationToken,
+) -> Result<(), eyre::Report> {
+    let version = format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")).into_bytes();
+
+    let encode = |message: Message| {
+        Dnstap {
+            identity: None,
+            version: Some(version.clone()),
+            message: Some(message),
+            r#type: DnstapType::Message.into(),
+        }
+        .encode_to_vec()
+    };
+
+    loop {
+        let message = tokio::select! {
+            biased;
+            () = cancellation_token.cancelled() => break,
+            message = receiver.recv() => match message {
+                Some(message) => message,
+                None => break,
+            },
+        };
+
+        write_data(writer, &encode(message)).await?;
+
+        // batch writes while busy, but don't sit on messages when idle
+        if receiver.is_empty() {
+            writer.flush().await?;
+        }
+    }
+
+    while let Ok(message) = receiver.try_recv() {
+        write_data(writer, &encode(message)).await?;
+    }
+
+    writer.flush().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+
+    use hickory_net::xfer::Protocol;
+    use hickory_server::proto::op::{Message as DnsMessage, Query};
+    use hickory_server::proto::rr::RecordType;
+    use hickory_server::server::Request;
+    use pretty_assertions::assert_eq;
+    use prost::Message as _;
+    use tokio::io::AsyncReadExt as _;
+    use tokio::net::UnixListener;
+    use tokio_util::sync::CancellationToken;
+
+    use crate::config::DnstapOutput;
+    use crate::dnstap::frame_stream::{ControlType, Frame, read_frame, write_control};
+    use crate::dnstap::message::{Dnstap, MessageType, SocketFamily, SocketProtocol};
+    use crate::dnstap::{DnstapLogger, DnstapWriter, TappedQuery, previous_capture};
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("docker-dns-rs-{}-{}", std::process::id(), name))
+    }
+
+    fn request() -> Request {
+        let mut message = DnsMessage::query();
+        message.add_query(Query::query("web.docker.".parse().unwrap(), RecordType::A));
+
+        Request::from_bytes(
+            message.to_vec().unwrap(),
+            SocketAddr::from(([192, 0, 2, 1], 5353)),
+            Protocol::Udp,
+        )
+        .unwrap()
+    }
+
+    fn data(frame: Frame) -> Dnstap {
+        match frame {
+            Frame::Data(payload) => Dnstap::decode(payload.as_slice()).unwrap(),
+            Frame::Control { control_type, .. } => {
+                panic!("Expected a data frame, got {:?}", control_type)
+            },
+        }
+    }
+
+    fn control(frame: &Frame) -> ControlType {
+        match *frame {
+            Frame::Control { control_type, .. } => control_type,
+            Frame::Data(_) => panic!("Expected a control frame"),
+        }
+    }
+
+    #[tokio::test]
+    async fn file_contains_query_and_response() {
+        let path = temp_path("dnstap.fstrm");
+
+        let (logger, receiver) = DnstapLogger::new();
+        let cancellation_token = CancellationToken::new();
+
+        let writer = DnstapWriter::open(DnstapOutput::File(path.clone()))
+            .await
+            .unwrap();
+
+        let request = request();
+        let query_time = SystemTime::UNIX_EPOCH + Duration::new(1_700_000_000, 5);
+        let response_time = query_time + Duration::from_micros(250);
+
+        let tapped = TappedQuery::new(&request, query_time);
+
+        logger.client_query(&tapped);
+        logger.client_response(&tapped, vec![1, 2, 3], response_time);
+
+        // everything queued before cancellation is still written
+        cancellation_token.cancel();
+        writer.run(receiver, cancellation_token).await;
+
+        let bytes = tokio::fs::read(&path).await.unwrap();
+        tokio::fs::remove_file(&path).await.unwrap();
+
+        let mut reader = bytes.as_slice();
+
+        assert_eq!(
+            control(&read_frame(&mut reader).await.unwrap()),
+            ControlType::Start
+        );
+
+        let query = data(read_frame(&mut reader).await.unwrap())
+            .message
+            .unwrap();
+
+        assert_eq!(query.r#type, i32::from(MessageType::ClientQuery));
+        assert_eq!(query.socket_family, Some(SocketFamily::Inet.into()));
+        assert_eq!(query.socket_protocol, Some(SocketProtocol::Udp.into()));
+        assert_eq!(query.query_address, Some(vec![192, 0, 2, 1]));
+        assert_eq!(query.query_port, Some(5353));
+        assert_eq!(query.query_time_sec, Some(1_700_000_000));
+        assert_eq!(query.query_time_nsec, Some(5));
+        assert_eq!(query.query_message, Some(request.as_slice().to_vec()));
+        assert_eq!(query.response_message, None);
+
+        let response = data(read_frame(&mut reader).await.unwrap())
+            .message
+            .unwrap();
+
+        assert_eq!(response.r#type, i32::from(MessageType::ClientResponse));
+        assert_eq!(response.query_time_sec, Some(1_700_000_000));
+        assert_eq!(response.response_time_sec, Some(1_700_000_000));
+        assert_eq!(response.response_time_nsec, Some(250_005));
+        assert_eq!(response.response_message, Some(vec![1, 2, 3]));
+
+        assert_eq!(
+            control(&read_frame(&mut reader).await.unwrap()),
+            ControlType::Stop
+        );
+        assert!(reader.is_empty());
+    }
+
+    #[tokio::test]
+    async fn previous_capture_is_kept() {
+        let path = temp_path("previous.fstrm");
+        let previous = previous_capture(&path);
+
+        tokio::fs::write(&path, b"first run").await.unwrap();
+
+        let _writer = DnstapWriter::open(DnstapOutput::File(path.clone()))
+            .await
+            .unwrap();
+
+        let kept = tokio::fs::read(&previous).await.unwrap();
+        let current = tokio::fs::read(&path).await.unwrap();
+
+        tokio::fs::remove_file(&path).await.unwrap();
+        tokio::fs::remove_file(&previous).await.unwrap();
+
+        assert_eq!(kept, b"first run");
+        assert_eq!(current, b"");
+    }
+
+    #[tokio::test]
+    async fn socket_handshake() {
+        let path = temp_path("dnstap.sock");
+        let _r = std::fs::remove_file(&path);
+
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let (logger, receiver) = DnstapLogger::new();
+        let cancellation_token = CancellationToken::new();
+
+        let writer = DnstapWriter::open(DnstapOutput::Socket(path.clone()))
+            .await
+            .unwrap();
+
+        let writer_task = tokio::spawn(writer.run(receiver, cancellation_token.clone()));
+
+        let (mut stream, _) = listener.accept().await.unwrap();
+
+        assert_eq!(
+            control(&read_frame(&mut stream).await.unwrap()),
+            ControlType::Ready
+        );
+
+        write_control(&mut stream, ControlType::Accept)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            control(&read_frame(&mut stream).await.unwrap()),
+            ControlType::Start
+        );
+
+        logger.client_query(&TappedQuery::new(&request(), SystemTime::now()));
+
+        let query = data(read_frame(&mut stream).await.unwrap())
+            .message
+            .unwrap();
+
+        assert_eq!(query.r#type, i32::from(MessageType::ClientQuery));
+
+        cancellation_token.cancel();
+
+        assert_eq!(
+            control(&read_frame(&mut stream).await.unwrap()),
+            ControlType::Stop
+        );
+
+        write_control(&mut stream, ControlType::Finish)
+            .await
+            .unwrap();
+
+        writer_task.await.unwrap();
+
+        // the writer closed its end
+        assert_eq!(stream.read(&mut [0; 1]).await.unwrap(), 0);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
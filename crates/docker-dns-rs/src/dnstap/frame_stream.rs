@@ -0,0 +1,260 @@
+//! Frame Streams, the framing dnstap uses, see <https://farsightsec.github.io/fstrm/>.
+//!
+//! A data frame is its length as a big-endian `u32`, followed by the payload.
+//! A control frame starts with a zero length (the escape), then its own length, type and fields.
+
+use color_eyre::eyre;
+use tokio::io::{AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _};
+
+pub const CONTENT_TYPE: &[u8] = b"protobuf:dnstap.Dnstap";
+
+/// Control frames can't be larger than this, per the spec.
+const MAX_CONTROL_FRAME_LEN: u32 = 512;
+
+const CONTROL_FIELD_CONTENT_TYPE: u32 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ControlType {
+    Accept,
+    Start,
+    Stop,
+    Ready,
+    Finish,
+}
+
+impl ControlType {
+    const fn to_u32(self) -> u32 {
+        match self {
+            ControlType::Accept => 1,
+            ControlType::Start => 2,
+            ControlType::Stop => 3,
+            ControlType::Ready => 4,
+            ControlType::Finish => 5,
+        }
+    }
+
+    fn from_u32(value: u32) -> Option<Self> {
+        match value {
+            1 => Some(ControlType::Accept),
+            2 => Some(ControlType::Start),
+            3 => Some(ControlType::Stop),
+            4 => Some(ControlType::Ready),
+            5 => Some(ControlType::Finish),
+            _ => None,
+        }
+    }
+
+    /// Whether this frame carries our content type.
+    ///
+    /// `STOP` and `FINISH` carry none, `ACCEPT` and `READY` list what the sender supports, and
+    /// `START` names the one that follows.
+    const fn has_content_type(self) -> bool {
+        matches!(
+            self,
+            ControlType::Accept | ControlType::Start | ControlType::Ready
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Frame {
+    Data(Vec<u8>),
+    Control {
+        control_type: ControlType,
+        content_types: Vec<Vec<u8>>,
+    },
+}
+
+pub async fn write_data<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    payload: &[u8],
+) -> Result<(), eyre::Report> {
+    let len = u32::try_from(payload.len())?;
+
+    // a zero length data frame would be read as a control frame escape
+    if len == 0 {
+        return Ok(());
+    }
+
+    writer.write_u32(len).await?;
+    writer.write_all(payload).await?;
+
+    Ok(())
+}
+
+pub async fn write_control<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    control_type: ControlType,
+) -> Result<(), eyre::Report> {
+    let mut frame = Vec::with_capacity(16 + CONTENT_TYPE.len());
+
+    push_u32(&mut frame, control_type.to_u32());
+
+    if control_type.has_content_type() {
+        push_u32(&mut frame, CONTROL_FIELD_CONTENT_TYPE);
+        push_u32(&mut frame, u32::try_from(CONTENT_TYPE.len())?);
+        frame.extend_from_slice(CONTENT_TYPE);
+    }
+
+    writer.write_u32(0).await?;
+    writer.write_u32(u32::try_from(frame.len())?).await?;
+    writer.write_all(&frame).await?;
+    writer.flush().await?;
+
+    Ok(())
+}
+
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Frame, eyre::Report> {
+    let len = reader.read_u32().await?;
+
+    if len != 0 {
+        let mut payload = vec![0; usize::try_from(len)?];
+        reader.read_exact(&mut payload).await?;
+
+        return Ok(Frame::Data(payload));
+    }
+
+    let len = reader.read_u32().await?;
+
+    if !(4..=MAX_CONTROL_FRAME_LEN).contains(&len) {
+        return Err(eyre::Report::msg(format!(
+            "Invalid control frame length {}",
+            len
+        )));
+    }
+
+    let mut frame = vec![0; usize::try_from(len)?];
+    reader.read_exact(&mut frame).await?;
+
+    let mut fields = frame.as_slice();
+
+    let control_type = read_u32(&mut fields)
+        .and_then(ControlType::from_u32)
+        .ok_or_else(|| eyre::Report::msg("Unknown control frame type"))?;
+
+    let mut content_types = Vec::new();
+
+    while !fields.is_empty() {
+        let (Some(field_type), Some(field_len)) = (read_u32(&mut fields), read_u32(&mut fields))
+        else {
+            return Err(eyre::Report::msg("Truncated control frame field"));
+        };
+
+        let field_len = usize::try_from(field_len)?;
+
+        let Some((value, rest)) = fields.split_at_checked(field_len) else {
+            return Err(eyre::Report::msg("Truncated control frame field"));
+        };
+
+        if field_type == CONTROL_FIELD_CONTENT_TYPE {
+            content_types.push(value.to_vec());
+        }
+
+        fields = rest;
+    }
+
+    Ok(Frame::Control {
+        control_type,
+        content_types,
+    })
+}
+
+#[expect(clippy::big_endian_bytes, reason = "Frame Streams is big-endian")]
+fn push_u32(frame: &mut Vec<u8>, value: u32) {
+    frame.extend_from_slice(&value.to_be_bytes());
+}
+
+#[expect(clippy::big_endian_bytes, reason = "Frame Streams is big-endian")]
+fn read_u32(bytes: &mut &[u8]) -> Option<u32> {
+    let (value, rest) = bytes.split_first_chunk::<4>()?;
+
+    *bytes = rest;
+
+    Some(u32::from_be_bytes(*value))
+}
+
+/// Reads a control frame of `expected` type, which must support our content type if it lists any.
+pub async fn expect_control<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    expected: ControlType,
+) -> Result<(), eyre::Report> {
+    match read_frame(reader).await? {
+        Frame::Control {
+            control_type,
+            content_types,
+        } if control_type == expected => {
+            if expected.has_content_type()
+                && !content_types.is_empty()
+                && !content_types.iter().any(|c| c == CONTENT_TYPE)
+            {
+                return Err(eyre::Report::msg(format!(
+                    "Peer does not accept content type {}",
+                    String::from_utf8_lossy(CONTENT_TYPE)
+                )));
+            }
+
+            Ok(())
+        },
+        frame @ (Frame::Data(_) | Frame::Control { .. }) => Err(eyre::Report::msg(format!(
+            "Expected {:?} control frame, got {:?}",
+            expected, frame
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::dnstap::frame_stream::{
+        CONTENT_TYPE, ControlType, Frame, read_frame, write_control, write_data,
+    };
+
+    #[tokio::test]
+    async fn frames_round_trip() {
+        let mut buffer = Vec::new();
+
+        write_control(&mut buffer, ControlType::Start)
+            .await
+            .unwrap();
+        write_data(&mut buffer, b"payload").await.unwrap();
+        write_data(&mut buffer, b"").await.unwrap();
+        write_control(&mut buffer, ControlType::Stop).await.unwrap();
+
+        let mut reader = buffer.as_slice();
+
+        assert_eq!(
+            read_frame(&mut reader).await.unwrap(),
+            Frame::Control {
+                control_type: ControlType::Start,
+                content_types: vec![CONTENT_TYPE.to_vec()],
+            }
+        );
+        assert_eq!(
+            read_frame(&mut reader).await.unwrap(),
+            Frame::Data(b"payload".to_vec())
+        );
+        assert_eq!(
+            read_frame(&mut reader).await.unwrap(),
+            Frame::Control {
+                control_type: ControlType::Stop,
+                content_types: vec![],
+            }
+        );
+        assert!(reader.is_empty());
+    }
+
+    #[tokio::test]
+    async fn start_frame_bytes() {
+        let mut buffer = Vec::new();
+
+        write_control(&mut buffer, ControlType::Start)
+            .await
+            .unwrap();
+
+        let mut expected = vec![0, 0, 0, 0, 0, 0, 0, 34, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 22];
+        expected.extend_from_slice(CONTENT_TYPE);
+
+        assert_eq!(buffer, expected);
+    }
+}
@@ -0,0 +1,83 @@
+//! The subset of the dnstap schema we emit, see <https://github.com/dnstap/dnstap.pb/blob/master/dnstap.proto>.
+//!
+//! Written by hand instead of generated, so we don't need `protoc` at build time.
+//! Field numbers and enum values must match the upstream schema.
+
+/// The top-level dnstap container.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Dnstap {
+    /// The name of the server.
+    #[prost(bytes = "vec", optional, tag = "1")]
+    pub identity: Option<Vec<u8>>,
+    /// The implementation and version of the server.
+    #[prost(bytes = "vec", optional, tag = "2")]
+    pub version: Option<Vec<u8>>,
+    #[prost(message, optional, tag = "14")]
+    pub message: Option<Message>,
+    #[prost(enumeration = "DnstapType", required, tag = "15")]
+    pub r#type: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum DnstapType {
+    Message = 1,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+#[expect(clippy::struct_field_names, reason = "Named after the dnstap schema")]
+pub struct Message {
+    #[prost(enumeration = "MessageType", required, tag = "1")]
+    pub r#type: i32,
+    #[prost(enumeration = "SocketFamily", optional, tag = "2")]
+    pub socket_family: Option<i32>,
+    #[prost(enumeration = "SocketProtocol", optional, tag = "3")]
+    pub socket_protocol: Option<i32>,
+    /// The client address, 4 or 16 bytes.
+    #[prost(bytes = "vec", optional, tag = "4")]
+    pub query_address: Option<Vec<u8>>,
+    /// Our address, 4 or 16 bytes.
+    #[prost(bytes = "vec", optional, tag = "5")]
+    pub response_address: Option<Vec<u8>>,
+    #[prost(uint32, optional, tag = "6")]
+    pub query_port: Option<u32>,
+    #[prost(uint32, optional, tag = "7")]
+    pub response_port: Option<u32>,
+    #[prost(uint64, optional, tag = "8")]
+    pub query_time_sec: Option<u64>,
+    #[prost(fixed32, optional, tag = "9")]
+    pub query_time_nsec: Option<u32>,
+    /// The query in wire format.
+    #[prost(bytes = "vec", optional, tag = "10")]
+    pub query_message: Option<Vec<u8>>,
+    #[prost(uint64, optional, tag = "12")]
+    pub response_time_sec: Option<u64>,
+    #[prost(fixed32, optional, tag = "13")]
+    pub response_time_nsec: Option<u32>,
+    /// The response in wire format.
+    #[prost(bytes = "vec", optional, tag = "14")]
+    pub response_message: Option<Vec<u8>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum MessageType {
+    ClientQuery = 5,
+    ClientResponse = 6,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum SocketFamily {
+    Inet = 1,
+    Inet6 = 2,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum SocketProtocol {
+    Udp = 1,
+    Tcp = 2,
+    Dot = 3,
+    Doh = 4,
+}
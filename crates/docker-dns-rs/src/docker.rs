@@ -1 +1,5 @@
+pub mod connection;
+pub mod daemons;
 pub mod monitor;
+pub mod status;
+pub mod swarm;
@@ -0,0 +1,283 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use color_eyre::eyre;
+use tokio::time::{Instant, MissedTickBehavior, sleep_until};
+use tokio_util::sync::CancellationToken;
+use tracing::{Level, event};
+use twistlock::client::Client;
+
+use crate::config::DockerConfig;
+use crate::docker::monitor::Monitor;
+use crate::docker::status::DockerStatus;
+
+/// The first retry is quick, at boot Docker is often only a moment late.
+const MIN_RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+fn build_docker(docker_config: &DockerConfig) -> Result<Client, eyre::Report> {
+    Client::build(
+        docker_config.docker_host.clone(),
+        docker_config.cacert.clone(),
+        docker_config.client_key.clone(),
+        docker_config.client_cert.clone(),
+        docker_config.timeout,
+    )
+}
+
+/// The delay before the next attempt, doubled after every failure.
+fn next_retry_delay(retry_delay: Duration) -> Duration {
+    retry_delay.saturating_mul(2).min(MAX_RETRY_DELAY)
+}
+
+/// Keeps a daemon connected until we're cancelled.
+///
+/// DNS doesn't wait for this: while the daemon is unreachable, its records are what we knew last,
+/// and the monitor's status says it's degraded. Once it has been gone for the grace period, its
+/// records are withdrawn, until it's back. The other daemons' records aren't affected.
+pub async fn supervise(
+    docker_config: DockerConfig,
+    monitor: Arc<Monitor>,
+    cancellation_token: CancellationToken,
+) {
+    let mut retry_delay = MIN_RETRY_DELAY;
+    // when the records of the unreachable daemon are withdrawn, `None` once they are
+    let mut withdraw_at = Some(Instant::now() + docker_config.disconnect_grace);
+
+    'retry: loop {
+        let result = connect(&docker_config, &monitor, &cancellation_token).await;
+
+        if cancellation_token.is_cancelled() {
+            break;
+        }
+
+        let error = result
+            .err()
+            .unwrap_or_else(|| eyre::Report::msg("Docker closed the event stream"));
+
+        // a connection that worked starts over with a quick retry, and a new grace period
+        if matches!(monitor.status(), DockerStatus::Connected { .. }) {
+            retry_delay = MIN_RETRY_DELAY;
+            withdraw_at = Some(Instant::now() + docker_config.disconnect_grace);
+        }
+
+        event!(
+            Level::WARN,
+            daemon = %docker_config.daemon,
+            ?error,
+            ?retry_delay,
+            "Docker is unavailable, serving the records we know, retrying"
+        );
+
+        monitor.set_status(DockerStatus::degraded(&error));
+
+        let retry_at = Instant::now() + retry_delay;
+
+        loop {
+            tokio::select! {
+                biased;
+                () = cancellation_token.cancelled() => break 'retry,
+                () = sleep_until(withdraw_at.unwrap_or(retry_at)), if withdraw_at.is_some() => {
+                    event!(
+                        Level::WARN,
+                        daemon = %docker_config.daemon,
+                        grace = ?docker_config.disconnect_grace,
+                        "Docker has been unavailable for too long, withdrawing its records"
+                    );
+
+                    monitor.withdraw("disconnect").await;
+                    withdraw_at = None;
+                },
+                () = sleep_until(retry_at) => break,
+            }
+        }
+
+        retry_delay = next_retry_delay(retry_delay);
+    }
+
+    event!(Level::INFO, daemon = %docker_config.daemon, "Docker connection stopped");
+}
+
+/// Catches up with what Docker has now, then follows its events, until either fails.
+async fn connect(
+    docker_config: &DockerConfig,
+    monitor: &Monitor,
+    cancellation_token: &CancellationToken,
+) -> Result<(), eyre::Report> {
+    let docker = build_docker(docker_config)?;
+
+    let (sender, receiver) = tokio::sync::mpsc::channel(50);
+
+    // whichever side stops first stops the other
+    let connection_token = cancellation_token.child_token();
+
+    // events are queued while `start` lists what's there, so nothing in between is missed
+    let produce = async {
+        let _guard = connection_token.drop_guard_ref();
+
+        docker.produce_events(sender, &connection_token).await
+    };
+
+    let consume = async {
+        let _guard = connection_token.drop_guard_ref();
+
+        monitor.start(&docker).await?;
+
+        monitor.set_status(DockerStatus::connected());
+        event!(Level::INFO, daemon = %docker_config.daemon, "Connected to Docker");
+
+        monitor
+            .consume_events(&docker, receiver, &connection_token)
+            .await;
+
+        Ok::<_, eyre::Report>(())
+    };
+
+    // Swarm's tasks on other nodes come and go without events. Events reconcile too, each lists
+    // and applies in turn, so an older listing never overwrites a newer one
+    let reconcile = async {
+        let mut interval = tokio::time::interval(docker_config.swarm_interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        // the first tick is right away, `start` has that covered
+        interval.tick().await;
+
+        loop {
+            tokio::select! {
+                biased;
+                () = connection_token.cancelled() => break,
+                _ = interval.tick() => monitor.reconcile_swarm(&docker, "reconcile").await,
+            }
+        }
+    };
+
+    let (produced, consumed, ()) = tokio::join!(produce, consume, reconcile);
+
+    consumed.and(produced)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use hickory_server::proto::rr::Name;
+    use pretty_assertions::assert_eq;
+    use tokio::time::{sleep, timeout};
+    use tokio_util::sync::CancellationToken;
+    use twistlock::config::Endpoint;
+
+    use crate::config::DockerConfig;
+    use crate::docker::connection::{MAX_RETRY_DELAY, connect, next_retry_delay, supervise};
+    use crate::docker::daemons::Daemons;
+    use crate::docker::monitor::{ContainerSummary, Monitor};
+    use crate::docker::status::DockerStatus;
+    use crate::table::AuthorityWrapper;
+
+    /// A daemon that isn't there.
+    fn unreachable(daemon: &str, disconnect_grace: Duration) -> DockerConfig {
+        DockerConfig {
+            daemon: Box::from(daemon),
+            docker_host: Endpoint::Socket("/nonexistent/docker.sock".into()),
+            cacert: None,
+            client_key: None,
+            client_cert: None,
+            timeout: Duration::from_secs(1),
+            swarm_interval: Duration::from_secs(30),
+            disconnect_grace,
+        }
+    }
+
+    #[test]
+    fn retry_delay_doubles_up_to_the_maximum() {
+        assert_eq!(
+            next_retry_delay(Duration::from_secs(1)),
+            Duration::from_secs(2)
+        );
+        assert_eq!(next_retry_delay(Duration::from_secs(20)), MAX_RETRY_DELAY);
+        assert_eq!(next_retry_delay(MAX_RETRY_DELAY), MAX_RETRY_DELAY);
+    }
+
+    #[tokio::test]
+    async fn missing_socket_fails_the_connection_but_not_the_records() {
+        let authority_wrapper = Arc::new(AuthorityWrapper::new("docker.".parse().unwrap()));
+        let monitor = Arc::new(Monitor::new(
+            Arc::clone(&authority_wrapper),
+            Box::from("local"),
+            "docker.".parse().unwrap(),
+            false,
+        ));
+
+        let docker_config = unreachable("local", Duration::from_secs(60));
+
+        let _error = connect(&docker_config, &monitor, &CancellationToken::new())
+            .await
+            .unwrap_err();
+
+        assert_eq!(monitor.status(), DockerStatus::Connecting);
+        assert_eq!(authority_wrapper.zone_files().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn unreachable_daemon_withdraws_only_its_records() {
+        let domain: Name = "docker.".parse().unwrap();
+        let daemons = Daemons::new(
+            AuthorityWrapper::new(domain.clone()),
+            domain,
+            false,
+            ["build1", "build2"].map(Box::from),
+        );
+
+        // what both were running when the state file was written
+        for (last, monitor) in (2_u8..).zip(daemons.monitors()) {
+            monitor
+                .restore(
+                    vec![],
+                    vec![ContainerSummary {
+                        daemon: monitor.daemon().to_owned(),
+                        id: "abc123".to_owned(),
+                        names: vec![format!("{}.docker.", monitor.daemon())],
+                        networks: BTreeMap::from([(
+                            "bridge".to_owned(),
+                            vec![IpAddr::V4(Ipv4Addr::new(172, 17, 0, last))],
+                        )]),
+                    }],
+                )
+                .await;
+        }
+
+        let cancellation_token = CancellationToken::new();
+        let supervisor = tokio::spawn(supervise(
+            unreachable("build1", Duration::ZERO),
+            Arc::clone(&daemons.monitors()[0]),
+            cancellation_token.clone(),
+        ));
+
+        timeout(Duration::from_secs(5), async {
+            while daemons.containers().await.len() > 1 {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        cancellation_token.cancel();
+        supervisor.await.unwrap();
+
+        assert_eq!(
+            daemons
+                .authority_wrapper()
+                .forward_records()
+                .await
+                .into_iter()
+                .filter_map(|record| Some((record.name.to_string(), record.data.ip_addr()?)))
+                .collect::<Vec<_>>(),
+            [(
+                "build2.docker.".to_owned(),
+                IpAddr::V4(Ipv4Addr::new(172, 17, 0, 3))
+            )]
+        );
+    }
+}
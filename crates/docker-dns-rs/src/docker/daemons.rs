@@ -0,0 +1,146 @@
+use std::sync::Arc;
+
+use hickory_server::proto::rr::Name;
+use tracing::{Level, event};
+
+use crate::docker::monitor::{ContainerSummary, Monitor, NetworkSummary};
+use crate::docker::status::DaemonStatus;
+use crate::state_file::{STATE_VERSION, StateSnapshot};
+use crate::table::AuthorityWrapper;
+
+/// Every daemon we follow, each with a monitor of its own, all feeding the same zones.
+pub struct Daemons {
+    authority_wrapper: Arc<AuthorityWrapper>,
+    domain: Name,
+    rfc2317: bool,
+    /// In the order the daemons were configured.
+    monitors: Vec<Arc<Monitor>>,
+}
+
+impl Daemons {
+    pub fn new<I>(
+        authority_wrapper: AuthorityWrapper,
+        domain: Name,
+        rfc2317: bool,
+        daemons: I,
+    ) -> Self
+    where
+        I: IntoIterator<Item = Box<str>>,
+    {
+        let authority_wrapper = Arc::new(authority_wrapper);
+
+        let monitors = daemons
+            .into_iter()
+            .map(|daemon| {
+                Arc::new(Monitor::new(
+                    Arc::clone(&authority_wrapper),
+                    daemon,
+                    domain.clone(),
+                    rfc2317,
+                ))
+            })
+            .collect();
+
+        Self {
+            authority_wrapper,
+            domain,
+            rfc2317,
+            monitors,
+        }
+    }
+
+    pub fn authority_wrapper(&self) -> &AuthorityWrapper {
+        &self.authority_wrapper
+    }
+
+    pub fn monitors(&self) -> &[Arc<Monitor>] {
+        &self.monitors
+    }
+
+    /// Every daemon's containers, by daemon, then by ID.
+    pub async fn containers(&self) -> Vec<ContainerSummary> {
+        let mut containers = Vec::new();
+
+        for monitor in &self.monitors {
+            containers.extend(monitor.containers().await);
+        }
+
+        containers
+    }
+
+    /// Every daemon's networks, by daemon, then by ID.
+    pub async fn networks(&self) -> Vec<NetworkSummary> {
+        let mut networks = Vec::new();
+
+        for monitor in &self.monitors {
+            networks.extend(monitor.networks().await);
+        }
+
+        networks
+    }
+
+    pub fn statuses(&self) -> Vec<DaemonStatus> {
+        self.monitors
+            .iter()
+            .map(|monitor| DaemonStatus {
+                daemon: monitor.daemon().to_owned(),
+                status: monitor.status(),
+            })
+            .collect()
+    }
+
+    /// What `restore` takes back.
+    pub async fn snapshot(&self) -> StateSnapshot {
+        StateSnapshot {
+            version: STATE_VERSION,
+            domain: self.domain.to_string(),
+            rfc2317: self.rfc2317,
+            networks: self.networks().await,
+            containers: self.containers().await,
+        }
+    }
+
+    /// Hands every daemon what it had when `snapshot` was taken. Daemons that are no longer
+    /// configured are left out.
+    pub async fn restore(&self, snapshot: StateSnapshot) {
+        if snapshot.version != STATE_VERSION
+            || snapshot.domain != self.domain.to_string()
+            || snapshot.rfc2317 != self.rfc2317
+        {
+            event!(
+                Level::INFO,
+                "State snapshot is from another version or configuration, ignoring it"
+            );
+            return;
+        }
+
+        let StateSnapshot {
+            mut networks,
+            mut containers,
+            ..
+        } = snapshot;
+
+        for monitor in &self.monitors {
+            let (our_networks, other_networks) = networks
+                .into_iter()
+                .partition(|network| network.daemon == monitor.daemon());
+            networks = other_networks;
+
+            let (our_containers, other_containers) = containers
+                .into_iter()
+                .partition(|container| container.daemon == monitor.daemon());
+            containers = other_containers;
+
+            monitor.restore(our_networks, our_containers).await;
+        }
+
+        if !networks.is_empty() || !containers.is_empty() {
+            event!(
+                Level::INFO,
+                networks = networks.len(),
+                containers = containers.len(),
+                "State snapshot has daemons that are no longer configured, ignoring them"
+            );
+        }
+    }
+}
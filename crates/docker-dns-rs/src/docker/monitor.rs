@@ -1,31 +1,51 @@
+use std::collections::BTreeMap;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::string::ToString as _;
 use std::sync::{Arc, LazyLock};
+use std::time::Instant;
 
 use color_eyre::eyre;
-use hashbrown::HashMap;
 use hashbrown::hash_map::EntryRef;
-use hickory_server::proto::rr::{LowerName, Name};
-use hickory_server::zone_handler::Catalog;
+use hashbrown::{HashMap, HashSet};
+use hickory_server::proto::rr::Name;
 use ipnet::{IpNet, Ipv4Net, Ipv6Net};
 use itertools::Either;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc::Receiver;
-use tokio::sync::{Mutex, RwLock};
+use tokio::sync::{Mutex, watch};
 use tokio_util::sync::CancellationToken;
-use tracing::{Level, event};
+use tracing::{Instrument as _, Level, Span, event, field, instrument, span};
 use twistlock::client::Client;
+use twistlock::endpoint::ApiEndpointCallError;
 use twistlock::filters::Filters;
 use twistlock::models::container_inspect::{
     ContainerInspect, ContainerNetwork, ContainerNetworkSettings,
 };
 use twistlock::models::events::{Event, EventType};
 
-use crate::dns_listener::set_up_authority;
-use crate::table::AuthorityWrapper;
+use crate::audit::Trigger;
+use crate::docker::status::DockerStatus;
+use crate::docker::swarm::{
+    ListServices, ListTasks, SwarmObject, is_not_a_swarm_manager, swarm_objects,
+};
+use crate::table::{AuthorityWrapper, ChangeSet, is_classless, zone_name};
 
 static RE_VALIDNAME: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"[^\w\d.-]").unwrap());
 
+/// Set on the containers of Swarm tasks.
+const SWARM_TASK_LABEL: &str = "com.docker.swarm.task.id";
+
+/// Docker Compose's labels first, then podman-compose's, older versions only set their own.
+const COMPOSE_LABEL_PREFIXES: [&str; 2] = ["com.docker.compose.", "io.podman.compose."];
+
+/// Which engine sent an event, Podman's Docker-compatible API describes some of them differently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Engine {
+    Docker,
+    Podman,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum NetworkIps {
     V4Only(Ipv4Addr),
@@ -43,6 +63,25 @@ impl NetworkIps {
         }
     }
 
+    /// The first IPv4 and the first IPv6 address, like `ips` yields them.
+    fn from_ips(ips: &[IpAddr]) -> Option<Self> {
+        let v4 = ips.iter().find_map(|ip| match *ip {
+            IpAddr::V4(v4) => Some(v4),
+            IpAddr::V6(_) => None,
+        });
+        let v6 = ips.iter().find_map(|ip| match *ip {
+            IpAddr::V4(_) => None,
+            IpAddr::V6(v6) => Some(v6),
+        });
+
+        match (v4, v6) {
+            (Some(v4), Some(v6)) => Some(Self::Both(v4, v6)),
+            (Some(v4), None) => Some(Self::V4Only(v4)),
+            (None, Some(v6)) => Some(Self::V6Only(v6)),
+            (None, None) => None,
+        }
+    }
+
     fn ips(self) -> impl Iterator<Item = IpAddr> {
         match self {
             Self::V4Only(v4) => [Some(IpAddr::V4(v4)), None],
@@ -54,31 +93,82 @@ impl NetworkIps {
     }
 }
 
+/// A container as the admin API shows it.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContainerSummary {
+    pub daemon: String,
+    pub id: String,
+    pub names: Vec<String>,
+    /// Network name to the container's addresses on it.
+    pub networks: BTreeMap<String, Vec<IpAddr>>,
+}
+
+/// A network as the admin API shows it.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkSummary {
+    pub daemon: String,
+    pub id: String,
+    pub reverse_zones: Vec<ReverseZoneSummary>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReverseZoneSummary {
+    pub network: IpNet,
+    pub zone: Option<String>,
+}
+
 struct ContainerState {
     names: Arc<[Name]>,
     /// `network_name` to IPs.
     networks: HashMap<Box<str>, NetworkIps>,
 }
 
+impl ContainerState {
+    /// Every name with every address, what the container has in the forward zone.
+    fn records(&self) -> HashSet<(Name, IpAddr)> {
+        self.networks
+            .values()
+            .flat_map(|network_ips| network_ips.ips())
+            .flat_map(|ip| self.names.iter().map(move |name| (name.clone(), ip)))
+            .collect()
+    }
+}
+
+/// What we know about a Docker daemon, and the records for it.
+///
+/// The connection is passed in, so what we know outlives it, and is reconciled with a new one.
 pub struct Monitor {
-    authority_wrapper: AuthorityWrapper,
-    catalog: Arc<RwLock<Catalog>>,
-    docker: Arc<Client>,
+    /// Shared with the monitors of the other daemons.
+    authority_wrapper: Arc<AuthorityWrapper>,
+    /// What our records are tagged with, another daemon's are never changed.
+    daemon: Box<str>,
+    status: watch::Sender<DockerStatus>,
     domain: Name,
+    /// Whether IPv4 subnets smaller than a /24 get one classless zone instead of a zone per address.
+    rfc2317: bool,
     /// `container_id` to `ContainerState`.
     /// Invariant: names and network entries are always co-located, you cannot have
     /// a network entry without its accompanying names.
     containers: Mutex<HashMap<Box<str>, ContainerState>>,
     /// `network_id` to the reverse zones registered for it.
     networks: Mutex<HashMap<Box<str>, Vec<IpNet>>>,
+    /// Swarm `service_id` or `task_id` to its records, network names are network IDs here. `None`
+    /// when the daemon isn't a Swarm manager, then Swarm is left alone until we reconnect.
+    swarm: Mutex<Option<HashMap<Box<str>, ContainerState>>>,
+}
+
+fn compose_label<'b>(bag: &'b HashMap<Box<str>, Box<str>>, key: &str) -> Option<&'b str> {
+    COMPOSE_LABEL_PREFIXES
+        .iter()
+        .find_map(|prefix| bag.get(&*format!("{prefix}{key}")))
+        .map(|value| &**value)
 }
 
 fn append_compose_names(
     mut names: Vec<Box<str>>,
     bag: &HashMap<Box<str>, Box<str>>,
 ) -> Vec<Box<str>> {
-    let instance = bag
-        .get("com.docker.compose.container-number")
+    let instance = compose_label(bag, "container-number")
         .and_then(|n| match n.parse::<usize>() {
             Ok(v) => Some(v),
             Err(error) => {
@@ -86,15 +176,15 @@ fn append_compose_names(
                     Level::WARN,
                     label_value = %n,
                     ?error,
-                    "Invalid value for com.docker.compose.container-number label, defaulting to 1"
+                    "Invalid value for the compose container-number label, defaulting to 1"
                 );
                 None
             },
         })
         .unwrap_or(1);
 
-    let service = bag.get("com.docker.compose.service");
-    let project = bag.get("com.docker.compose.project");
+    let service = compose_label(bag, "service");
+    let project = compose_label(bag, "project");
 
     if let (Some(service), Some(project)) = (service, project) {
         names.push(format!("{}.{}.{}", instance, service, project).into_boxed_str());
@@ -122,6 +212,20 @@ fn get_all_names_from_event(event: &Event) -> Vec<Box<str>> {
     append_compose_names(names, &event.actor.attributes)
 }
 
+/// The container and the network a network event is about.
+///
+/// Docker's actor is the network, with the container and the network's name as attributes.
+/// Podman's actor is the container, with the network's name as the `network` attribute.
+fn network_event_target(event: &Event) -> Option<(Engine, &str, &str)> {
+    let attributes = &event.actor.attributes;
+
+    match (attributes.get("container"), attributes.get("network")) {
+        (Some(container_id), _) => Some((Engine::Docker, container_id, attributes.get("name")?)),
+        (None, Some(network_name)) => Some((Engine::Podman, &event.actor.id, network_name)),
+        (None, None) => None,
+    }
+}
+
 fn get_all_names_from_inspect(container_inspect: &ContainerInspect) -> Vec<Box<str>> {
     let mut names = vec![];
 
@@ -131,6 +235,32 @@ fn get_all_names_from_inspect(container_inspect: &ContainerInspect) -> Vec<Box<s
     append_compose_names(names, &container_inspect.config.labels)
 }
 
+/// Calls the daemon in a span of its own, so a trace shows how long Docker took to answer.
+async fn timed_inspect<F: Future>(object: &'static str, id: &str, call: F) -> F::Output {
+    let span = span!(
+        Level::DEBUG,
+        "docker inspect",
+        object,
+        id,
+        latency_us = field::Empty
+    );
+
+    async {
+        let start = Instant::now();
+
+        let result = call.await;
+
+        let latency_us = u64::try_from(start.elapsed().as_micros()).unwrap_or(u64::MAX);
+        Span::current().record("latency_us", latency_us);
+
+        event!(Level::DEBUG, object, id, latency_us, "Inspected");
+
+        result
+    }
+    .instrument(span)
+    .await
+}
+
 fn to_full_names(raw: Vec<Box<str>>, domain: &Name) -> Arc<[Name]> {
     raw.into_iter()
         .filter_map(|name| {
@@ -152,12 +282,16 @@ fn to_full_names(raw: Vec<Box<str>>, domain: &Name) -> Arc<[Name]> {
         .collect()
 }
 
-fn parse_subnet_ipv4(net: Ipv4Net) -> impl Iterator<Item = IpNet> {
+fn parse_subnet_ipv4(net: Ipv4Net, rfc2317: bool) -> impl Iterator<Item = IpNet> {
     const OCTET_BOUNDARY: u8 = 8;
 
+    if rfc2317 && is_classless(IpNet::V4(net)) {
+        return Either::Left(std::iter::once(IpNet::V4(net.trunc())));
+    }
+
     // Round prefix up to the next octet boundary (min /8).
     let boundary = net.prefix_len().max(1).div_ceil(OCTET_BOUNDARY) * OCTET_BOUNDARY;
-    net.subnets(boundary).into_iter().flatten().map(IpNet::V4)
+    Either::Right(net.subnets(boundary).into_iter().flatten().map(IpNet::V4))
 }
 
 fn parse_subnet_ipv6(net: Ipv6Net) -> impl Iterator<Item = IpNet> {
@@ -168,44 +302,6 @@ fn parse_subnet_ipv6(net: Ipv6Net) -> impl Iterator<Item = IpNet> {
     net.subnets(boundary).into_iter().flatten().map(IpNet::V6)
 }
 
-fn zone_name(network: IpNet) -> Option<Name> {
-    match network {
-        IpNet::V4(net) => {
-            static DOMAIN: LazyLock<Name> =
-                LazyLock::new(|| Name::from_str_relaxed("in-addr.arpa").unwrap());
-
-            let byte_count = usize::from(net.prefix_len()) / 8;
-
-            let labels = net
-                .addr()
-                .octets()
-                .into_iter()
-                .take(byte_count)
-                .rev()
-                .map(|n| n.to_string());
-
-            Name::from_labels(labels).ok()?.append_domain(&DOMAIN).ok()
-        },
-        IpNet::V6(net) => {
-            static DOMAIN: LazyLock<Name> =
-                LazyLock::new(|| Name::from_str_relaxed("ip6.arpa").unwrap());
-
-            let nibble_count = usize::from(net.prefix_len()) / 4;
-            let octets = net.addr().octets();
-            let labels = (0..nibble_count).rev().map(move |i| {
-                let nibble = if i % 2 == 0 {
-                    octets[i / 2] >> 4
-                } else {
-                    octets[i / 2] & 0x0f
-                };
-                format!("{nibble:x}")
-            });
-
-            Name::from_labels(labels).ok()?.append_domain(&DOMAIN).ok()
-        },
-    }
-}
-
 /// Expand an `IpNet` into `(IpNet, Name)` pairs, one per reverse zone.
 ///
 /// ## IPv4:
@@ -217,11 +313,13 @@ fn zone_name(network: IpNet) -> Option<Name> {
 ///
 /// E.g. `172.16.66.123/18` becomes `172.16.64.0/24..=172.16.127.0/24` = 64 new subnets.
 ///
+/// With `rfc2317`, prefixes between /25 and /31 produce a single classless zone instead of a zone per address.
+///
 /// ## IPv6:
 /// Zones are at the /4 nibble boundary with the same rounding logic as IPv4.
-fn parse_subnet(network: IpNet) -> impl Iterator<Item = (IpNet, Name)> {
+fn parse_subnet(network: IpNet, rfc2317: bool) -> impl Iterator<Item = (IpNet, Name)> {
     let iter = match network {
-        IpNet::V4(net) => Either::Left(parse_subnet_ipv4(net)),
+        IpNet::V4(net) => Either::Left(parse_subnet_ipv4(net, rfc2317)),
         IpNet::V6(net) => Either::Right(parse_subnet_ipv6(net)),
     };
 
@@ -238,23 +336,95 @@ fn parse_subnet(network: IpNet) -> impl Iterator<Item = (IpNet, Name)> {
 
 impl Monitor {
     pub fn new(
-        docker: Arc<Client>,
-        authority_wrapper: AuthorityWrapper,
-        catalog: Arc<RwLock<Catalog>>,
+        authority_wrapper: Arc<AuthorityWrapper>,
+        daemon: Box<str>,
         domain: Name,
+        rfc2317: bool,
     ) -> Self {
         Self {
             authority_wrapper,
-            catalog,
-            docker,
+            daemon,
+            status: watch::Sender::new(DockerStatus::Connecting),
             domain,
+            rfc2317,
             containers: Mutex::new(HashMap::new()),
             networks: Mutex::new(HashMap::new()),
+            swarm: Mutex::new(Some(HashMap::new())),
         }
     }
 
+    pub fn daemon(&self) -> &str {
+        &self.daemon
+    }
+
+    /// Changes to our records, made in response to `event` for `container_id`.
+    fn change_set(&self, container_id: &str, event: &str) -> ChangeSet {
+        ChangeSet::new(Trigger::new(&self.daemon, container_id, event))
+    }
+
+    pub fn status(&self) -> DockerStatus {
+        self.status.borrow().clone()
+    }
+
+    pub fn set_status(&self, status: DockerStatus) {
+        self.status.send_replace(status);
+    }
+
+    /// The containers we registered records for, ordered by ID.
+    pub async fn containers(&self) -> Vec<ContainerSummary> {
+        let mut containers = self
+            .containers
+            .lock()
+            .await
+            .iter()
+            .map(|(id, state)| ContainerSummary {
+                daemon: self.daemon.to_string(),
+                id: id.to_string(),
+                names: state.names.iter().map(ToString::to_string).collect(),
+                networks: state
+                    .networks
+                    .iter()
+                    .map(|(network_name, network_ips)| {
+                        (network_name.to_string(), network_ips.ips().collect())
+                    })
+                    .collect(),
+            })
+            .collect::<Vec<_>>();
+
+        containers.sort_unstable_by(|left, right| left.id.cmp(&right.id));
+
+        containers
+    }
+
+    /// The networks we registered reverse zones for, ordered by ID.
+    pub async fn networks(&self) -> Vec<NetworkSummary> {
+        let mut networks = self
+            .networks
+            .lock()
+            .await
+            .iter()
+            .map(|(id, reverse_zones)| NetworkSummary {
+                daemon: self.daemon.to_string(),
+                id: id.to_string(),
+                reverse_zones: reverse_zones
+                    .iter()
+                    .map(|&network| ReverseZoneSummary {
+                        network,
+                        zone: zone_name(network).map(|zone| zone.to_string()),
+                    })
+                    .collect(),
+            })
+            .collect::<Vec<_>>();
+
+        networks.sort_unstable_by(|left, right| left.id.cmp(&right.id));
+
+        networks
+    }
+
+    /// `event` is what the changes are attributed to in the audit log.
     async fn register_container_networks(
         &self,
+        event: &str,
         container_id: &str,
         full_names: Arc<[Name]>,
         network_settings: ContainerNetworkSettings,
@@ -274,6 +444,8 @@ impl Monitor {
                 .into_mut(),
         };
 
+        let mut change_set = self.change_set(container_id, event);
+
         for (network_name, network) in network_settings.networks {
             let Some(network_ips) = NetworkIps::from_network(&network) else {
                 continue;
@@ -281,12 +453,14 @@ impl Monitor {
 
             for ip in network_ips.ips() {
                 for name in &*container_state.names {
-                    self.authority_wrapper.add(name, ip).await;
+                    change_set.add(name, ip);
                 }
             }
 
             container_state.networks.insert(network_name, network_ips);
         }
+
+        self.authority_wrapper.apply(change_set).await;
     }
 
     async fn handle_container_rename(&self, event: Event) {
@@ -313,13 +487,41 @@ impl Monitor {
         match (old_name, new_name) {
             (None, None) => event!(Level::WARN, "Rename event without oldName and without name"),
             (None, Some(new_name)) => {
-                event!(Level::WARN, old_name = %"<ABSENT>", %new_name, "Rename event without oldName");
+                // Podman only sends the new name, the container's records are rebuilt from it
+                let networks = self
+                    .containers
+                    .lock()
+                    .await
+                    .get(&*event.actor.id)
+                    .map(|state| state.networks.clone());
+
+                let Some(networks) = networks else {
+                    event!(Level::WARN, container_id = %event.actor.id, %new_name, "Rename event for an unknown container");
+                    return;
+                };
+
+                let names = to_full_names(get_all_names_from_event(&event), &self.domain);
+
+                self.replace_container(
+                    "container:rename",
+                    &event.actor.id,
+                    ContainerState { names, networks },
+                )
+                .await;
             },
             (Some(old_name), None) => {
                 event!(Level::WARN, %old_name, new_name = %"<ABSENT>", "Rename event without name");
             },
             (Some(old_name), Some(new_name)) => {
-                if let Err(error) = self.authority_wrapper.rename(&old_name, &new_name).await {
+                if let Err(error) = self
+                    .authority_wrapper
+                    .rename(
+                        &old_name,
+                        &new_name,
+                        Trigger::new(&self.daemon, &event.actor.id, "container:rename"),
+                    )
+                    .await
+                {
                     event!(
                         Level::WARN,
                         ?error,
@@ -339,13 +541,20 @@ impl Monitor {
         }
     }
 
-    async fn handle_container_start(&self, event: Event) {
-        match self.docker.inspect_container(&event.actor.id).await {
+    async fn handle_container_start(&self, docker: &Client, event: Event) {
+        match timed_inspect(
+            "container",
+            &event.actor.id,
+            docker.inspect_container(&event.actor.id),
+        )
+        .await
+        {
             Ok(container) => {
                 let full_names =
                     to_full_names(get_all_names_from_inspect(&container), &self.domain);
 
                 self.register_container_networks(
+                    "container:start",
                     &event.actor.id,
                     full_names,
                     container.network_settings,
@@ -364,41 +573,52 @@ impl Monitor {
     }
 
     async fn handle_container_die(&self, event: Event) {
-        let Some(state) = self.containers.lock().await.remove(&*event.actor.id) else {
+        self.remove_container(&event.actor.id, "container:die")
+            .await;
+    }
+
+    async fn remove_container(&self, container_id: &str, event: &str) {
+        let Some(state) = self.containers.lock().await.remove(container_id) else {
             return;
         };
 
+        let mut change_set = self.change_set(container_id, event);
+
         for (_, network_ips) in state.networks {
             for ip in network_ips.ips() {
                 for name in &*state.names {
-                    self.authority_wrapper.remove_address(name, ip).await;
+                    change_set.remove(name, ip);
                 }
             }
         }
+
+        self.authority_wrapper.apply(change_set).await;
     }
 
-    async fn handle_network_connect(&self, event: Event) {
-        let Some(container_id) = event.actor.attributes.get("container") else {
+    async fn handle_network_connect(&self, docker: &Client, event: Event) {
+        let Some((engine, container_id, network_name)) = network_event_target(&event) else {
             event!(
                 Level::WARN,
                 ?event,
-                "Got network connect event, but event did not contain container id"
+                "Got network connect event, but event did not contain container id and network name"
             );
             return;
         };
 
-        let Some(network_name) = event.actor.attributes.get("name") else {
-            event!(
-                Level::WARN,
-                ?event,
-                "Got network connect event, but event did not contain network name"
-            );
-            return;
-        };
+        // Podman doesn't announce the networks it creates, only the connections to them
+        if engine == Engine::Podman {
+            self.register_network(docker, network_name).await;
+        }
 
-        match self.docker.inspect_container(container_id).await {
+        match timed_inspect(
+            "container",
+            container_id,
+            docker.inspect_container(container_id),
+        )
+        .await
+        {
             Ok(container) => {
-                let Some(network) = container.network_settings.networks.get(&**network_name) else {
+                let Some(network) = container.network_settings.networks.get(network_name) else {
                     event!(
                         Level::WARN,
                         %container_id,
@@ -420,11 +640,11 @@ impl Monitor {
 
                 let mut containers = self.containers.lock().await;
 
-                let state = match containers.entry_ref(&**container_id) {
+                let state = match containers.entry_ref(container_id) {
                     EntryRef::Occupied(occupied_entry) => occupied_entry.into_mut(),
                     EntryRef::Vacant(vacant_entry_ref) => vacant_entry_ref
                         .insert_entry_with_key(
-                            container_id.clone(),
+                            Box::from(container_id),
                             ContainerState {
                                 names: to_full_names(
                                     get_all_names_from_inspect(&container),
@@ -437,13 +657,15 @@ impl Monitor {
                 };
 
                 // If the same IPs were previously registered for this network (e.g. startup race between start()'s container list and this event), skip.
-                // If different IPs were registered, remove the stale DNS records first.
-                match state.networks.insert(network_name.clone(), network_ips) {
+                // If different IPs were registered, replace the stale DNS records in the same change.
+                let mut change_set = self.change_set(container_id, "network:connect");
+
+                match state.networks.insert(Box::from(network_name), network_ips) {
                     Some(old_ips) if old_ips == network_ips => return,
                     Some(old_ips) => {
                         for ip in old_ips.ips() {
                             for name in &*state.names {
-                                self.authority_wrapper.remove_address(name, ip).await;
+                                change_set.remove(name, ip);
                             }
                         }
                     },
@@ -452,9 +674,11 @@ impl Monitor {
 
                 for ip in network_ips.ips() {
                     for name in &*state.names {
-                        self.authority_wrapper.add(name, ip).await;
+                        change_set.add(name, ip);
                     }
                 }
+
+                self.authority_wrapper.apply(change_set).await;
             },
             Err(error) => {
                 event!(
@@ -468,27 +692,18 @@ impl Monitor {
     }
 
     async fn handle_network_disconnect(&self, event: Event) {
-        let Some(container_id) = event.actor.attributes.get("container") else {
+        let Some((_, container_id, network_name)) = network_event_target(&event) else {
             event!(
                 Level::WARN,
                 ?event,
-                "Got network disconnect event, but event did not contain container id"
-            );
-            return;
-        };
-
-        let Some(network_name) = event.actor.attributes.get("name") else {
-            event!(
-                Level::WARN,
-                ?event,
-                "Got network disconnect event, but event did not contain network name"
+                "Got network disconnect event, but event did not contain container id and network name"
             );
             return;
         };
 
         let mut containers = self.containers.lock().await;
 
-        let Some(state) = containers.get_mut(&**container_id) else {
+        let Some(state) = containers.get_mut(container_id) else {
             event!(
                 Level::WARN,
                 %container_id,
@@ -498,7 +713,7 @@ impl Monitor {
             return;
         };
 
-        let Some(network_ips) = state.networks.remove(&**network_name) else {
+        let Some(network_ips) = state.networks.remove(network_name) else {
             event!(
                 Level::WARN,
                 %container_id,
@@ -508,22 +723,32 @@ impl Monitor {
             return;
         };
 
+        let mut change_set = self.change_set(container_id, "network:disconnect");
+
         for ip in network_ips.ips() {
             for name in &*state.names {
-                self.authority_wrapper.remove_address(name, ip).await;
+                change_set.remove(name, ip);
             }
         }
+
+        self.authority_wrapper.apply(change_set).await;
     }
 
-    async fn register_network(&self, network_id: &str) {
-        let inspect = match self.docker.inspect_network(network_id).await {
+    /// `network` is the network's ID, or its name, Docker takes either.
+    async fn register_network(&self, docker: &Client, network: &str) {
+        let inspect = match timed_inspect("network", network, docker.inspect_network(network)).await
+        {
             Ok(n) => n,
             Err(error) => {
-                event!(Level::WARN, ?error, %network_id, "Failed to inspect network");
+                event!(Level::WARN, ?error, %network, "Failed to inspect network");
                 return;
             },
         };
 
+        if self.networks.lock().await.contains_key(&inspect.id) {
+            return;
+        }
+
         let mut registered = Vec::new();
 
         for config in &inspect.ipam.config {
@@ -531,33 +756,17 @@ impl Monitor {
                 continue;
             };
 
-            for (ip_network, zone_name) in parse_subnet(subnet) {
-                let authority = match set_up_authority(zone_name.clone()).await {
-                    Ok(a) => Arc::new(a),
-                    Err(error) => {
-                        event!(Level::WARN, ?error, %zone_name, "Failed to create reverse zone authority");
-                        continue;
-                    },
-                };
-
+            for (ip_network, zone_name) in parse_subnet(subnet, self.rfc2317) {
                 self.authority_wrapper
-                    .add_reverse_zone(ip_network, Arc::clone(&authority))
+                    .add_reverse_zone(ip_network, zone_name.clone())
                     .await;
 
-                self.catalog
-                    .write()
-                    .await
-                    .upsert(LowerName::new(&zone_name), vec![authority]);
-
                 event!(Level::INFO, %zone_name, "Registered reverse zone");
                 registered.push(ip_network);
             }
         }
 
-        self.networks
-            .lock()
-            .await
-            .insert(network_id.into(), registered);
+        self.networks.lock().await.insert(inspect.id, registered);
     }
 
     async fn deregister_network(&self, network_id: &str) {
@@ -572,22 +781,17 @@ impl Monitor {
                 .await;
 
             if let Some(zone_name) = zone_name(ip_network) {
-                self.catalog
-                    .write()
-                    .await
-                    .remove(&LowerName::new(&zone_name));
-
                 event!(Level::INFO, %zone_name, "Deregistered reverse zone");
             }
         }
     }
 
-    async fn handle_network_create(&self, event: Event) {
+    async fn handle_network_create(&self, docker: &Client, event: Event) {
         if self.networks.lock().await.contains_key(&event.actor.id) {
             return;
         }
 
-        self.register_network(&event.actor.id).await;
+        self.register_network(docker, &event.actor.id).await;
     }
 
     async fn handle_network_destroy(&self, event: Event) {
@@ -596,6 +800,7 @@ impl Monitor {
 
     pub async fn consume_events(
         &self,
+        docker: &Client,
         mut receiver: Receiver<Event>,
         cancellation_token: &CancellationToken,
     ) {
@@ -617,71 +822,462 @@ impl Monitor {
                 }
             };
 
-            match event.r#type {
-                EventType::Container => match &*event.action {
-                    "start" => self.handle_container_start(event).await,
+            self.handle_event(docker, event).await;
+        }
+    }
+
+    #[instrument(skip_all, fields(r#type = ?event.r#type, action = %event.action, actor_id = %event.actor.id))]
+    async fn handle_event(&self, docker: &Client, event: Event) {
+        match event.r#type {
+            EventType::Container => {
+                // on this node, a task's container is the only sign of the task
+                let task_event = event
+                    .actor
+                    .attributes
+                    .contains_key(SWARM_TASK_LABEL)
+                    .then(|| format!("task:{}", event.action));
+
+                match &*event.action {
+                    "start" => self.handle_container_start(docker, event).await,
                     "rename" => self.handle_container_rename(event).await,
-                    "die" => self.handle_container_die(event).await,
+                    // Podman says "died"
+                    "die" | "died" => self.handle_container_die(event).await,
                     rest => {
                         event!(Level::TRACE, r#type = ?event.r#type, event = rest, "ignoring event");
+                        return;
                     },
+                }
+
+                if let Some(task_event) = task_event {
+                    self.reconcile_swarm(docker, &task_event).await;
+                }
+            },
+            EventType::Network => match &*event.action {
+                "connect" => self.handle_network_connect(docker, event).await,
+                "disconnect" => self.handle_network_disconnect(event).await,
+                "create" => self.handle_network_create(docker, event).await,
+                "destroy" => self.handle_network_destroy(event).await,
+                rest => {
+                    event!(Level::TRACE, r#type = ?event.r#type, event = rest, "ignoring event");
                 },
-                EventType::Network => match &*event.action {
-                    "connect" => self.handle_network_connect(event).await,
-                    "disconnect" => self.handle_network_disconnect(event).await,
-                    "create" => self.handle_network_create(event).await,
-                    "destroy" => self.handle_network_destroy(event).await,
-                    rest => {
-                        event!(Level::TRACE, r#type = ?event.r#type, event = rest, "ignoring event");
-                    },
-                },
-                EventType::Builder
-                | EventType::Config
-                | EventType::Daemon
-                | EventType::Image
-                | EventType::Node
-                | EventType::Plugin
-                | EventType::Secret
-                | EventType::Service
-                | EventType::Volume => {
-                    event!(Level::TRACE, ?event, "Ignoring event");
-                },
+            },
+            EventType::Service => {
+                self.reconcile_swarm(docker, &format!("service:{}", event.action))
+                    .await;
+            },
+            // a node that's gone takes its tasks along
+            EventType::Node => {
+                self.reconcile_swarm(docker, &format!("node:{}", event.action))
+                    .await;
+            },
+            EventType::Builder
+            | EventType::Config
+            | EventType::Daemon
+            | EventType::Image
+            | EventType::Plugin
+            | EventType::Secret
+            | EventType::Volume => {
+                event!(Level::TRACE, ?event, "Ignoring event");
+            },
+        }
+    }
+
+    /// Serves what the daemon looked like when its summaries were taken, until `start` catches up.
+    pub async fn restore(&self, networks: Vec<NetworkSummary>, containers: Vec<ContainerSummary>) {
+        for network in networks {
+            let mut registered = Vec::with_capacity(network.reverse_zones.len());
+
+            for reverse_zone in network.reverse_zones {
+                let Some(origin) = zone_name(reverse_zone.network) else {
+                    continue;
+                };
+
+                self.authority_wrapper
+                    .add_reverse_zone(reverse_zone.network, origin)
+                    .await;
+
+                registered.push(reverse_zone.network);
             }
+
+            self.networks
+                .lock()
+                .await
+                .insert(network.id.into_boxed_str(), registered);
+        }
+
+        for container in containers {
+            let names = container
+                .names
+                .iter()
+                .filter_map(|name| name.parse::<Name>().ok())
+                .collect::<Arc<[Name]>>();
+
+            let networks = container
+                .networks
+                .into_iter()
+                .filter_map(|(network_name, addresses)| {
+                    Some((
+                        network_name.into_boxed_str(),
+                        NetworkIps::from_ips(&addresses)?,
+                    ))
+                })
+                .collect();
+
+            self.replace_container("restore", &container.id, ContainerState { names, networks })
+                .await;
+        }
+
+        event!(Level::INFO, daemon = %self.daemon, "Restored state snapshot");
+    }
+
+    /// Replaces what we know about the container, only the records that differ are changed.
+    async fn replace_container(&self, event: &str, container_id: &str, state: ContainerState) {
+        let mut containers = self.containers.lock().await;
+
+        let before = containers
+            .get(container_id)
+            .map(ContainerState::records)
+            .unwrap_or_default();
+        let after = state.records();
+
+        let mut change_set = self.change_set(container_id, event);
+
+        for &(ref name, ip) in before.difference(&after) {
+            change_set.remove(name, ip);
+        }
+
+        for &(ref name, ip) in after.difference(&before) {
+            change_set.add(name, ip);
+        }
+
+        containers.insert(Box::from(container_id), state);
+
+        self.authority_wrapper.apply(change_set).await;
+    }
+
+    /// Lists Swarm's services and tasks, and replaces their records with what's there now.
+    ///
+    /// Swarm doesn't send events for tasks on other nodes, so besides following the events, this
+    /// is done every now and then.
+    pub async fn reconcile_swarm(&self, docker: &Client, event: &str) {
+        // held while listing, so what an earlier listing found never replaces a later one's
+        let mut swarm = self.swarm.lock().await;
+
+        let Some(current) = swarm.as_mut() else {
+            return;
+        };
+
+        let listed = async {
+            Ok::<_, ApiEndpointCallError<_>>((
+                docker.call::<ListServices>(&()).await?,
+                docker.call::<ListTasks>(&()).await?,
+            ))
+        }
+        .await;
+
+        match listed {
+            Ok((services, tasks)) => {
+                self.apply_swarm(current, event, swarm_objects(&services, &tasks))
+                    .await;
+            },
+            // we're not talking to a Swarm manager (anymore), asking again won't change that
+            Err(error) if is_not_a_swarm_manager(&error) => {
+                event!(Level::INFO, daemon = %self.daemon, ?error, "Not a Swarm manager, ignoring Swarm until reconnected");
+
+                self.apply_swarm(current, event, Vec::new()).await;
+                *swarm = None;
+            },
+            // e.g. a leader election, what we have is still the best answer
+            Err(error) => {
+                event!(Level::WARN, daemon = %self.daemon, ?error, "Failed to list Swarm services and tasks, keeping their records");
+            },
+        }
+    }
+
+    /// Replaces the Swarm records with `objects`, unless Swarm is being ignored.
+    async fn replace_swarm(&self, event: &str, objects: Vec<SwarmObject>) {
+        if let Some(current) = self.swarm.lock().await.as_mut() {
+            self.apply_swarm(current, event, objects).await;
         }
     }
 
-    pub async fn start(&self) -> Result<(), eyre::Report> {
+    async fn apply_swarm(
+        &self,
+        current: &mut HashMap<Box<str>, ContainerState>,
+        event: &str,
+        objects: Vec<SwarmObject>,
+    ) {
+        let objects = objects
+            .into_iter()
+            .map(|object| {
+                let state = ContainerState {
+                    names: to_full_names(vec![object.name], &self.domain),
+                    networks: object
+                        .networks
+                        .into_iter()
+                        .filter_map(|(network_id, ips)| {
+                            Some((network_id, NetworkIps::from_ips(&ips)?))
+                        })
+                        .collect(),
+                };
+
+                (object.id, state)
+            })
+            .collect::<HashMap<_, _>>();
+
+        let before = current
+            .values()
+            .flat_map(ContainerState::records)
+            .collect::<HashSet<_>>();
+        let after = objects
+            .values()
+            .flat_map(ContainerState::records)
+            .collect::<HashSet<_>>();
+
+        // all at once, a service's new address is served the moment its old one isn't
+        let mut change_set = self.change_set("swarm", event);
+
+        for &(ref name, ip) in before.difference(&after) {
+            change_set.remove(name, ip);
+        }
+
+        for &(ref name, ip) in after.difference(&before) {
+            change_set.add(name, ip);
+        }
+
+        *current = objects;
+
+        self.authority_wrapper.apply(change_set).await;
+    }
+
+    /// Withdraws all of the daemon's records and reverse zones, other daemons keep theirs. The next
+    /// `start` registers what's there again.
+    pub async fn withdraw(&self, event: &str) {
+        let container_ids = self
+            .containers
+            .lock()
+            .await
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for container_id in container_ids {
+            self.remove_container(&container_id, event).await;
+        }
+
+        self.replace_swarm(event, Vec::new()).await;
+
+        let network_ids = self
+            .networks
+            .lock()
+            .await
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for network_id in network_ids {
+            self.deregister_network(&network_id).await;
+        }
+    }
+
+    /// Registers what Docker has now. After a `restore`, also drops what's gone since, and
+    /// corrects what changed.
+    pub async fn start(&self, docker: &Client) -> Result<(), eyre::Report> {
         // Register reverse zones for all existing networks first,
         // so PTR records are in place before containers are processed.
-        let networks = self.docker.list_networks().await?;
-        for network in networks {
-            self.register_network(&network.id).await;
+        let networks = docker.list_networks().await?;
+
+        let removed_networks = {
+            let known = self.networks.lock().await;
+
+            known
+                .keys()
+                .filter(|&id| !networks.iter().any(|network| *network.id == **id))
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+
+        for network_id in removed_networks {
+            self.deregister_network(&network_id).await;
         }
 
-        for container in self.docker.list_containers(&Filters::default()).await? {
-            if &*container.state != "running" {
+        for network in networks {
+            // a network's subnets never change, a restored one is up to date
+            if self.networks.lock().await.contains_key(&*network.id) {
                 continue;
             }
 
-            let full_names = to_full_names(Vec::from(container.names), &self.domain);
+            self.register_network(docker, &network.id).await;
+        }
+
+        let running = docker
+            .list_containers(&Filters::default())
+            .await?
+            .into_iter()
+            .filter(|container| &*container.state == "running")
+            .collect::<Vec<_>>();
 
-            self.register_container_networks(&container.id, full_names, container.network_settings)
+        let stopped_containers = {
+            let known = self.containers.lock().await;
+
+            known
+                .keys()
+                .filter(|&id| !running.iter().any(|container| *container.id == **id))
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+
+        for container_id in stopped_containers {
+            self.remove_container(&container_id, "startup").await;
+        }
+
+        for container in running {
+            let names = to_full_names(
+                append_compose_names(Vec::from(container.names), &container.labels),
+                &self.domain,
+            );
+
+            let networks = container
+                .network_settings
+                .networks
+                .into_iter()
+                .filter_map(|(network_name, network)| {
+                    Some((network_name, NetworkIps::from_network(&network)?))
+                })
+                .collect();
+
+            self.replace_container("startup", &container.id, ContainerState { names, networks })
                 .await;
         }
 
+        // a new connection may be to a daemon that joined a Swarm since
+        self.swarm.lock().await.get_or_insert_default();
+
+        self.reconcile_swarm(docker, "startup").await;
+
         Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::BTreeMap;
+    use std::convert::Infallible;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+    use std::path::Path;
     use std::str::FromStr as _;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
 
+    use hashbrown::HashMap;
     use hickory_server::proto::rr::Name;
+    use http_body_util::Full;
+    use hyper::body::{Bytes, Incoming};
+    use hyper::server::conn::http1;
+    use hyper::service::service_fn;
+    use hyper::{Response, StatusCode};
+    use hyper_util::rt::TokioIo;
     use ipnet::IpNet;
     use pretty_assertions::assert_eq;
+    use serde_json::Value as JsonValue;
+    use tokio::net::UnixListener;
+    use tokio::sync::mpsc::channel;
+    use tokio_util::sync::CancellationToken;
+    use twistlock::client::Client;
+    use twistlock::config::Endpoint;
+    use twistlock::models::container_inspect::{ContainerNetwork, ContainerNetworkSettings};
+    use twistlock::models::events::Event;
+
+    use crate::docker::daemons::Daemons;
+    use crate::docker::monitor::{
+        ContainerSummary, Engine, append_compose_names, get_all_names_from_event,
+        network_event_target, parse_subnet,
+    };
+    use crate::docker::swarm::SwarmObject;
+    use crate::table::AuthorityWrapper;
+
+    /// Responses recorded from Podman's Docker-compatible API.
+    const PODMAN_NETWORKS: &str = include_str!("../../tests/fixtures/podman/networks.json");
+    /// Created after the others, and only ever connected to, Podman doesn't announce it.
+    const PODMAN_BACKEND: &str = include_str!("../../tests/fixtures/podman/backend.json");
+    const PODMAN_CONTAINERS: &str = include_str!("../../tests/fixtures/podman/containers.json");
+    const PODMAN_INSPECT: &str = include_str!("../../tests/fixtures/podman/inspect.json");
+    const PODMAN_EVENTS: &str = include_str!("../../tests/fixtures/podman/events.jsonl");
+
+    fn podman_response(path: &str) -> Option<String> {
+        let find = |objects: JsonValue, key: &str, value: &str| {
+            objects
+                .as_array()?
+                .iter()
+                .find(|object| object[key] == value)
+                .map(ToString::to_string)
+        };
+
+        let networks = || {
+            let mut networks = serde_json::from_str::<JsonValue>(PODMAN_NETWORKS).unwrap();
+            networks
+                .as_array_mut()
+                .unwrap()
+                .push(serde_json::from_str(PODMAN_BACKEND).unwrap());
+
+            networks
+        };
+
+        match *path.split('/').skip(1).collect::<Vec<_>>() {
+            ["networks"] => Some(PODMAN_NETWORKS.to_owned()),
+            ["networks", network] => {
+                find(networks(), "Id", network).or_else(|| find(networks(), "Name", network))
+            },
+            ["containers", "json"] => Some(PODMAN_CONTAINERS.to_owned()),
+            ["containers", id, "json"] => {
+                find(serde_json::from_str(PODMAN_INSPECT).unwrap(), "Id", id)
+            },
+            ["events"] => Some(PODMAN_EVENTS.to_owned()),
+            _ => None,
+        }
+    }
+
+    /// Stands in for a daemon on a socket of its own, `response` answers a path or it's a 404 with
+    /// `not_found` as the body.
+    fn start_daemon(socket: &Path, response: fn(&str) -> Option<String>, not_found: &'static str) {
+        let listener = UnixListener::bind(socket).unwrap();
 
-    use crate::docker::monitor::parse_subnet;
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+
+                let service = service_fn(move |request: hyper::Request<Incoming>| async move {
+                    let Some(body) = response(request.uri().path()) else {
+                        let mut response = Response::new(Full::new(Bytes::from(not_found)));
+                        *response.status_mut() = StatusCode::NOT_FOUND;
+
+                        return Ok::<_, Infallible>(response);
+                    };
+
+                    Ok(Response::new(Full::new(Bytes::from(body))))
+                });
+
+                tokio::spawn(http1::Builder::new().serve_connection(TokioIo::new(stream), service));
+            }
+        });
+    }
+
+    /// Stands in for Podman, on a socket of its own.
+    fn start_podman(socket: &Path) {
+        start_daemon(
+            socket,
+            podman_response,
+            r#"{"cause":"no such object","message":"no such object","response":404}"#,
+        );
+    }
+
+    fn podman_events() -> Vec<Event> {
+        PODMAN_EVENTS
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
 
     fn subnet(s: &str) -> IpNet {
         s.parse().unwrap()
@@ -689,7 +1285,7 @@ mod tests {
 
     #[test]
     fn parse_subnet_ipv4() {
-        let ranges = parse_subnet(subnet("172.16.66.123/18")).collect::<Vec<_>>();
+        let ranges = parse_subnet(subnet("172.16.66.123/18"), false).collect::<Vec<_>>();
 
         let expected = (64..=127)
             .map(|octet| {
@@ -706,7 +1302,7 @@ mod tests {
     #[test]
     fn parse_subnet_ipv6_aligned() {
         let input = subnet("fd00::/64");
-        let ranges = parse_subnet(input).collect::<Vec<_>>();
+        let ranges = parse_subnet(input, false).collect::<Vec<_>>();
 
         let expected = vec![(
             IpNet::from_str("fd00::/64").unwrap(),
@@ -719,7 +1315,7 @@ mod tests {
     #[test]
     fn parse_subnet_ipv6_non_aligned() {
         let input = subnet("fd00::/62");
-        let ranges = parse_subnet(input).collect::<Vec<_>>();
+        let ranges = parse_subnet(input, false).collect::<Vec<_>>();
 
         let expected = (0_u16..4)
             .map(|i| {
@@ -738,7 +1334,7 @@ mod tests {
     fn parse_subnet_ipv6_odd_nibble_count() {
         // /52 = 13 nibbles — odd count, so the last nibble lands on the high nibble of a byte
         let input = subnet("fd00::/52");
-        let ranges = parse_subnet(input).collect::<Vec<_>>();
+        let ranges = parse_subnet(input, false).collect::<Vec<_>>();
 
         let expected = vec![(
             IpNet::from_str("fd00::/52").unwrap(),
@@ -751,7 +1347,7 @@ mod tests {
     #[test]
     fn parse_subnet_ipv6_doc_prefix() {
         let input = subnet("2001:db8::/48");
-        let ranges = parse_subnet(input).collect::<Vec<_>>();
+        let ranges = parse_subnet(input, false).collect::<Vec<_>>();
 
         let expected = vec![(
             IpNet::from_str("2001:db8::/48").unwrap(),
@@ -760,4 +1356,489 @@ mod tests {
 
         assert_eq!(ranges, expected);
     }
+
+    #[test]
+    fn parse_subnet_ipv4_rfc2317() {
+        let ranges = parse_subnet(subnet("192.168.1.70/26"), true)
+            .map(|(network, name)| (network, name.to_string()))
+            .collect::<Vec<_>>();
+
+        // `/` isn't valid in host names, so it's escaped in presentation format
+        assert_eq!(
+            ranges,
+            vec![(
+                IpNet::from_str("192.168.1.64/26").unwrap(),
+                r"64\/26.1.168.192.in-addr.arpa.".to_owned(),
+            )]
+        );
+    }
+
+    #[test]
+    fn parse_subnet_ipv4_without_rfc2317() {
+        assert_eq!(parse_subnet(subnet("192.168.1.64/26"), false).count(), 64);
+    }
+
+    fn build_daemons(domain: &str, daemons: &[&str]) -> Daemons {
+        let domain: Name = domain.parse().unwrap();
+
+        Daemons::new(
+            AuthorityWrapper::new(domain.clone()),
+            domain,
+            false,
+            daemons.iter().map(|&daemon| Box::from(daemon)),
+        )
+    }
+
+    fn bridge(v4: Ipv4Addr, v6: Option<Ipv6Addr>) -> ContainerNetworkSettings {
+        ContainerNetworkSettings {
+            networks: HashMap::from([(
+                Box::from("bridge"),
+                ContainerNetwork {
+                    ip_address: Some(v4),
+                    global_ipv6_address: v6,
+                },
+            )]),
+        }
+    }
+
+    #[tokio::test]
+    async fn containers_are_summarized() {
+        let daemons = build_daemons("docker.", &["local"]);
+        let monitor = &daemons.monitors()[0];
+
+        let v4 = Ipv4Addr::new(172, 17, 0, 2);
+        let v6 = Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 2);
+
+        monitor
+            .register_container_networks(
+                "startup",
+                "abc123",
+                Arc::from(["web.docker.".parse::<Name>().unwrap()]),
+                bridge(v4, Some(v6)),
+            )
+            .await;
+
+        assert_eq!(
+            monitor.containers().await,
+            [ContainerSummary {
+                daemon: "local".to_owned(),
+                id: "abc123".to_owned(),
+                names: vec!["web.docker.".to_owned()],
+                networks: BTreeMap::from([(
+                    "bridge".to_owned(),
+                    vec![IpAddr::V4(v4), IpAddr::V6(v6)]
+                )]),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn daemons_only_withdraw_their_own_records() {
+        let daemons = build_daemons("docker.", &["build1", "build2"]);
+        let (build1, build2) = (&daemons.monitors()[0], &daemons.monitors()[1]);
+
+        let served = async || {
+            daemons
+                .authority_wrapper()
+                .forward_records()
+                .await
+                .into_iter()
+                .filter_map(|record| Some((record.name.to_string(), record.data.ip_addr()?)))
+                .collect::<Vec<_>>()
+        };
+
+        let web = Arc::<[Name]>::from(["web.docker.".parse::<Name>().unwrap()]);
+        let address = Ipv4Addr::new(172, 17, 0, 2);
+
+        // both hosts run a `web` on the same address of their own bridge
+        for monitor in [build1, build2] {
+            monitor
+                .register_container_networks(
+                    "startup",
+                    "abc123",
+                    Arc::clone(&web),
+                    bridge(address, None),
+                )
+                .await;
+        }
+
+        build1
+            .register_container_networks(
+                "startup",
+                "def456",
+                Arc::from(["db.docker.".parse::<Name>().unwrap()]),
+                bridge(Ipv4Addr::new(172, 17, 0, 3), None),
+            )
+            .await;
+
+        build1.remove_container("abc123", "container:die").await;
+        build1.remove_container("def456", "container:die").await;
+
+        assert_eq!(
+            served().await,
+            [("web.docker.".to_owned(), IpAddr::V4(address))]
+        );
+        assert_eq!(daemons.containers().await.len(), 1);
+
+        build2.remove_container("abc123", "container:die").await;
+
+        assert_eq!(served().await, []);
+    }
+
+    #[tokio::test]
+    async fn restored_snapshot_is_served() {
+        let daemons = build_daemons("docker.", &["local", "build1"]);
+
+        for monitor in daemons.monitors() {
+            daemons
+                .authority_wrapper()
+                .add_reverse_zone(
+                    IpNet::from_str("172.17.0.0/16").unwrap(),
+                    "17.172.in-addr.arpa.".parse().unwrap(),
+                )
+                .await;
+            monitor.networks.lock().await.insert(
+                Box::from("net1"),
+                vec![IpNet::from_str("172.17.0.0/16").unwrap()],
+            );
+
+            monitor
+                .register_container_networks(
+                    "startup",
+                    "abc123",
+                    Arc::from([format!("{}.docker.", monitor.daemon())
+                        .parse::<Name>()
+                        .unwrap()]),
+                    bridge(Ipv4Addr::new(172, 17, 0, 2), None),
+                )
+                .await;
+        }
+
+        let snapshot = daemons.snapshot().await;
+
+        let restored = build_daemons("docker.", &["local", "build1"]);
+        restored
+            .restore(serde_json::from_slice(&serde_json::to_vec(&snapshot).unwrap()).unwrap())
+            .await;
+
+        assert_eq!(restored.snapshot().await, snapshot);
+        assert_eq!(
+            restored.authority_wrapper().zone_files().await,
+            daemons.authority_wrapper().zone_files().await
+        );
+
+        // a daemon that's no longer configured leaves its records behind
+        let fewer = build_daemons("docker.", &["local"]);
+        fewer.restore(daemons.snapshot().await).await;
+
+        assert_eq!(
+            fewer
+                .containers()
+                .await
+                .into_iter()
+                .map(|container| container.daemon)
+                .collect::<Vec<_>>(),
+            ["local"]
+        );
+
+        // names and reverse zones depend on the domain, the snapshot is of no use to another one
+        let elsewhere = build_daemons("containers.", &["local", "build1"]);
+        elsewhere.restore(snapshot).await;
+
+        assert_eq!(elsewhere.containers().await, []);
+        assert_eq!(elsewhere.networks().await, []);
+    }
+
+    #[test]
+    fn podman_compose_names() {
+        let events = podman_events();
+
+        assert_eq!(
+            get_all_names_from_event(&events[0]),
+            [
+                Box::from("shop_cache_1"),
+                Box::from("1.cache.shop"),
+                Box::from("cache.shop")
+            ]
+        );
+    }
+
+    #[test]
+    fn docker_compose_labels_come_first() {
+        let labels = HashMap::from([
+            (Box::from("com.docker.compose.service"), Box::from("web")),
+            (Box::from("com.docker.compose.project"), Box::from("shop")),
+            (
+                Box::from("com.docker.compose.container-number"),
+                Box::from("2"),
+            ),
+            (Box::from("io.podman.compose.service"), Box::from("other")),
+            (Box::from("io.podman.compose.project"), Box::from("other")),
+        ]);
+
+        assert_eq!(
+            append_compose_names(vec![], &labels),
+            [Box::from("2.web.shop")]
+        );
+    }
+
+    #[test]
+    fn podman_network_events() {
+        let events = podman_events();
+
+        assert_eq!(
+            network_event_target(&events[1]),
+            Some((
+                Engine::Podman,
+                "3c0c6f6a2d1e4b7f9a8c5d3e2f1a0b9c8d7e6f5a4b3c2d1e0f9a8b7c6d5e4f3a",
+                "backend"
+            ))
+        );
+
+        let docker: Event = serde_json::from_str(
+            r#"{"Type":"network","Action":"connect","Actor":{"ID":"c41a7e0f9d2b","Attributes":{"container":"3c0c6f6a2d1e","name":"backend","type":"bridge"}},"scope":"local","time":1792316004,"timeNano":1792316004077193120}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            network_event_target(&docker),
+            Some((Engine::Docker, "3c0c6f6a2d1e", "backend"))
+        );
+    }
+
+    #[tokio::test]
+    async fn podman_is_followed() {
+        let socket =
+            std::env::temp_dir().join(format!("docker-dns-rs-{}-podman.sock", std::process::id()));
+        let _unused = std::fs::remove_file(&socket);
+
+        start_podman(&socket);
+
+        let docker = Client::build(
+            Endpoint::Socket(socket.clone()),
+            None,
+            None,
+            None,
+            Duration::from_secs(5),
+        )
+        .unwrap();
+
+        let daemons = build_daemons("docker.", &["podman"]);
+        let monitor = &daemons.monitors()[0];
+
+        monitor.start(&docker).await.unwrap();
+
+        let (sender, receiver) = channel(16);
+        let cancellation_token = CancellationToken::new();
+
+        // the recorded events end, and so does the stream
+        let _report = docker
+            .produce_events(sender, &cancellation_token)
+            .await
+            .unwrap_err();
+        monitor
+            .consume_events(&docker, receiver, &cancellation_token)
+            .await;
+
+        std::fs::remove_file(&socket).unwrap();
+
+        // the cache came and went, the web server joined the backend network
+        assert_eq!(
+            monitor.containers().await,
+            [ContainerSummary {
+                daemon: "podman".to_owned(),
+                id: "3c0c6f6a2d1e4b7f9a8c5d3e2f1a0b9c8d7e6f5a4b3c2d1e0f9a8b7c6d5e4f3a".to_owned(),
+                names: vec![
+                    "shop_web_1.docker.".to_owned(),
+                    "1.web.shop.docker.".to_owned(),
+                    "web.shop.docker.".to_owned(),
+                ],
+                networks: BTreeMap::from([
+                    (
+                        "backend".to_owned(),
+                        vec![IpAddr::V4(Ipv4Addr::new(10, 90, 0, 2))]
+                    ),
+                    (
+                        "shop_default".to_owned(),
+                        vec![IpAddr::V4(Ipv4Addr::new(10, 89, 0, 2))]
+                    ),
+                ]),
+            }]
+        );
+
+        assert_eq!(
+            monitor
+                .networks()
+                .await
+                .into_iter()
+                .map(|network| {
+                    (
+                        network.id,
+                        network
+                            .reverse_zones
+                            .into_iter()
+                            .filter_map(|reverse_zone| reverse_zone.zone)
+                            .collect::<Vec<_>>(),
+                    )
+                })
+                .collect::<Vec<_>>(),
+            [
+                (
+                    "2f259bab93aaaaa2542ba43ef33eb990d0999ee1b9924b557b7be53c0b7a1bb9".to_owned(),
+                    vec!["88.10.in-addr.arpa.".to_owned()]
+                ),
+                (
+                    "8d5e2b4bcb3e49c3e1bd9d9a9bb1a4e8c6a0b3a4c1b0a2d5f1f3e7c9b2a4d6e8".to_owned(),
+                    vec!["0.89.10.in-addr.arpa.".to_owned()]
+                ),
+                (
+                    "c41a7e0f9d2b6c8a3e5f1d4b7a9c2e6f0b3d8a1c5e7f9b2d4a6c8e0f1b3d5a7c".to_owned(),
+                    vec!["0.90.10.in-addr.arpa.".to_owned()]
+                ),
+            ]
+        );
+
+        let mut served = daemons
+            .authority_wrapper()
+            .forward_records()
+            .await
+            .into_iter()
+            .filter_map(|record| Some((record.name.to_string(), record.data.ip_addr()?)))
+            .collect::<Vec<_>>();
+        served.sort_unstable();
+
+        assert_eq!(
+            served
+                .iter()
+                .filter(|&&(_, ip)| ip == IpAddr::V4(Ipv4Addr::new(10, 90, 0, 2)))
+                .map(|&(ref name, _)| &**name)
+                .collect::<Vec<_>>(),
+            [
+                "1.web.shop.docker.",
+                "shop_web_1.docker.",
+                "web.shop.docker."
+            ]
+        );
+        assert!(!served.iter().any(|&(ref name, _)| name.contains("cache")));
+    }
+
+    #[tokio::test]
+    async fn swarm_records_follow_the_services() {
+        let daemons = build_daemons("docker.", &["manager"]);
+        let monitor = &daemons.monitors()[0];
+
+        let served = async || {
+            let mut served = daemons
+                .authority_wrapper()
+                .forward_records()
+                .await
+                .into_iter()
+                .filter_map(|record| Some((record.name.to_string(), record.data.ip_addr()?)))
+                .collect::<Vec<_>>();
+            served.sort_unstable();
+
+            served
+        };
+
+        let object = |id: &str, name: &str, address: [u8; 4]| SwarmObject {
+            id: Box::from(id),
+            name: Box::from(name),
+            networks: vec![(Box::from("y2mg8vb4ld4t"), vec![IpAddr::from(address)])],
+        };
+
+        monitor
+            .replace_swarm(
+                "startup",
+                vec![
+                    object("9mnpnzenvg8p", "web", [10, 0, 1, 2]),
+                    object("0kzzo1i0y4jz", "tasks.web", [10, 0, 1, 3]),
+                    object("1yljwbmlr8er", "tasks.web", [10, 0, 1, 4]),
+                ],
+            )
+            .await;
+
+        assert_eq!(
+            served().await,
+            [
+                ("tasks.web.docker.".to_owned(), IpAddr::from([10, 0, 1, 3])),
+                ("tasks.web.docker.".to_owned(), IpAddr::from([10, 0, 1, 4])),
+                ("web.docker.".to_owned(), IpAddr::from([10, 0, 1, 2])),
+            ]
+        );
+
+        // scaled down to one task
+        monitor
+            .replace_swarm(
+                "service:update",
+                vec![
+                    object("9mnpnzenvg8p", "web", [10, 0, 1, 2]),
+                    object("0kzzo1i0y4jz", "tasks.web", [10, 0, 1, 3]),
+                ],
+            )
+            .await;
+
+        assert_eq!(
+            served().await,
+            [
+                ("tasks.web.docker.".to_owned(), IpAddr::from([10, 0, 1, 3])),
+                ("web.docker.".to_owned(), IpAddr::from([10, 0, 1, 2])),
+            ]
+        );
+
+        // no longer a manager
+        monitor.replace_swarm("reconcile", vec![]).await;
+
+        assert_eq!(served().await, []);
+        assert_eq!(monitor.containers().await, []);
+    }
+
+    #[tokio::test]
+    async fn swarm_is_ignored_until_reconnected() {
+        static SERVICES_LISTED: AtomicUsize = AtomicUsize::new(0);
+
+        // a daemon without the Swarm API, nothing else is running
+        fn response(path: &str) -> Option<String> {
+            match path {
+                "/networks" | "/containers/json" => Some("[]".to_owned()),
+                "/services" => {
+                    SERVICES_LISTED.fetch_add(1, Ordering::Relaxed);
+                    None
+                },
+                _ => None,
+            }
+        }
+
+        let socket = std::env::temp_dir().join(format!(
+            "docker-dns-rs-{}-no-swarm.sock",
+            std::process::id()
+        ));
+        let _unused = std::fs::remove_file(&socket);
+
+        start_daemon(&socket, response, "404 page not found");
+
+        let docker = Client::build(
+            Endpoint::Socket(socket.clone()),
+            None,
+            None,
+            None,
+            Duration::from_secs(5),
+        )
+        .unwrap();
+
+        let daemons = build_daemons("docker.", &["podman"]);
+        let monitor = &daemons.monitors()[0];
+
+        monitor.start(&docker).await.unwrap();
+        monitor.reconcile_swarm(&docker, "reconcile").await;
+        monitor.reconcile_swarm(&docker, "reconcile").await;
+
+        assert_eq!(SERVICES_LISTED.load(Ordering::Relaxed), 1);
+
+        // a new connection asks again
+        monitor.start(&docker).await.unwrap();
+
+        std::fs::remove_file(&socket).unwrap();
+
+        assert_eq!(SERVICES_LISTED.load(Ordering::Relaxed), 2);
+    }
 }
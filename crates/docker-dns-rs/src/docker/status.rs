@@ -0,0 +1,44 @@
+use color_eyre::eyre;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+
+fn now() -> String {
+    OffsetDateTime::now_utc()
+        .format(&Rfc3339)
+        .expect("Formatting the current time never fails")
+}
+
+/// How the connection to Docker is doing. While degraded, the records are what we knew last, until
+/// the disconnect grace period is over and they're withdrawn.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "lowercase")]
+pub enum DockerStatus {
+    /// Not connected since startup yet.
+    Connecting,
+    /// `since` is RFC 3339, in UTC.
+    Connected { since: String },
+    /// The connection failed or was lost, and is retried in the background.
+    Degraded { error: String, since: String },
+}
+
+impl DockerStatus {
+    pub fn connected() -> Self {
+        Self::Connected { since: now() }
+    }
+
+    pub fn degraded(error: &eyre::Report) -> Self {
+        Self::Degraded {
+            error: error.to_string(),
+            since: now(),
+        }
+    }
+}
+
+/// A daemon's status, as the admin API shows it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DaemonStatus {
+    pub daemon: String,
+    #[serde(flatten)]
+    pub status: DockerStatus,
+}
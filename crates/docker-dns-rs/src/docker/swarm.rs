@@ -0,0 +1,288 @@
+use std::net::IpAddr;
+
+use hashbrown::HashSet;
+use hyper::{Method, StatusCode};
+use ipnet::IpNet;
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
+use twistlock::endpoint::{ApiEndpoint, ApiEndpointCallError};
+
+/// Only answered by a Swarm manager.
+pub struct ListServices;
+
+impl ApiEndpoint for ListServices {
+    type Request = ();
+    type Response = Vec<Service>;
+    type Error = JsonValue;
+
+    const METHOD: Method = Method::GET;
+
+    fn path_and_query(_request: &Self::Request) -> Result<String, std::io::Error> {
+        Ok("/services".to_owned())
+    }
+}
+
+/// Only answered by a Swarm manager.
+pub struct ListTasks;
+
+impl ApiEndpoint for ListTasks {
+    type Request = ();
+    type Response = Vec<Task>;
+    type Error = JsonValue;
+
+    const METHOD: Method = Method::GET;
+
+    fn path_and_query(_request: &Self::Request) -> Result<String, std::io::Error> {
+        // `{"desired-state":["running"]}`
+        Ok("/tasks?filters=%7B%22desired-state%22%3A%5B%22running%22%5D%7D".to_owned())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Service {
+    #[serde(rename = "ID")]
+    pub id: Box<str>,
+    pub spec: ServiceSpec,
+    #[serde(default)]
+    pub endpoint: ServiceEndpoint,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ServiceSpec {
+    pub name: Box<str>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ServiceEndpoint {
+    /// Absent for services in `dnsrr` mode, and services without networks.
+    #[serde(default, rename = "VirtualIPs")]
+    pub virtual_ips: Vec<VirtualIp>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct VirtualIp {
+    #[serde(rename = "NetworkID")]
+    pub network_id: Box<str>,
+    #[serde(default)]
+    pub addr: Option<IpNet>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Task {
+    #[serde(rename = "ID")]
+    pub id: Box<str>,
+    #[serde(rename = "ServiceID")]
+    pub service_id: Box<str>,
+    pub status: TaskStatus,
+    #[serde(default)]
+    pub networks_attachments: Vec<NetworkAttachment>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct TaskStatus {
+    pub state: Box<str>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct NetworkAttachment {
+    pub network: AttachedNetwork,
+    #[serde(default)]
+    pub addresses: Vec<IpNet>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct AttachedNetwork {
+    #[serde(rename = "ID")]
+    pub id: Box<str>,
+    #[serde(default)]
+    pub spec: AttachedNetworkSpec,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct AttachedNetworkSpec {
+    #[serde(default)]
+    pub ingress: bool,
+}
+
+/// Whether Docker answered that it isn't a Swarm manager, with a 503, or the API has no Swarm at
+/// all, with a bare 404 or 503 (e.g. Podman). Any other error, e.g. during a leader election, says
+/// nothing about the services.
+pub fn is_not_a_swarm_manager(error: &ApiEndpointCallError<JsonValue>) -> bool {
+    match *error {
+        ApiEndpointCallError::Typed(ref body) | ApiEndpointCallError::Generic(ref body) => body
+            .get("message")
+            .and_then(JsonValue::as_str)
+            .is_some_and(|message| message.contains("not a swarm manager")),
+        ApiEndpointCallError::HttpError { status, .. } => {
+            status == StatusCode::NOT_FOUND || status == StatusCode::SERVICE_UNAVAILABLE
+        },
+        ApiEndpointCallError::Transport(_) => false,
+    }
+}
+
+/// A service or a task, with the name it's resolved by, relative to the domain.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SwarmObject {
+    pub id: Box<str>,
+    pub name: Box<str>,
+    /// Network ID to the object's addresses on it.
+    pub networks: Vec<(Box<str>, Vec<IpAddr>)>,
+}
+
+/// What Docker's internal DNS serves: `<service>` resolves to the service's virtual IPs,
+/// `tasks.<service>` to the addresses of its running tasks.
+///
+/// Addresses on the ingress network are left out, they're only for the routing mesh.
+pub fn swarm_objects(services: &[Service], tasks: &[Task]) -> Vec<SwarmObject> {
+    let ingress = tasks
+        .iter()
+        .flat_map(|task| &task.networks_attachments)
+        .filter(|attachment| attachment.network.spec.ingress)
+        .map(|attachment| &*attachment.network.id)
+        .collect::<HashSet<_>>();
+
+    let mut objects = services
+        .iter()
+        .map(|service| SwarmObject {
+            id: service.id.clone(),
+            name: service.spec.name.clone(),
+            networks: service
+                .endpoint
+                .virtual_ips
+                .iter()
+                .filter(|virtual_ip| !ingress.contains(&*virtual_ip.network_id))
+                .filter_map(|virtual_ip| {
+                    Some((virtual_ip.network_id.clone(), vec![virtual_ip.addr?.addr()]))
+                })
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+
+    for task in tasks {
+        if &*task.status.state != "running" {
+            continue;
+        }
+
+        let Some(service) = services
+            .iter()
+            .find(|service| service.id == task.service_id)
+        else {
+            continue;
+        };
+
+        objects.push(SwarmObject {
+            id: task.id.clone(),
+            name: format!("tasks.{}", service.spec.name).into_boxed_str(),
+            networks: task
+                .networks_attachments
+                .iter()
+                .filter(|attachment| !attachment.network.spec.ingress)
+                .map(|attachment| {
+                    (
+                        attachment.network.id.clone(),
+                        attachment.addresses.iter().map(IpNet::addr).collect(),
+                    )
+                })
+                .collect(),
+        });
+    }
+
+    objects
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use hyper::StatusCode;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use twistlock::endpoint::ApiEndpointCallError;
+
+    use crate::docker::swarm::{Service, SwarmObject, Task, is_not_a_swarm_manager, swarm_objects};
+
+    /// Responses recorded from a Swarm manager.
+    const SERVICES: &str = include_str!("../../tests/fixtures/swarm/services.json");
+    const TASKS: &str = include_str!("../../tests/fixtures/swarm/tasks.json");
+
+    fn v4(a: u8, b: u8, c: u8, d: u8) -> Vec<IpAddr> {
+        vec![IpAddr::V4(Ipv4Addr::new(a, b, c, d))]
+    }
+
+    #[test]
+    fn services_and_their_running_tasks() {
+        let services = serde_json::from_str::<Vec<Service>>(SERVICES).unwrap();
+        let tasks = serde_json::from_str::<Vec<Task>>(TASKS).unwrap();
+
+        let overlay = Box::<str>::from("y2mg8vb4ld4tqd6n0hl4ryg0x");
+
+        assert_eq!(
+            swarm_objects(&services, &tasks),
+            [
+                SwarmObject {
+                    id: Box::from("9mnpnzenvg8p8tdbtq4wvbkcz"),
+                    name: Box::from("web"),
+                    networks: vec![(overlay.clone(), v4(10, 0, 1, 2))],
+                },
+                SwarmObject {
+                    id: Box::from("kbk0ew9bhu8u3aowr5i3tgn8d"),
+                    name: Box::from("db"),
+                    networks: vec![],
+                },
+                SwarmObject {
+                    id: Box::from("0kzzo1i0y4jz6027t0k7aezc7"),
+                    name: Box::from("tasks.web"),
+                    networks: vec![(overlay.clone(), v4(10, 0, 1, 3))],
+                },
+                SwarmObject {
+                    id: Box::from("1yljwbmlr8er2waf8orvqpwms"),
+                    name: Box::from("tasks.web"),
+                    networks: vec![(overlay.clone(), v4(10, 0, 1, 4))],
+                },
+                SwarmObject {
+                    id: Box::from("38ciaotwjuritcdtn9npbnkuz"),
+                    name: Box::from("tasks.db"),
+                    networks: vec![(overlay, v4(10, 0, 1, 6))],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn only_a_worker_has_no_services() {
+        assert!(is_not_a_swarm_manager(&ApiEndpointCallError::Typed(
+            json!({
+                "message": "This node is not a swarm manager. Worker nodes can't be used to view or modify cluster state. Please run this command on a manager node or promote the current node to a manager."
+            })
+        )));
+        assert!(!is_not_a_swarm_manager(&ApiEndpointCallError::Typed(
+            json!({
+                "message": "rpc error: code = Unknown desc = The swarm does not have a leader. It's possible that too few managers are online. Make sure more than half of the managers are online."
+            })
+        )));
+    }
+
+    #[test]
+    fn no_swarm_api_is_no_swarm() {
+        for status in [StatusCode::NOT_FOUND, StatusCode::SERVICE_UNAVAILABLE] {
+            assert!(is_not_a_swarm_manager(&ApiEndpointCallError::HttpError {
+                status,
+                body: String::from("404 page not found"),
+            }));
+        }
+
+        assert!(!is_not_a_swarm_manager(&ApiEndpointCallError::HttpError {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            body: String::new(),
+        }));
+    }
+}
@@ -0,0 +1,213 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use color_eyre::eyre;
+use color_eyre::eyre::WrapErr as _;
+use hashbrown::HashSet;
+use hickory_server::proto::rr::{LowerName, Name, Record};
+use tokio_util::sync::CancellationToken;
+use tracing::{Level, event};
+
+use crate::config::RawRecord;
+use crate::docker::daemons::Daemons;
+use crate::utils::fs::write_atomically;
+
+/// Everything above this line belongs to the user, everything below it is ours.
+const MARKER: &str = "# Generated by docker-dns-rs, changes below this line are overwritten";
+
+/// Keeps a hosts file in sync with the records we serve.
+pub struct HostsFileWriter {
+    path: PathBuf,
+    records: Vec<RawRecord>,
+}
+
+impl HostsFileWriter {
+    pub fn new(path: PathBuf, records: Vec<RawRecord>) -> Self {
+        Self { path, records }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes the file now, and again after every change until we're cancelled.
+    pub async fn run(self, daemons: Arc<Daemons>, cancellation_token: CancellationToken) {
+        let authority_wrapper = daemons.authority_wrapper();
+        let mut published = authority_wrapper.subscribe();
+
+        loop {
+            // marked as seen before reading, so a change while we write causes another write
+            published.borrow_and_update();
+
+            let forward_records = authority_wrapper.forward_records().await;
+
+            if let Err(error) = self.write(&forward_records).await {
+                event!(Level::ERROR, ?error, path = %self.path.display(), "Failed to write hosts file");
+            }
+
+            tokio::select! {
+                biased;
+                () = cancellation_token.cancelled() => break,
+                result = published.changed() => if result.is_err() {
+                    break;
+                },
+            }
+        }
+    }
+
+    async fn write(&self, forward_records: &[Record]) -> Result<(), eyre::Report> {
+        let existing = match tokio::fs::read_to_string(&self.path).await {
+            Ok(existing) => Some(existing),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+            Err(error) => return Err(error).wrap_err("Failed to read the hosts file"),
+        };
+
+        let contents = render(
+            header(existing.as_deref().unwrap_or_default()),
+            &self.records,
+            forward_records,
+        );
+
+        if existing.as_deref() == Some(contents.as_str()) {
+            return Ok(());
+        }
+
+        write_atomically(&self.path, contents.as_bytes()).await?;
+
+        event!(Level::DEBUG, path = %self.path.display(), "Wrote hosts file");
+
+        Ok(())
+    }
+}
+
+/// What the user wrote above our marker. A file without one is kept as a whole.
+fn header(existing: &str) -> &str {
+    if existing.starts_with(&format!("{MARKER}\n")) {
+        return "";
+    }
+
+    existing
+        .split_once(&format!("\n{MARKER}\n"))
+        .map_or(existing, |(header, _)| header)
+}
+
+fn host_name(name: &Name) -> String {
+    name.to_string().trim_end_matches('.').to_owned()
+}
+
+/// One line per address. Like the DNS listener, a static record hides the zone's records
+/// for its name.
+fn render(header: &str, records: &[RawRecord], forward_records: &[Record]) -> String {
+    let static_names = records
+        .iter()
+        .map(|record| LowerName::new(&record.name))
+        .collect::<HashSet<_>>();
+
+    let mut hosts = BTreeMap::<IpAddr, Vec<String>>::new();
+
+    let entries = records
+        .iter()
+        .map(|record| (record.addr, &record.name))
+        .chain(forward_records.iter().filter_map(|record| {
+            let address = record.data.ip_addr()?;
+
+            (!static_names.contains(&LowerName::new(&record.name)))
+                .then_some((address, &record.name))
+        }));
+
+    for (address, name) in entries {
+        let names = hosts.entry(address).or_default();
+        let name = host_name(name);
+
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+
+    let mut contents = header.to_owned();
+
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+
+    contents.push_str(MARKER);
+    contents.push('\n');
+
+    for (address, names) in hosts {
+        writeln!(contents, "{address}\t{}", names.join(" "))
+            .expect("Writing to a String never fails");
+    }
+
+    contents
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use hickory_server::proto::rr::{RData, Record};
+    use pretty_assertions::assert_eq;
+
+    use crate::config::RawRecord;
+    use crate::hosts_file::{HostsFileWriter, MARKER, header, render};
+
+    fn record(name: &str, address: IpAddr) -> Record {
+        Record::from_rdata(name.parse().unwrap(), 5, RData::from(address))
+    }
+
+    #[test]
+    fn static_records_hide_the_zone() {
+        let records = [RawRecord {
+            name: "static.docker.".parse().unwrap(),
+            addr: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+        }];
+
+        let forward_records = [
+            record("web.docker.", IpAddr::V4(Ipv4Addr::new(172, 17, 0, 2))),
+            record("www.docker.", IpAddr::V4(Ipv4Addr::new(172, 17, 0, 2))),
+            record("static.docker.", IpAddr::V4(Ipv4Addr::new(172, 17, 0, 3))),
+        ];
+
+        assert_eq!(
+            render("", &records, &forward_records),
+            format!("{MARKER}\n10.0.0.1\tstatic.docker\n172.17.0.2\tweb.docker www.docker\n")
+        );
+    }
+
+    #[test]
+    fn header_is_what_precedes_the_marker() {
+        let existing = format!("127.0.0.1\tlocalhost\n{MARKER}\n172.17.0.2\tweb.docker\n");
+
+        assert_eq!(header(&existing), "127.0.0.1\tlocalhost");
+        assert_eq!(header("127.0.0.1\tlocalhost\n"), "127.0.0.1\tlocalhost\n");
+    }
+
+    #[tokio::test]
+    async fn header_survives_rewrites() {
+        let path = std::env::temp_dir().join(format!("docker-dns-rs-{}-hosts", std::process::id()));
+
+        tokio::fs::write(&path, "127.0.0.1\tlocalhost\n")
+            .await
+            .unwrap();
+
+        let writer = HostsFileWriter::new(path.clone(), vec![]);
+
+        writer
+            .write(&[record(
+                "web.docker.",
+                IpAddr::V4(Ipv4Addr::new(172, 17, 0, 2)),
+            )])
+            .await
+            .unwrap();
+        writer.write(&[]).await.unwrap();
+
+        let contents = tokio::fs::read_to_string(&path).await.unwrap();
+        tokio::fs::remove_file(&path).await.unwrap();
+
+        assert_eq!(contents, format!("127.0.0.1\tlocalhost\n{MARKER}\n"));
+    }
+}
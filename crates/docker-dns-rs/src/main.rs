@@ -1,43 +1,73 @@
 use std::convert::Infallible;
 use std::env::{self, VarError};
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::process::{ExitCode, Termination as _};
 use std::sync::Arc;
 use std::time::Duration;
 
+use clap::Parser as _;
 use color_eyre::config::HookBuilder;
 use color_eyre::eyre;
+use color_eyre::eyre::WrapErr as _;
 use dotenvy::dotenv;
-use hickory_server::zone_handler::Catalog;
+use hickory_server::proto::rr::Name;
+use rustls::ServerConfig;
 use tokio::net::{TcpListener, UdpSocket};
-use tokio::sync::RwLock;
-use tokio::sync::mpsc::Receiver;
+use tokio::runtime::Handle;
 use tokio::time::timeout;
 use tokio_util::sync::CancellationToken;
 use tokio_util::task::TaskTracker;
 use tracing::{Level, event};
 use tracing_subscriber::Layer as _;
 use tracing_subscriber::filter::EnvFilter;
+#[cfg(feature = "otlp")]
+use tracing_subscriber::filter::Targets;
 use tracing_subscriber::layer::SubscriberExt as _;
 use tracing_subscriber::util::SubscriberInitExt as _;
-use twistlock::client::Client as Daemon;
-use twistlock::models::events::Event;
+use twistlock::config::Endpoint;
 
+use crate::admin::{AdminListeners, AdminState, set_up_admin_server};
+use crate::audit::{AuditLog, AuditWriter};
 use crate::build_env::get_build_env;
-use crate::config::{AppConfig, RawRecord};
-use crate::dns_listener::{DnsRequestHandler, set_up_authority, set_up_catalog, set_up_dns_server};
-use crate::docker::monitor::Monitor;
+use crate::config::{
+    AppConfig, DnstapOutput, DockerConfig, ListenerConfig, LogFormat, RawConfig, RawRecord,
+};
+use crate::dns_listener::doh::{load_tls_config, set_up_doh_server};
+use crate::dns_listener::{DnsRequestHandler, set_up_dns_server};
+use crate::dnstap::{DnstapLogger, DnstapWriter};
+use crate::docker::connection::supervise;
+use crate::docker::daemons::Daemons;
+use crate::hosts_file::HostsFileWriter;
+#[cfg(feature = "otlp")]
+use crate::otlp::OtlpExporter;
+use crate::privileges::drop_privileges;
 use crate::shutdown::Shutdown;
+use crate::sockets::systemd::{DOH_FD_NAME, ListenFd, activated_sockets, take_listen_fds};
+use crate::sockets::{bind_tcp, bind_udp_reuse_port, bind_unix, needs_only_v6};
+use crate::state_file::StateFile;
 use crate::table::AuthorityWrapper;
 use crate::task_tracker_ext::TaskTrackerExt as _;
 use crate::utils::flatten_shutdown_handle;
+use crate::utils::fs::check_writable;
 use crate::utils::task::spawn_with_name;
 
+mod admin;
+mod audit;
 mod build_env;
 mod config;
+mod control;
 mod dns_listener;
+mod dnstap;
 mod docker;
+mod hosts_file;
+#[cfg(feature = "otlp")]
+mod otlp;
+mod privileges;
 mod shutdown;
 mod signal_handlers;
+mod sockets;
+mod state_file;
 mod table;
 mod task_tracker_ext;
 mod utils;
@@ -67,16 +97,60 @@ fn build_filter() -> (EnvFilter, Option<eyre::Report>) {
     (filter, parsing_error)
 }
 
-fn init_tracing(filter: EnvFilter) -> Result<(), eyre::Report> {
+/// Keeps the trace exporter alive, dropping it flushes what wasn't exported yet.
+struct TracingGuard {
+    #[cfg(feature = "otlp")]
+    _otlp_exporter: Option<OtlpExporter>,
+}
+
+#[cfg_attr(
+    not(feature = "otlp"),
+    expect(unused_variables, reason = "Only the exporter needs the runtime")
+)]
+fn init_tracing(
+    filter: EnvFilter,
+    raw_config: &RawConfig,
+    runtime: &Handle,
+) -> Result<TracingGuard, eyre::Report> {
     let registry = tracing_subscriber::registry();
 
     #[cfg(feature = "tokio-console")]
     let registry = registry.with(console_subscriber::ConsoleLayer::builder().spawn());
 
-    Ok(registry
-        .with(tracing_subscriber::fmt::layer().with_filter(filter))
+    #[cfg(feature = "otlp")]
+    let otlp_exporter = raw_config
+        .otlp_endpoint
+        .clone()
+        .map(|endpoint| OtlpExporter::new(endpoint, runtime.clone()))
+        .transpose()?;
+
+    // only our own spans, not those of the runtime or the libraries
+    #[cfg(feature = "otlp")]
+    let registry = registry.with(otlp_exporter.as_ref().map(|exporter| {
+        exporter
+            .layer()
+            .with_filter(Targets::new().with_target(env!("CARGO_CRATE_NAME"), Level::TRACE))
+    }));
+
+    let fmt_layer = match raw_config.log_format {
+        LogFormat::Text => tracing_subscriber::fmt::layer().boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .flatten_event(true)
+            .with_current_span(false)
+            .with_span_list(true)
+            .boxed(),
+    };
+
+    registry
+        .with(fmt_layer.with_filter(filter))
         .with(tracing_error::ErrorLayer::default())
-        .try_init()?)
+        .try_init()?;
+
+    Ok(TracingGuard {
+        #[cfg(feature = "otlp")]
+        _otlp_exporter: otlp_exporter,
+    })
 }
 
 fn main() -> ExitCode {
@@ -89,28 +163,51 @@ fn main() -> ExitCode {
         .install()
         .expect("Failed to install panic handler");
 
+    // parsed before tracing is set up, as it decides the log format
+    let mut raw_config = match RawConfig::try_parse() {
+        Ok(raw_config) => raw_config,
+        Err(error) => error.exit(),
+    };
+
+    // before the runtime starts its threads, the variables are unset
+    let listen_fds = match take_listen_fds() {
+        Ok(listen_fds) => listen_fds,
+        Err(error) => return Err::<Infallible, _>(error).report(),
+    };
+
+    // initialize the runtime, before tracing as exporting traces needs it
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_io()
+        .enable_time()
+        .build()
+        .expect("Failed building the Runtime");
+
+    // a subcommand asks a running instance, its output is the table or JSON, not logs
+    if let Some(command) = raw_config.command.take() {
+        return match runtime.block_on(control::run(command, raw_config.control_socket.as_deref())) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(error) => Err::<Infallible, _>(error).report(),
+        };
+    }
+
     let (env_filter, parsing_error) = build_filter();
 
-    init_tracing(env_filter).expect("Failed to set up tracing");
+    // declared after the runtime, so it's dropped, and flushes traces, while the runtime still runs
+    let _tracing_guard =
+        init_tracing(env_filter, &raw_config, runtime.handle()).expect("Failed to set up tracing");
 
     // bubble up the parsing error
     if let Err(error) = parsing_error.map_or(Ok(()), Err) {
         return Err::<Infallible, _>(error).report();
     }
 
-    // initialize the runtime
-    let shutdown: Shutdown = tokio::runtime::Builder::new_multi_thread()
-        .enable_io()
-        .enable_time()
-        .build()
-        .expect("Failed building the Runtime")
-        .block_on(async {
-            // explicitly launch everything in a spawned task
-            // see https://docs.rs/tokio/latest/tokio/attr.main.html#non-worker-async-function
-            let handle = spawn_with_name("main task runner", start_tasks());
+    let shutdown: Shutdown = runtime.block_on(async {
+        // explicitly launch everything in a spawned task
+        // see https://docs.rs/tokio/latest/tokio/attr.main.html#non-worker-async-function
+        let handle = spawn_with_name("main task runner", start_tasks(raw_config, listen_fds));
 
-            flatten_shutdown_handle(handle).await
-        });
+        flatten_shutdown_handle(handle).await
+    });
 
     shutdown.report()
 }
@@ -123,113 +220,156 @@ fn print_header() {
 
     event!(
         Level::INFO,
-        "{} v{} - built for {} ({})",
-        NAME,
-        VERSION,
-        build_env.get_target(),
-        build_env.get_target_cpu().unwrap_or("base cpu variant"),
+        name = NAME,
+        version = VERSION,
+        build_target = build_env.get_target(),
+        target_cpu = build_env.get_target_cpu().unwrap_or("base cpu variant"),
+        "Starting"
     );
 }
 
 // This function would be shorter if we had `FromResidual`
-async fn start_tasks() -> Shutdown {
+async fn start_tasks(raw_config: RawConfig, listen_fds: Vec<ListenFd>) -> Shutdown {
     print_header();
 
+    let app_config = match AppConfig::build(raw_config, listen_fds) {
+        Ok(app_config) => app_config,
+        Err(error) => return Shutdown::from(error),
+    };
+
     let AppConfig {
-        docker_config,
+        docker_configs,
         domain,
-        dns_bind,
+        mut listener_config,
         records,
-    } = match AppConfig::build() {
-        Ok(config) => config,
-        Err(error) => return Shutdown::from(error),
-    };
+        rfc2317,
+        audit_log,
+        hosts_file,
+        state_file,
+        user,
+        group,
+    } = app_config;
 
-    // DNS
-    let authority = match set_up_authority(domain.clone()).await {
-        Ok(authority) => authority,
-        Err(error) => return Shutdown::from(error),
+    let cancellation_token = CancellationToken::new();
+
+    let tasks = TaskTracker::new();
+
+    // dnstap, before the DNS handler so the first queries are logged
+    let dnstap = match listener_config.dnstap_output.take() {
+        Some(output) => match start_dnstap(&tasks, output, &cancellation_token).await {
+            Ok(logger) => Some(logger),
+            Err(error) => return Shutdown::from(error),
+        },
+        None => None,
     };
 
-    let forward_authority = Arc::new(authority);
-    let catalog = Arc::new(tokio::sync::RwLock::new(set_up_catalog(
+    // DNS
+    let authority_wrapper = match build_authority_wrapper(
+        &tasks,
         domain.clone(),
-        Arc::clone(&forward_authority),
-    )));
-    let authority_wrapper = AuthorityWrapper::new(Arc::clone(&forward_authority));
+        &records,
+        audit_log,
+        &cancellation_token,
+    )
+    .await
+    {
+        Ok(authority_wrapper) => authority_wrapper,
+        Err(error) => {
+            cancellation_token.cancel();
 
-    // docker
-    let daemon = match Daemon::build(
-        docker_config.docker_host,
-        docker_config.cacert,
-        docker_config.client_key,
-        docker_config.client_cert,
-        docker_config.timeout,
-    ) {
-        Ok(daemon) => daemon,
-        Err(error) => return Shutdown::from(error),
+            return Shutdown::from(error);
+        },
     };
+    let handler = DnsRequestHandler::new(authority_wrapper.catalog(), records.clone(), dnstap);
 
-    let docker = Arc::new(daemon);
+    // docker
+    let docker_sockets = docker_sockets(&docker_configs);
 
-    let docker_monitor = Monitor::new(
-        Arc::clone(&docker),
-        authority_wrapper,
-        Arc::clone(&catalog),
-        domain.clone(),
-    );
+    let daemons = build_daemons(authority_wrapper, domain.clone(), rfc2317, &docker_configs);
+    let state_file = state_file.map(StateFile::new);
 
-    let cancellation_token = CancellationToken::new();
+    // before the listeners, so the first queries are answered from it
+    if let Some(ref state_file) = state_file {
+        restore_state(state_file, &daemons).await;
+    }
 
-    let (sender, receiver) = tokio::sync::mpsc::channel(50);
+    let hosts_file_writer = hosts_file.map(|path| HostsFileWriter::new(path, records.clone()));
+    let admin_state = AdminState::new(
+        Arc::clone(&daemons),
+        records,
+        listener_config.admin_token.take(),
+    );
 
-    let tasks = TaskTracker::new();
+    // bound as root, handed to the user we run as below
+    let control_socket = listener_config.control_socket.clone();
 
-    // event handler
-    {
-        tasks.spawn_with_name(
-            "docker event monitor",
-            docker_event_monitor(docker_monitor, receiver, cancellation_token.clone()),
-        );
+    if let Err(error) = start_listeners(
+        &tasks,
+        listener_config,
+        handler,
+        admin_state,
+        &cancellation_token,
+    ) {
+        cancellation_token.cancel();
+
+        return Shutdown::from(error);
     }
 
-    // pump messages from Docker to the DockerMonitor
-    {
-        tasks.spawn_with_name(
-            "docker listener",
-            docker_listener(docker, sender, cancellation_token.clone()),
-        );
+    // everything that needs root (binding port 53, reading certificates) is done by now, files
+    // are written as the user we run as from then on, checked up front so a write doesn't fail later
+    let dropped = drop_privileges(
+        user.as_deref(),
+        group.as_deref(),
+        &docker_sockets,
+        control_socket.as_deref(),
+    )
+    .and_then(|()| {
+        start_file_writers(
+            &tasks,
+            hosts_file_writer,
+            state_file,
+            &daemons,
+            &cancellation_token,
+        )
+    });
+
+    if let Err(error) = dropped {
+        cancellation_token.cancel();
+
+        return Shutdown::from(error);
     }
 
-    {
-        let socket = match UdpSocket::bind(dns_bind).await {
-            Ok(socket) => socket,
-            Err(error) => return Shutdown::from(error),
-        };
+    // DNS is already served, every daemon is connected, and reconnected, in the background
+    start_docker_connections(&tasks, docker_configs, &daemons, &cancellation_token);
 
-        let listener = match TcpListener::bind(dns_bind).await {
-            Ok(listener) => listener,
-            Err(error) => return Shutdown::from(error),
-        };
+    let shutdown_reason = wait_for_shutdown(&cancellation_token).await;
 
-        tasks.spawn_with_name(
-            "dns handler",
-            dns_handler(
-                socket,
-                listener,
-                Arc::clone(&catalog),
-                records,
-                cancellation_token.clone(),
-            ),
-        );
+    // catch all cancel in case we got here via something else than a cancellation token
+    cancellation_token.cancel();
+
+    tasks.close();
+
+    // wait for the tasks that holds the server to exit gracefully
+    // this is easier to write than x separate timeoouts
+    // while we don't know if any of them gets killed
+    // this will do for now, and we can always trace back the logs
+    if timeout(Duration::from_secs(10), tasks.wait())
+        .await
+        .is_err()
+    {
+        event!(Level::ERROR, "Task didn't stop within allotted time!");
     }
 
+    shutdown_reason
+}
+
+async fn wait_for_shutdown(cancellation_token: &CancellationToken) -> Shutdown {
     // now we wait forever for either
     // * SIGTERM
     // * CTRL+c (SIGINT)
     // * cancellation of the shutdown token, triggered by another task when it
     //   completes unexpectedly (which means it failed)
-    let shutdown_reason = tokio::select! {
+    tokio::select! {
         biased;
         () = cancellation_token.cancelled() => {
             event!(Level::WARN, "Underlying task stopped, stopping all other tasks");
@@ -245,71 +385,327 @@ async fn start_tasks() -> Shutdown {
         result = signal_handlers::wait_for_sigint() => {
             result
         },
-    };
+    }
+}
 
-    // catch all cancel in case we got here via something else than a cancellation token
-    cancellation_token.cancel();
+async fn start_dnstap(
+    tasks: &TaskTracker,
+    output: DnstapOutput,
+    cancellation_token: &CancellationToken,
+) -> Result<DnstapLogger, eyre::Report> {
+    // opened now, while we still have our privileges
+    let writer = DnstapWriter::open(output).await?;
 
-    tasks.close();
+    let (logger, receiver) = DnstapLogger::new();
 
-    // wait for the tasks that holds the server to exit gracefully
-    // this is easier to write than x separate timeoouts
-    // while we don't know if any of them gets killed
-    // this will do for now, and we can always trace back the logs
-    if timeout(Duration::from_secs(10), tasks.wait())
-        .await
-        .is_err()
-    {
-        event!(Level::ERROR, "Task didn't stop within allotted time!");
+    tasks.spawn_with_name(
+        "dnstap writer",
+        writer.run(receiver, cancellation_token.clone()),
+    );
+
+    Ok(logger)
+}
+
+/// The zones we serve, starting with the static records' PTR records.
+async fn build_authority_wrapper(
+    tasks: &TaskTracker,
+    domain: Name,
+    records: &[RawRecord],
+    audit_log: Option<PathBuf>,
+    cancellation_token: &CancellationToken,
+) -> Result<AuthorityWrapper, eyre::Report> {
+    let mut authority_wrapper = AuthorityWrapper::new(domain);
+
+    if let Some(path) = audit_log {
+        // opened now, while we still have our privileges
+        let writer = AuditWriter::open(path).await?;
+
+        let (audit_log, receiver) = AuditLog::new();
+
+        tasks.spawn_with_name(
+            "audit log writer",
+            writer.run(receiver, cancellation_token.clone()),
+        );
+
+        authority_wrapper = authority_wrapper.with_audit_log(audit_log);
     }
 
-    shutdown_reason
+    authority_wrapper.add_static_records(records).await;
+
+    Ok(authority_wrapper)
+}
+
+/// One monitor per daemon, in the order they were configured.
+fn build_daemons(
+    authority_wrapper: AuthorityWrapper,
+    domain: Name,
+    rfc2317: bool,
+    docker_configs: &[DockerConfig],
+) -> Arc<Daemons> {
+    Arc::new(Daemons::new(
+        authority_wrapper,
+        domain,
+        rfc2317,
+        docker_configs
+            .iter()
+            .map(|docker_config| docker_config.daemon.clone()),
+    ))
+}
+
+/// The daemons we connect to over a Unix socket, we need their group after dropping privileges.
+fn docker_sockets(docker_configs: &[DockerConfig]) -> Vec<PathBuf> {
+    docker_configs
+        .iter()
+        .filter_map(|docker_config| match docker_config.docker_host {
+            Endpoint::Socket(ref path) => Some(path.clone()),
+            Endpoint::Direct(_) => None,
+        })
+        .collect()
+}
+
+/// `daemons` has a monitor for every config, in the same order.
+fn start_docker_connections(
+    tasks: &TaskTracker,
+    docker_configs: Vec<DockerConfig>,
+    daemons: &Daemons,
+    cancellation_token: &CancellationToken,
+) {
+    for (docker_config, monitor) in docker_configs.into_iter().zip(daemons.monitors()) {
+        tasks.spawn_with_name(
+            &format!("docker connection {}", docker_config.daemon),
+            supervise(
+                docker_config,
+                Arc::clone(monitor),
+                cancellation_token.clone(),
+            ),
+        );
+    }
+}
+
+async fn restore_state(state_file: &StateFile, daemons: &Daemons) {
+    match state_file.load().await {
+        Ok(Some(snapshot)) => daemons.restore(snapshot).await,
+        Ok(None) => {},
+        Err(error) => {
+            event!(
+                Level::WARN,
+                ?error,
+                "Failed to load state file, starting without it"
+            );
+        },
+    }
+}
+
+fn start_file_writers(
+    tasks: &TaskTracker,
+    hosts_file_writer: Option<HostsFileWriter>,
+    state_file: Option<StateFile>,
+    daemons: &Arc<Daemons>,
+    cancellation_token: &CancellationToken,
+) -> Result<(), eyre::Report> {
+    let paths = hosts_file_writer
+        .as_ref()
+        .map(HostsFileWriter::path)
+        .into_iter()
+        .chain(state_file.as_ref().map(StateFile::path));
+
+    for path in paths {
+        check_writable(path)?;
+    }
+
+    if let Some(writer) = hosts_file_writer {
+        tasks.spawn_with_name(
+            "hosts file writer",
+            writer.run(Arc::clone(daemons), cancellation_token.clone()),
+        );
+    }
+
+    if let Some(state_file) = state_file {
+        tasks.spawn_with_name(
+            "state file writer",
+            state_file.run(Arc::clone(daemons), cancellation_token.clone()),
+        );
+    }
+
+    Ok(())
+}
+
+fn start_admin_server(
+    tasks: &TaskTracker,
+    admin_bind: Option<SocketAddr>,
+    control_socket: Option<PathBuf>,
+    admin_state: AdminState,
+    cancellation_token: &CancellationToken,
+) -> Result<(), eyre::Report> {
+    if admin_bind.is_none() && control_socket.is_none() {
+        return Ok(());
+    }
+
+    let tcp = admin_bind
+        .map(|admin_bind| {
+            bind_tcp(admin_bind, false)
+                .wrap_err_with(|| format!("Failed to bind TCP {}", admin_bind))
+        })
+        .transpose()?;
+
+    let control_socket = control_socket
+        .map(|path| {
+            bind_unix(&path)
+                .map(|listener| (listener, path.clone()))
+                .wrap_err_with(|| format!("Failed to bind control socket {}", path.display()))
+        })
+        .transpose()?;
+
+    tasks.spawn_with_name(
+        "admin handler",
+        admin_handler(
+            AdminListeners {
+                tcp,
+                control_socket,
+            },
+            admin_state,
+            cancellation_token.clone(),
+        ),
+    );
+
+    Ok(())
+}
+
+fn start_listeners(
+    tasks: &TaskTracker,
+    listener_config: ListenerConfig,
+    handler: DnsRequestHandler,
+    admin_state: AdminState,
+    cancellation_token: &CancellationToken,
+) -> Result<(), eyre::Report> {
+    let ListenerConfig {
+        dns_binds,
+        udp_sockets,
+        doh_config,
+        admin_bind,
+        control_socket,
+        listen_fds,
+        ..
+    } = listener_config;
+
+    start_admin_server(
+        tasks,
+        admin_bind,
+        control_socket,
+        admin_state,
+        cancellation_token,
+    )?;
+
+    let activated = activated_sockets(listen_fds)?;
+
+    let (sockets, listeners, activated_doh_listener) = if let Some(activated) = activated {
+        event!(
+            Level::INFO,
+            "Using sockets passed by systemd, ignoring DNS bind addresses"
+        );
+
+        (
+            activated.udp_sockets,
+            activated.tcp_listeners,
+            activated.doh_listener,
+        )
+    } else {
+        let mut sockets = Vec::with_capacity(dns_binds.len() * udp_sockets.get());
+        let mut listeners = Vec::with_capacity(dns_binds.len());
+
+        for &dns_bind in &dns_binds {
+            let only_v6 = needs_only_v6(dns_bind, &dns_binds);
+
+            sockets.extend(
+                bind_udp_reuse_port(dns_bind, only_v6, udp_sockets)
+                    .wrap_err_with(|| format!("Failed to bind UDP {}", dns_bind))?,
+            );
+            listeners.push(
+                bind_tcp(dns_bind, only_v6)
+                    .wrap_err_with(|| format!("Failed to bind TCP {}", dns_bind))?,
+            );
+        }
+
+        (sockets, listeners, None)
+    };
+
+    tasks.spawn_with_name(
+        "dns handler",
+        dns_handler(
+            sockets,
+            listeners,
+            handler.clone(),
+            cancellation_token.clone(),
+        ),
+    );
+
+    match (doh_config, activated_doh_listener) {
+        (Some(doh_config), activated_doh_listener) => {
+            let tls_config = load_tls_config(&doh_config.cert, &doh_config.key)?;
+
+            let listener = match (activated_doh_listener, doh_config.bind) {
+                (Some(listener), _) => listener,
+                (None, Some(doh_bind)) => bind_tcp(doh_bind, false)
+                    .wrap_err_with(|| format!("Failed to bind TCP {}", doh_bind))?,
+                (None, None) => {
+                    return Err(eyre::Report::msg(format!(
+                        "DNS-over-HTTPS needs a bind address or a systemd socket named `{}`",
+                        DOH_FD_NAME
+                    )));
+                },
+            };
+
+            tasks.spawn_with_name(
+                "doh handler",
+                doh_handler(listener, tls_config, handler, cancellation_token.clone()),
+            );
+        },
+        (None, Some(_)) => {
+            event!(
+                Level::WARN,
+                "systemd passed a DNS-over-HTTPS socket, but no certificate and key were configured"
+            );
+        },
+        (None, None) => {},
+    }
+
+    Ok(())
 }
 
 async fn dns_handler(
-    socket: UdpSocket,
-    listener: TcpListener,
-    catalog: Arc<RwLock<Catalog>>,
-    records: Vec<RawRecord>,
+    sockets: Vec<UdpSocket>,
+    listeners: Vec<TcpListener>,
+    handler: DnsRequestHandler,
     cancellation_token: CancellationToken,
 ) {
     let _guard = cancellation_token.clone().drop_guard();
 
-    let handler = DnsRequestHandler::new(catalog, records);
-    set_up_dns_server(listener, socket, handler, cancellation_token).await;
+    set_up_dns_server(listeners, sockets, handler, cancellation_token).await;
 
     event!(Level::INFO, "DNS Server stopped");
 }
 
-async fn docker_listener(
-    docker: Arc<Daemon>,
-    sender: tokio::sync::mpsc::Sender<Event>,
+async fn admin_handler(
+    listeners: AdminListeners,
+    state: AdminState,
     cancellation_token: CancellationToken,
 ) {
     let _guard = cancellation_token.clone().drop_guard();
 
-    if let Err(error) = docker.produce_events(sender, &cancellation_token).await {
-        event!(Level::ERROR, ?error, "Event producer Handler failed");
-    } else {
-        event!(Level::INFO, "Event producer stopped");
-    }
+    set_up_admin_server(listeners, state, cancellation_token).await;
+
+    event!(Level::INFO, "Admin API Server stopped");
 }
 
-async fn docker_event_monitor(
-    docker_monitor: Monitor,
-    receiver: Receiver<Event>,
+async fn doh_handler(
+    listener: TcpListener,
+    tls_config: Arc<ServerConfig>,
+    handler: DnsRequestHandler,
     cancellation_token: CancellationToken,
 ) {
     let _guard = cancellation_token.clone().drop_guard();
 
-    if let Err(error) = docker_monitor.start().await {
-        event!(Level::ERROR, ?error, "Failed to fetch containers");
-        return;
-    }
-
-    docker_monitor
-        .consume_events(receiver, &cancellation_token)
-        .await;
+    set_up_doh_server(listener, tls_config, handler, cancellation_token).await;
 
-    event!(Level::INFO, "Event handler stopped");
+    event!(Level::INFO, "DNS-over-HTTPS Server stopped");
 }
@@ -0,0 +1,228 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use color_eyre::eyre;
+use hyper::body::Bytes;
+use hyper::{Request, Response};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_http::hyper::HyperClient;
+use opentelemetry_http::{HttpClient, HttpError};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig as _, WithHttpConfig as _};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::trace::{SdkTracer, SdkTracerProvider};
+use tokio::runtime::Handle;
+use tracing::{Level, Subscriber, event};
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::registry::LookupSpan;
+
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Sends the requests from our runtime.
+///
+/// The SDK exports from a thread of its own, which has no runtime for hyper to run on.
+#[derive(Debug)]
+struct RuntimeHttpClient {
+    client: HyperClient,
+    runtime: Handle,
+}
+
+#[async_trait]
+impl HttpClient for RuntimeHttpClient {
+    async fn send_bytes(&self, request: Request<Bytes>) -> Result<Response<Bytes>, HttpError> {
+        let client = self.client.clone();
+
+        self.runtime
+            .spawn(async move { client.send_bytes(request).await })
+            .await?
+    }
+}
+
+/// Exports spans over OTLP/HTTP in batches.
+///
+/// Dropping it flushes the spans that weren't exported yet, the runtime must still be running then.
+pub struct OtlpExporter {
+    provider: SdkTracerProvider,
+}
+
+impl OtlpExporter {
+    pub fn new(endpoint: String, runtime: Handle) -> Result<Self, eyre::Report> {
+        let client = RuntimeHttpClient {
+            client: HyperClient::with_default_connector(EXPORT_TIMEOUT, None),
+            runtime,
+        };
+
+        let exporter = SpanExporter::builder()
+            .with_http()
+            .with_http_client(client)
+            .with_endpoint(endpoint)
+            .with_timeout(EXPORT_TIMEOUT)
+            .build()?;
+
+        let provider = SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(
+                Resource::builder()
+                    .with_service_name(env!("CARGO_PKG_NAME"))
+                    .build(),
+            )
+            .build();
+
+        Ok(Self { provider })
+    }
+
+    pub fn layer<S>(&self) -> OpenTelemetryLayer<S, SdkTracer>
+    where
+        S: Subscriber + for<'span> LookupSpan<'span>,
+    {
+        tracing_opentelemetry::layer().with_tracer(self.provider.tracer(env!("CARGO_CRATE_NAME")))
+    }
+}
+
+impl Drop for OtlpExporter {
+    fn drop(&mut self) {
+        if let Err(error) = self.provider.shutdown() {
+            event!(Level::WARN, ?error, "Failed to flush traces");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    use hickory_net::runtime::TokioTime;
+    use hickory_net::xfer::Protocol;
+    use hickory_server::proto::op::{Message, Query};
+    use hickory_server::proto::rr::RecordType;
+    use hickory_server::server::{Request, RequestHandler as _};
+    use http_body_util::{BodyExt as _, Full};
+    use hyper::body::{Bytes, Incoming};
+    use hyper::server::conn::http1;
+    use hyper::service::service_fn;
+    use hyper::{Response, StatusCode};
+    use hyper_util::rt::TokioIo;
+    use opentelemetry_proto::tonic::collector::trace::v1::ExportTraceServiceRequest;
+    use opentelemetry_proto::tonic::common::v1::any_value::Value;
+    use opentelemetry_proto::tonic::trace::v1::Span;
+    use pretty_assertions::assert_eq;
+    use prost::Message as _;
+    use tokio::net::TcpListener;
+    use tokio::runtime::Handle;
+    use tokio::sync::mpsc::{UnboundedSender, unbounded_channel};
+    use tracing::subscriber::set_default;
+    use tracing_subscriber::layer::SubscriberExt as _;
+
+    use crate::dns_listener::DnsRequestHandler;
+    use crate::dns_listener::response_buffer::BufferedResponseHandler;
+    use crate::otlp::OtlpExporter;
+    use crate::table::AuthorityWrapper;
+
+    /// Stands in for an OpenTelemetry collector, passing on the spans it receives.
+    async fn start_collector(spans: UnboundedSender<(String, Span)>) -> SocketAddr {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let spans = spans.clone();
+
+                let service = service_fn(move |request: hyper::Request<Incoming>| {
+                    let spans = spans.clone();
+
+                    async move {
+                        let path = request.uri().path().to_owned();
+                        let body = request.into_body().collect().await?.to_bytes();
+
+                        let export = ExportTraceServiceRequest::decode(body).unwrap();
+
+                        for span in export
+                            .resource_spans
+                            .into_iter()
+                            .flat_map(|resource| resource.scope_spans)
+                            .flat_map(|scope| scope.spans)
+                        {
+                            spans.send((path.clone(), span)).unwrap();
+                        }
+
+                        let mut response = Response::new(Full::new(Bytes::new()));
+                        *response.status_mut() = StatusCode::OK;
+
+                        Ok::<_, hyper::Error>(response)
+                    }
+                });
+
+                tokio::spawn(http1::Builder::new().serve_connection(TokioIo::new(stream), service));
+            }
+        });
+
+        address
+    }
+
+    fn attribute(span: &Span, key: &str) -> Option<String> {
+        span.attributes
+            .iter()
+            .find(|attribute| attribute.key == key)
+            .and_then(|attribute| attribute.value.as_ref()?.value.as_ref())
+            .and_then(|value| {
+                if let Value::StringValue(ref string) = *value {
+                    Some(string.clone())
+                } else {
+                    None
+                }
+            })
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn dns_request_span_is_exported() {
+        let (sender, mut receiver) = unbounded_channel();
+        let address = start_collector(sender).await;
+
+        let exporter =
+            OtlpExporter::new(format!("http://{}/v1/traces", address), Handle::current()).unwrap();
+
+        let subscriber = tracing_subscriber::registry().with(exporter.layer());
+
+        let authority_wrapper = AuthorityWrapper::new("docker.".parse().unwrap());
+        let handler = DnsRequestHandler::new(authority_wrapper.catalog(), vec![], None);
+
+        let mut message = Message::query();
+        message.add_query(Query::query(
+            "missing.docker.".parse().unwrap(),
+            RecordType::A,
+        ));
+
+        let request = Request::from_bytes(
+            message.to_vec().unwrap(),
+            SocketAddr::from((Ipv4Addr::LOCALHOST, 53)),
+            Protocol::Udp,
+        )
+        .unwrap();
+
+        {
+            let _guard = set_default(subscriber);
+
+            handler
+                .handle_request::<_, TokioTime>(&request, BufferedResponseHandler::default())
+                .await;
+        }
+
+        // flushing waits for the export, which runs on this runtime
+        tokio::task::spawn_blocking(move || drop(exporter))
+            .await
+            .unwrap();
+
+        // children end, and are exported, before their parents
+        let (path, span) = std::iter::from_fn(|| receiver.try_recv().ok())
+            .find(|&(_, ref span)| span.name == "handle_request")
+            .unwrap();
+
+        assert_eq!(path, "/v1/traces");
+        assert_eq!(
+            attribute(&span, "qname").as_deref(),
+            Some("missing.docker.")
+        );
+        assert_eq!(attribute(&span, "qtype").as_deref(), Some("A"));
+        assert_eq!(attribute(&span, "rcode").as_deref(), Some("NXDomain"));
+    }
+}
@@ -0,0 +1,211 @@
+use std::io;
+use std::os::unix::fs::MetadataExt as _;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre;
+use color_eyre::eyre::WrapErr as _;
+use nix::unistd::{Gid, Group, Uid, User, getgid, getuid, setgid, setgroups, setuid};
+use tracing::{Level, event};
+
+fn resolve_user(user: &str) -> Result<(Uid, Option<Gid>), eyre::Report> {
+    let entry = if let Ok(uid) = user.parse::<u32>() {
+        let uid = Uid::from_raw(uid);
+
+        // numeric users don't need a passwd entry, but if there is one, use its primary group
+        match User::from_uid(uid)? {
+            Some(entry) => entry,
+            None => return Ok((uid, None)),
+        }
+    } else {
+        User::from_name(user)?
+            .ok_or_else(|| eyre::Report::msg(format!("Unknown user `{}`", user)))?
+    };
+
+    Ok((entry.uid, Some(entry.gid)))
+}
+
+fn resolve_group(group: &str) -> Result<Gid, eyre::Report> {
+    if let Ok(gid) = group.parse::<u32>() {
+        return Ok(Gid::from_raw(gid));
+    }
+
+    Group::from_name(group)?
+        .map(|entry| entry.gid)
+        .ok_or_else(|| eyre::Report::msg(format!("Unknown group `{}`", group)))
+}
+
+/// The group to switch to, never the one we're running as, that's root's.
+fn target_group(
+    user: &str,
+    user_gid: Option<Gid>,
+    group: Option<&str>,
+) -> Result<Gid, eyre::Report> {
+    match (group, user_gid) {
+        (Some(group), _) => resolve_group(group),
+        (None, Some(user_gid)) => Ok(user_gid),
+        (None, None) => Err(eyre::Report::msg(format!(
+            "User `{}` has no passwd entry to take a group from, pass a group",
+            user
+        ))),
+    }
+}
+
+/// Switches to `user`, and to `group` or else the user's primary group. Must be called after
+/// binding privileged ports.
+///
+/// A group without a user would leave us running as root, the configuration rejects that.
+///
+/// The only supplementary groups kept are the ones owning the Docker sockets, so access to them
+/// survives. Fails if we're no longer allowed to use a Docker socket afterwards. A socket that isn't
+/// there yet, e.g. at boot, is left to the Docker connection to retry.
+///
+/// The control socket is handed to `user` and `group`, so the subcommands work as that user too.
+pub fn drop_privileges(
+    user: Option<&str>,
+    group: Option<&str>,
+    docker_sockets: &[PathBuf],
+    control_socket: Option<&Path>,
+) -> Result<(), eyre::Report> {
+    let Some(user) = user else {
+        if let Some(group) = group {
+            return Err(eyre::Report::msg(format!(
+                "Group `{}` was passed without a user, we would keep running as root",
+                group
+            )));
+        }
+
+        if getuid().is_root() {
+            event!(
+                Level::WARN,
+                "Running as root, consider passing a user to drop privileges to"
+            );
+        }
+
+        return Ok(());
+    };
+
+    let (uid, user_gid) = resolve_user(user)?;
+
+    let gid = target_group(user, user_gid, group)?;
+
+    // only root can give a file away
+    if let Some(control_socket) = control_socket {
+        std::os::unix::fs::chown(control_socket, Some(uid.as_raw()), Some(gid.as_raw()))
+            .wrap_err_with(|| {
+                format!(
+                    "Failed to hand over control socket {}",
+                    control_socket.display()
+                )
+            })?;
+    }
+
+    let mut supplementary_groups = Vec::new();
+
+    for docker_socket in docker_sockets {
+        match docker_socket.metadata() {
+            Ok(metadata) => {
+                let socket_gid = Gid::from_raw(metadata.gid());
+
+                if socket_gid != gid && !supplementary_groups.contains(&socket_gid) {
+                    supplementary_groups.push(socket_gid);
+                }
+            },
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                event!(
+                    Level::WARN,
+                    docker_socket = %docker_socket.display(),
+                    "Docker socket doesn't exist yet, its group can't be kept, pass it as the group if it's needed"
+                );
+            },
+            Err(error) => {
+                return Err(error).wrap_err_with(|| {
+                    format!("Failed to stat Docker socket {}", docker_socket.display())
+                });
+            },
+        }
+    }
+
+    // order matters: once we're no longer root we can't change groups anymore
+    setgroups(&supplementary_groups).wrap_err("Failed to set supplementary groups")?;
+    setgid(gid).wrap_err_with(|| format!("Failed to switch to group {}", gid))?;
+
+    setuid(uid).wrap_err_with(|| format!("Failed to switch to user {}", uid))?;
+
+    if !uid.is_root() && setuid(Uid::from_raw(0)).is_ok() {
+        return Err(eyre::Report::msg(
+            "Was able to regain root after dropping privileges",
+        ));
+    }
+
+    event!(
+        Level::INFO,
+        uid = %getuid(),
+        gid = %getgid(),
+        ?supplementary_groups,
+        "Dropped privileges"
+    );
+
+    // only a denied connection is ours to fix, Docker not running yet is retried
+    for docker_socket in docker_sockets {
+        if let Err(error) = UnixStream::connect(docker_socket)
+            && error.kind() == io::ErrorKind::PermissionDenied
+        {
+            return Err(error).wrap_err_with(|| {
+                format!(
+                    "Docker socket {} is not accessible after dropping privileges",
+                    docker_socket.display()
+                )
+            });
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use nix::unistd::{Gid, Uid};
+    use pretty_assertions::assert_eq;
+
+    use crate::privileges::{drop_privileges, resolve_group, resolve_user, target_group};
+
+    #[test]
+    fn resolve_user_by_name() {
+        assert_eq!(
+            resolve_user("root").unwrap(),
+            (Uid::from_raw(0), Some(Gid::from_raw(0)))
+        );
+    }
+
+    #[test]
+    fn resolve_unknown_numeric_user() {
+        assert_eq!(resolve_user("4242").unwrap(), (Uid::from_raw(4242), None));
+    }
+
+    #[test]
+    fn resolve_group_by_id() {
+        assert_eq!(resolve_group("4242").unwrap(), Gid::from_raw(4242));
+    }
+
+    #[test]
+    fn resolve_unknown_user() {
+        let _report = resolve_user("docker-dns-rs-does-not-exist").unwrap_err();
+    }
+
+    #[test]
+    fn unknown_numeric_user_needs_a_group() {
+        let (_, user_gid) = resolve_user("4242").unwrap();
+
+        let _report = target_group("4242", user_gid, None).unwrap_err();
+        assert_eq!(
+            target_group("4242", user_gid, Some("4243")).unwrap(),
+            Gid::from_raw(4243)
+        );
+    }
+
+    #[test]
+    fn group_without_user_keeps_root() {
+        let _report = drop_privileges(None, Some("4242"), &[], None).unwrap_err();
+    }
+}
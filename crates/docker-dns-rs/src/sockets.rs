@@ -0,0 +1,197 @@
+use std::fs::Permissions;
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
+use std::os::unix::fs::{FileTypeExt as _, PermissionsExt as _};
+use std::path::Path;
+
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::{TcpListener, UdpSocket, UnixListener};
+
+pub mod systemd;
+
+/// Same backlog as the standard library uses.
+const TCP_BACKLOG: i32 = 128;
+
+/// Whether `address` has to be bound to IPv6 only. Linux binds `[::]` to IPv4 as well by default,
+/// which conflicts with an IPv4 address in `binds` on the same port. Otherwise the OS default is
+/// kept, and a lone `[::]:53` serves IPv4 clients too.
+pub fn needs_only_v6(address: SocketAddr, binds: &[SocketAddr]) -> bool {
+    address.is_ipv6()
+        && binds
+            .iter()
+            .any(|bind| bind.is_ipv4() && bind.port() == address.port())
+}
+
+fn new_socket(
+    address: SocketAddr,
+    only_v6: bool,
+    r#type: Type,
+    protocol: Protocol,
+) -> Result<Socket, std::io::Error> {
+    let socket = Socket::new(Domain::for_address(address), r#type, Some(protocol))?;
+
+    if only_v6 {
+        socket.set_only_v6(true)?;
+    }
+
+    socket.set_nonblocking(true)?;
+
+    Ok(socket)
+}
+
+pub fn bind_udp(address: SocketAddr, only_v6: bool) -> Result<UdpSocket, std::io::Error> {
+    let socket = new_socket(address, only_v6, Type::DGRAM, Protocol::UDP)?;
+
+    socket.bind(&address.into())?;
+
+    UdpSocket::from_std(socket.into())
+}
+
+/// Binds `count` UDP sockets to the same address with `SO_REUSEPORT`, the kernel spreads
+/// incoming datagrams over them by hashing the source address.
+pub fn bind_udp_reuse_port(
+    address: SocketAddr,
+    only_v6: bool,
+    count: NonZeroUsize,
+) -> Result<Vec<UdpSocket>, std::io::Error> {
+    if count.get() == 1 {
+        return Ok(vec![bind_udp(address, only_v6)?]);
+    }
+
+    let mut sockets = Vec::with_capacity(count.get());
+
+    // when binding to port 0 the first socket picks the port, the rest need to follow it
+    let mut address = address;
+
+    for _ in 0..count.get() {
+        let socket = new_socket(address, only_v6, Type::DGRAM, Protocol::UDP)?;
+
+        socket.set_reuse_port(true)?;
+        socket.bind(&address.into())?;
+
+        let socket = UdpSocket::from_std(socket.into())?;
+
+        address = socket.local_addr()?;
+
+        sockets.push(socket);
+    }
+
+    Ok(sockets)
+}
+
+pub fn bind_tcp(address: SocketAddr, only_v6: bool) -> Result<TcpListener, std::io::Error> {
+    let socket = new_socket(address, only_v6, Type::STREAM, Protocol::TCP)?;
+
+    socket.set_reuse_address(true)?;
+    socket.bind(&address.into())?;
+    socket.listen(TCP_BACKLOG)?;
+
+    TcpListener::from_std(socket.into())
+}
+
+/// Binds a Unix socket only its owner can connect to, replacing one a previous run left behind.
+pub fn bind_unix(path: &Path) -> Result<UnixListener, std::io::Error> {
+    if std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        std::fs::remove_file(path)?;
+    }
+
+    let listener = UnixListener::bind(path)?;
+
+    std::fs::set_permissions(path, Permissions::from_mode(0o600))?;
+
+    Ok(listener)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+    use std::num::NonZeroUsize;
+    use std::os::unix::fs::{FileTypeExt as _, PermissionsExt as _};
+
+    use pretty_assertions::assert_eq;
+
+    use crate::sockets::{bind_tcp, bind_udp, bind_udp_reuse_port, bind_unix, needs_only_v6};
+
+    #[tokio::test]
+    async fn unix_socket_replaces_stale_socket_and_is_private() {
+        let path =
+            std::env::temp_dir().join(format!("docker-dns-rs-{}-sockets.sock", std::process::id()));
+
+        // the first listener is dropped without removing its file, like after a crash
+        drop(bind_unix(&path).unwrap());
+
+        let listener = bind_unix(&path).unwrap();
+        let metadata = std::fs::metadata(&path).unwrap();
+
+        assert!(metadata.file_type().is_socket());
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+
+        drop(listener);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn reuse_port_shares_address() {
+        let sockets = bind_udp_reuse_port(
+            SocketAddr::from((Ipv4Addr::LOCALHOST, 0)),
+            false,
+            NonZeroUsize::new(4).unwrap(),
+        )
+        .unwrap();
+
+        let address = sockets[0].local_addr().unwrap();
+
+        assert_eq!(sockets.len(), 4);
+
+        for socket in &sockets {
+            assert_eq!(socket.local_addr().unwrap(), address);
+        }
+    }
+
+    #[test]
+    fn only_v6_when_ipv4_shares_the_port() {
+        let v4 = SocketAddr::from((Ipv4Addr::UNSPECIFIED, 53));
+        let v6 = SocketAddr::from((Ipv6Addr::UNSPECIFIED, 53));
+
+        assert!(needs_only_v6(v6, &[v4, v6]));
+        assert!(!needs_only_v6(v6, &[v6]));
+        assert!(!needs_only_v6(
+            v6,
+            &[SocketAddr::from((Ipv4Addr::UNSPECIFIED, 5353)), v6]
+        ));
+        assert!(!needs_only_v6(v4, &[v4, v6]));
+    }
+
+    #[tokio::test]
+    async fn lone_ipv6_wildcard_serves_ipv4() {
+        let v6 = bind_udp(SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)), false).unwrap();
+        let port = v6.local_addr().unwrap().port();
+
+        let client = bind_udp(SocketAddr::from((Ipv4Addr::LOCALHOST, 0)), false).unwrap();
+        client
+            .send_to(b"query", SocketAddr::from((Ipv4Addr::LOCALHOST, port)))
+            .await
+            .unwrap();
+
+        let mut buffer = [0; 5];
+        let (length, _) = v6.recv_from(&mut buffer).await.unwrap();
+
+        assert_eq!(&buffer[..length], b"query");
+    }
+
+    #[tokio::test]
+    async fn ipv4_and_ipv6_share_port() {
+        let v4 = bind_udp(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)), false).unwrap();
+        let port = v4.local_addr().unwrap().port();
+
+        let v6 = bind_udp(SocketAddr::from((Ipv6Addr::UNSPECIFIED, port)), true).unwrap();
+
+        assert_eq!(v6.local_addr().unwrap().port(), port);
+
+        let v4 = bind_tcp(SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)), false).unwrap();
+        let v6 = bind_tcp(SocketAddr::from((Ipv6Addr::UNSPECIFIED, port)), true).unwrap();
+
+        assert_eq!(v4.local_addr().unwrap().port(), port);
+        assert_eq!(v6.local_addr().unwrap().port(), port);
+    }
+}
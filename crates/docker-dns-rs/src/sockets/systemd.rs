@@ -0,0 +1,231 @@
+use std::env;
+use std::os::fd::{FromRawFd as _, RawFd};
+
+use color_eyre::eyre;
+use socket2::{Socket, Type};
+use tokio::net::{TcpListener, UdpSocket};
+use tracing::{Level, event};
+
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+/// More sockets than our unit could pass us, a count above it isn't one we'd take ownership of.
+const MAX_LISTEN_FDS: u32 = 256;
+
+/// `FileDescriptorName=` of the socket unit entry that should serve DNS-over-HTTPS.
+/// Every other socket serves plain DNS.
+pub const DOH_FD_NAME: &str = "doh";
+
+#[derive(Default)]
+pub struct ActivatedSockets {
+    pub udp_sockets: Vec<UdpSocket>,
+    pub tcp_listeners: Vec<TcpListener>,
+    pub doh_listener: Option<TcpListener>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ListenFd {
+    fd: RawFd,
+    name: Option<Box<str>>,
+}
+
+/// Parses the `LISTEN_*` variables. Returns nothing when they aren't meant for this process.
+fn parse_listen_fds(
+    pid: u32,
+    listen_pid: Option<&str>,
+    listen_fds: Option<&str>,
+    listen_fdnames: Option<&str>,
+) -> Result<Vec<ListenFd>, eyre::Report> {
+    let (Some(listen_pid), Some(listen_fds)) = (listen_pid, listen_fds) else {
+        return Ok(vec![]);
+    };
+
+    if listen_pid.parse::<u32>()? != pid {
+        // inherited from a parent that was activated, not for us
+        return Ok(vec![]);
+    }
+
+    let end = listen_fds
+        .parse::<u32>()
+        .ok()
+        .filter(|&count| count <= MAX_LISTEN_FDS)
+        .and_then(|count| RawFd::try_from(count).ok())
+        .and_then(|count| SD_LISTEN_FDS_START.checked_add(count))
+        .ok_or_else(|| eyre::Report::msg(format!("Invalid LISTEN_FDS `{}`", listen_fds)))?;
+
+    let mut names = listen_fdnames.map(|names| names.split(':'));
+
+    let listen_fds = (SD_LISTEN_FDS_START..end)
+        .map(|fd| {
+            let name = names
+                .as_mut()
+                .and_then(Iterator::next)
+                .filter(|name| !name.is_empty())
+                .map(Into::into);
+
+            ListenFd { fd, name }
+        })
+        .collect();
+
+    Ok(listen_fds)
+}
+
+fn env_var(key: &str) -> Option<String> {
+    env::var(key).ok()
+}
+
+/// Reads the file descriptors systemd passed to us, as described in `sd_listen_fds(3)`.
+///
+/// systemd passes the sockets it opened as file descriptors starting at 3, and describes them
+/// through `LISTEN_PID`, `LISTEN_FDS` and (optionally) `LISTEN_FDNAMES`. The variables are unset,
+/// so a process we spawn doesn't take them as well.
+///
+/// Has to be called before any other thread is started, unsetting variables isn't thread safe.
+pub fn take_listen_fds() -> Result<Vec<ListenFd>, eyre::Report> {
+    const KEYS: [&str; 3] = ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"];
+
+    let [listen_pid, listen_fds, listen_fdnames] = KEYS.map(env_var);
+
+    for key in KEYS {
+        // SAFETY: the caller guarantees no other thread runs yet, so none reads the environment
+        unsafe {
+            env::remove_var(key);
+        }
+    }
+
+    parse_listen_fds(
+        std::process::id(),
+        listen_pid.as_deref(),
+        listen_fds.as_deref(),
+        listen_fdnames.as_deref(),
+    )
+}
+
+/// Takes ownership of the sockets from [`take_listen_fds`].
+///
+/// Returns `None` when the process wasn't socket activated.
+pub fn activated_sockets(
+    listen_fds: Vec<ListenFd>,
+) -> Result<Option<ActivatedSockets>, eyre::Report> {
+    if listen_fds.is_empty() {
+        return Ok(None);
+    }
+
+    let mut activated = ActivatedSockets::default();
+
+    for ListenFd { fd, name } in listen_fds {
+        // SAFETY: systemd guarantees that the descriptors in `SD_LISTEN_FDS_START..+LISTEN_FDS`
+        // are open sockets, and we take each of them exactly once.
+        let socket = unsafe { Socket::from_raw_fd(fd) };
+
+        // descriptors are passed without `FD_CLOEXEC`
+        socket.set_cloexec(true)?;
+        socket.set_nonblocking(true)?;
+
+        let r#type = socket.r#type()?;
+
+        event!(
+            Level::INFO,
+            fd,
+            name = name.as_deref(),
+            ?r#type,
+            "Received socket from systemd"
+        );
+
+        if r#type == Type::DGRAM {
+            activated
+                .udp_sockets
+                .push(UdpSocket::from_std(socket.into())?);
+        } else if r#type == Type::STREAM {
+            let listener = TcpListener::from_std(socket.into())?;
+
+            if name.as_deref() == Some(DOH_FD_NAME) {
+                if activated.doh_listener.replace(listener).is_some() {
+                    return Err(eyre::Report::msg(
+                        "More than one DNS-over-HTTPS socket passed by systemd",
+                    ));
+                }
+            } else {
+                activated.tcp_listeners.push(listener);
+            }
+        } else {
+            return Err(eyre::Report::msg(format!(
+                "Unsupported socket type {:?} passed by systemd on fd {}",
+                r#type, fd
+            )));
+        }
+    }
+
+    Ok(Some(activated))
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::sockets::systemd::{ListenFd, parse_listen_fds};
+
+    #[test]
+    fn not_activated() {
+        assert_eq!(parse_listen_fds(42, None, None, None).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn other_pid() {
+        assert_eq!(
+            parse_listen_fds(42, Some("41"), Some("2"), None).unwrap(),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn without_names() {
+        assert_eq!(
+            parse_listen_fds(42, Some("42"), Some("2"), None).unwrap(),
+            vec![
+                ListenFd { fd: 3, name: None },
+                ListenFd { fd: 4, name: None }
+            ]
+        );
+    }
+
+    #[test]
+    fn with_names() {
+        assert_eq!(
+            parse_listen_fds(42, Some("42"), Some("3"), Some("dns:dns:doh")).unwrap(),
+            vec![
+                ListenFd {
+                    fd: 3,
+                    name: Some("dns".into())
+                },
+                ListenFd {
+                    fd: 4,
+                    name: Some("dns".into())
+                },
+                ListenFd {
+                    fd: 5,
+                    name: Some("doh".into())
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_count() {
+        let _report = parse_listen_fds(42, Some("42"), Some("two"), None).unwrap_err();
+        let _report = parse_listen_fds(42, Some("42"), Some("-1"), None).unwrap_err();
+        let _report = parse_listen_fds(42, Some("42"), Some("2147483647"), None).unwrap_err();
+    }
+
+    #[test]
+    fn oversized_count() {
+        assert_eq!(
+            parse_listen_fds(42, Some("42"), Some("256"), None)
+                .unwrap()
+                .len(),
+            256
+        );
+
+        let _report = parse_listen_fds(42, Some("42"), Some("257"), None).unwrap_err();
+        let _report = parse_listen_fds(42, Some("42"), Some("100000"), None).unwrap_err();
+    }
+}
@@ -0,0 +1,139 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use color_eyre::eyre;
+use color_eyre::eyre::WrapErr as _;
+use serde::{Deserialize, Serialize};
+use serde_json::from_slice as from_json_slice;
+use tokio_util::sync::CancellationToken;
+use tracing::{Level, event};
+
+use crate::docker::daemons::Daemons;
+use crate::docker::monitor::{ContainerSummary, NetworkSummary};
+use crate::utils::fs::write_atomically;
+
+/// Bumped when the format changes, older snapshots are ignored.
+pub const STATE_VERSION: u32 = 2;
+
+/// Changes are written together when they come in quick succession, e.g. at startup.
+const WRITE_DELAY: Duration = Duration::from_secs(1);
+
+/// What the monitors know about their daemons, so a restart can answer before Docker is asked again.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateSnapshot {
+    pub version: u32,
+    /// Names and reverse zones depend on these, a snapshot taken with others is ignored.
+    pub domain: String,
+    pub rfc2317: bool,
+    pub networks: Vec<NetworkSummary>,
+    pub containers: Vec<ContainerSummary>,
+}
+
+pub struct StateFile {
+    path: PathBuf,
+}
+
+impl StateFile {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// `None` on the first start, when there's no file yet.
+    pub async fn load(&self) -> Result<Option<StateSnapshot>, eyre::Report> {
+        let contents = match tokio::fs::read(&self.path).await {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => {
+                return Err(error)
+                    .wrap_err_with(|| format!("Failed to read {}", self.path.display()));
+            },
+        };
+
+        from_json_slice(&contents)
+            .map(Some)
+            .wrap_err_with(|| format!("Failed to parse {}", self.path.display()))
+    }
+
+    /// Writes the state after every change, and once more when we're cancelled.
+    pub async fn run(self, daemons: Arc<Daemons>, cancellation_token: CancellationToken) {
+        let mut published = daemons.authority_wrapper().subscribe();
+
+        loop {
+            tokio::select! {
+                biased;
+                () = cancellation_token.cancelled() => break,
+                result = published.changed() => if result.is_err() {
+                    break;
+                },
+            }
+
+            tokio::select! {
+                biased;
+                () = cancellation_token.cancelled() => break,
+                () = tokio::time::sleep(WRITE_DELAY) => {},
+            }
+
+            published.borrow_and_update();
+
+            self.write(&daemons).await;
+        }
+
+        self.write(&daemons).await;
+    }
+
+    async fn write(&self, daemons: &Daemons) {
+        let result = async {
+            let contents = serde_json::to_vec(&daemons.snapshot().await)?;
+
+            write_atomically(&self.path, &contents).await
+        }
+        .await;
+
+        match result {
+            Ok(()) => event!(Level::DEBUG, path = %self.path.display(), "Wrote state file"),
+            Err(error) => {
+                event!(Level::ERROR, ?error, path = %self.path.display(), "Failed to write state file");
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::state_file::StateFile;
+
+    #[tokio::test]
+    async fn missing_file_is_a_first_start() {
+        let path = std::env::temp_dir().join(format!(
+            "docker-dns-rs-{}-missing-state.json",
+            std::process::id()
+        ));
+
+        assert_eq!(StateFile::new(path).load().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn unparseable_file_is_an_error() {
+        let path = std::env::temp_dir().join(format!(
+            "docker-dns-rs-{}-broken-state.json",
+            std::process::id()
+        ));
+
+        tokio::fs::write(&path, "{").await.unwrap();
+
+        let result = StateFile::new(path.clone()).load().await;
+        tokio::fs::remove_file(&path).await.unwrap();
+
+        let error = result.unwrap_err();
+
+        assert!(error.to_string().starts_with("Failed to parse"));
+    }
+}
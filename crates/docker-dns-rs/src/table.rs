@@ -1,21 +1,210 @@
 use std::borrow::Cow;
-use std::collections::BTreeMap;
 use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet};
 use std::net::IpAddr;
-use std::sync::Arc;
+use std::sync::{Arc, LazyLock};
+use std::time::SystemTime;
 
+use arc_swap::ArcSwap;
 use color_eyre::eyre::Report;
-use hashbrown::HashMap;
-use hickory_server::proto::rr::rdata::PTR;
-use hickory_server::proto::rr::{LowerName, Name, RData, Record, RecordSet, RecordType, RrKey};
+use hickory_server::proto::rr::rdata::{CNAME, PTR, SOA};
+use hickory_server::proto::rr::{
+    Label, LowerName, Name, RData, Record, RecordSet, RecordType, RrKey,
+};
 use hickory_server::store::in_memory::InMemoryZoneHandler;
-use ipnet::IpNet;
-use tokio::sync::RwLock;
+use hickory_server::zone_handler::{AxfrPolicy, Catalog, ZoneType};
+use ipnet::{IpNet, Ipv4Net, Ipv6Net};
+use tokio::sync::{Mutex, MutexGuard, watch};
 use tracing::{Level, event, instrument};
 
+use crate::audit::{Action, AuditEntry, AuditLog, RecordState, Trigger};
+use crate::config::RawRecord;
+use crate::table::master_file::ZoneFile;
+use crate::table::prefix_trie::PrefixTrie;
+
+pub mod master_file;
+mod prefix_trie;
+
+/// A zone as the monitor maintains it.
+///
+/// Queries never see this directly, they are answered from immutable snapshots of it.
+struct Zone {
+    origin: Name,
+    records: BTreeMap<RrKey, Arc<RecordSet>>,
+    /// Served instead of the record set with the same key in `records`, see `ManualRecord`.
+    overrides: BTreeMap<RrKey, Arc<RecordSet>>,
+    /// The last published handler, `None` when `records` changed since.
+    snapshot: Option<Arc<InMemoryZoneHandler>>,
+}
+
+/// The SOA record every zone we serve starts with.
+pub fn soa_record(origin: Name) -> Record {
+    Record::from_rdata(
+        origin.clone(),
+        3600,
+        RData::SOA(SOA::new(origin.clone(), origin, 0, 0, 0, 0, 0)),
+    )
+}
+
+impl Zone {
+    fn new(origin: Name) -> Self {
+        let records = BTreeMap::from([(
+            RrKey::new(LowerName::new(&origin), RecordType::SOA),
+            Arc::new(RecordSet::from(soa_record(origin.clone()))),
+        )]);
+
+        Self {
+            origin,
+            records,
+            overrides: BTreeMap::new(),
+            snapshot: None,
+        }
+    }
+
+    fn records_mut(&mut self) -> &mut BTreeMap<RrKey, Arc<RecordSet>> {
+        self.snapshot = None;
+
+        &mut self.records
+    }
+
+    fn snapshot(&mut self) -> Arc<InMemoryZoneHandler> {
+        let snapshot = self.snapshot.get_or_insert_with(|| {
+            let mut handler = InMemoryZoneHandler::empty(
+                self.origin.clone(),
+                ZoneType::Primary,
+                AxfrPolicy::Deny,
+            );
+
+            // record sets are shared with earlier snapshots, `Arc::make_mut` only copies
+            // the ones that are modified while a snapshot still holds on to them
+            *handler.records_get_mut() = served_records(&self.records, &self.overrides);
+
+            Arc::new(handler)
+        });
+
+        Arc::clone(snapshot)
+    }
+
+    fn zone_file(&self) -> ZoneFile {
+        let records = served_records(&self.records, &self.overrides);
+
+        ZoneFile::new(
+            self.origin.clone(),
+            records
+                .values()
+                .flat_map(|record_set| record_set.records_without_rrsigs()),
+        )
+    }
+}
+
+fn served_records(
+    records: &BTreeMap<RrKey, Arc<RecordSet>>,
+    overrides: &BTreeMap<RrKey, Arc<RecordSet>>,
+) -> BTreeMap<RrKey, Arc<RecordSet>> {
+    let mut served = records.clone();
+
+    served.extend(
+        overrides
+            .iter()
+            .map(|(key, record_set)| (key.clone(), Arc::clone(record_set))),
+    );
+
+    served
+}
+
+struct ReverseZone {
+    zone: Zone,
+    /// How many networks registered this zone, the same subnet can be used more than once
+    /// (e.g. by networks on other hosts, or networks Docker doesn't manage).
+    references: usize,
+}
+
+/// A forward record added through the admin API.
+///
+/// Kept apart from the records Docker reconciliation manages, and served instead of them:
+/// a manual A record for a name hides the container's A records until it's removed or expires.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ManualRecord {
+    pub name: Name,
+    pub address: IpAddr,
+    pub expires: Option<SystemTime>,
+}
+
+struct Zones {
+    forward: Zone,
+    reverse: PrefixTrie<ReverseZone>,
+    manual: BTreeMap<(LowerName, IpAddr), ManualRecord>,
+    /// The daemons each forward record is there for, it's served while any of them has it.
+    /// Two hosts can both run a `web` container on `172.17.0.2`.
+    owners: BTreeMap<(LowerName, IpAddr), BTreeSet<Box<str>>>,
+}
+
+enum Change {
+    Add { name: Name, address: IpAddr },
+    Remove { name: Name, address: IpAddr },
+}
+
+/// Record changes that belong together, e.g. everything a container event adds or removes.
+#[derive(Default)]
+pub struct ChangeSet {
+    changes: Vec<Change>,
+    trigger: Option<Trigger>,
+}
+
+impl ChangeSet {
+    /// A change set made in response to `trigger`, which the audit log records with every change.
+    pub fn new(trigger: Trigger) -> Self {
+        Self {
+            changes: Vec::new(),
+            trigger: Some(trigger),
+        }
+    }
+
+    /// Whose records these are. Changes without a trigger aren't any daemon's.
+    fn daemon(&self) -> &str {
+        self.trigger.as_ref().map_or("", |trigger| &trigger.daemon)
+    }
+
+    pub fn add(&mut self, name: &Name, address: IpAddr) {
+        self.changes.push(Change::Add {
+            name: name.clone(),
+            address,
+        });
+    }
+
+    pub fn remove(&mut self, name: &Name, address: IpAddr) {
+        self.changes.push(Change::Remove {
+            name: name.clone(),
+            address,
+        });
+    }
+}
+
+/// What an audited change is, for its entry in the audit log.
+#[derive(Clone, Copy)]
+struct AuditContext<'a> {
+    action: Action,
+    /// The address that's added or removed, absent for renames.
+    address: Option<IpAddr>,
+    trigger: Option<&'a Trigger>,
+    /// The name whose addresses are recorded before the change.
+    before: &'a Name,
+    /// The name whose addresses are recorded after the change, `before` unless it's a rename.
+    after: &'a Name,
+}
+
+/// Prefix length of the reverse zones created for static records.
+const STATIC_PREFIX_LEN_V4: u8 = 24;
+const STATIC_PREFIX_LEN_V6: u8 = 64;
+
 pub struct AuthorityWrapper {
-    forward: Arc<InMemoryZoneHandler>,
-    reverse_zones: RwLock<HashMap<IpNet, Arc<InMemoryZoneHandler>>>,
+    /// Only taken by writers.
+    zones: Mutex<Zones>,
+    /// What queries are answered from. Replaced as a whole after every change.
+    catalog: Arc<ArcSwap<Catalog>>,
+    /// Notified after every publish.
+    published: watch::Sender<()>,
+    audit_log: Option<AuditLog>,
 }
 
 fn append_to_record_set(
@@ -41,10 +230,9 @@ fn append_to_record_set(
 
 fn remove_from_ptr_set(
     records: &mut BTreeMap<RrKey, Arc<RecordSet>>,
-    ip: IpAddr,
+    reverse: Name,
     name: &LowerName,
 ) {
-    let reverse = Name::from(ip);
     let ptr_key = RrKey::new(LowerName::new(&reverse), RecordType::PTR);
 
     let Entry::Occupied(mut entry) = records.entry(ptr_key) else {
@@ -66,53 +254,359 @@ fn remove_from_ptr_set(
     }
 }
 
-impl AuthorityWrapper {
-    pub fn new(forward: Arc<InMemoryZoneHandler>) -> Self {
-        Self {
-            forward,
-            reverse_zones: RwLock::new(HashMap::new()),
-        }
+/// Whether `network` is served as an RFC 2317 classless zone: an IPv4 network smaller than a /24,
+/// but larger than a single address.
+pub fn is_classless(network: IpNet) -> bool {
+    matches!(network, IpNet::V4(net) if (25..32).contains(&net.prefix_len()))
+}
+
+/// The name of the reverse zone for `network`, which must be octet (IPv4) or nibble (IPv6) aligned,
+/// or classless.
+pub fn zone_name(network: IpNet) -> Option<Name> {
+    match network {
+        IpNet::V4(net) if is_classless(network) => {
+            // RFC 2317: `<first address>/<prefix length>` below the enclosing /24
+            let parent = zone_name(IpNet::V4(Ipv4Net::new(net.addr(), 24).ok()?.trunc()))?;
+            let label = format!("{}/{}", net.addr().octets()[3], net.prefix_len());
+
+            parent
+                .prepend_label(Label::from_raw_bytes(label.as_bytes()).ok()?)
+                .ok()
+        },
+        IpNet::V4(net) => {
+            static DOMAIN: LazyLock<Name> =
+                LazyLock::new(|| Name::from_str_relaxed("in-addr.arpa").unwrap());
+
+            let byte_count = usize::from(net.prefix_len()) / 8;
+
+            let labels = net
+                .addr()
+                .octets()
+                .into_iter()
+                .take(byte_count)
+                .rev()
+                .map(|n| n.to_string());
+
+            Name::from_labels(labels).ok()?.append_domain(&DOMAIN).ok()
+        },
+        IpNet::V6(net) => {
+            static DOMAIN: LazyLock<Name> =
+                LazyLock::new(|| Name::from_str_relaxed("ip6.arpa").unwrap());
+
+            let nibble_count = usize::from(net.prefix_len()) / 4;
+            let octets = net.addr().octets();
+            let labels = (0..nibble_count).rev().map(move |i| {
+                let nibble = if i % 2 == 0 {
+                    octets[i / 2] >> 4
+                } else {
+                    octets[i / 2] & 0x0f
+                };
+                format!("{nibble:x}")
+            });
+
+            Name::from_labels(labels).ok()?.append_domain(&DOMAIN).ok()
+        },
     }
+}
+
+/// The /24 that delegates to a classless zone, and its name.
+fn classless_parent(network: IpNet) -> (IpNet, Name) {
+    let parent = IpNet::new(network.addr(), 24)
+        .expect("24 is a valid IPv4 prefix length")
+        .trunc();
+
+    (parent, Name::from(parent.addr()).base_name())
+}
 
-    pub async fn add_reverse_zone(&self, network: IpNet, authority: Arc<InMemoryZoneHandler>) {
-        self.reverse_zones.write().await.insert(network, authority);
+/// The owner of the PTR record for `ip` in the reverse zone for `network`.
+///
+/// Classless zones only have the last octet below their own origin
+/// (`65.64/26.1.168.192.in-addr.arpa.`), the others use the regular reverse name.
+fn ptr_name(ip: IpAddr, network: IpNet, origin: &Name) -> Name {
+    let reverse = Name::from(ip);
+
+    if !is_classless(network) {
+        return reverse;
     }
 
-    pub async fn remove_reverse_zone(&self, network: &IpNet) {
-        self.reverse_zones.write().await.remove(network);
+    let last_octet = reverse.iter().next().expect("Reverse names have labels");
+
+    origin
+        .prepend_label(Label::from_raw_bytes(last_octet).expect("Octets are valid labels"))
+        .expect("Classless zone names are short")
+}
+
+/// The inverse of `ptr_name`.
+fn ptr_address(owner: &LowerName, network: IpNet) -> Option<IpAddr> {
+    let owner = Name::from(owner);
+
+    match network {
+        IpNet::V4(net) if is_classless(network) => {
+            let last_octet = std::str::from_utf8(owner.iter().next()?)
+                .ok()?
+                .parse::<u8>()
+                .ok()?;
+
+            let [a, b, c, _] = net.addr().octets();
+
+            Some(IpAddr::from([a, b, c, last_octet]))
+        },
+        IpNet::V4(_) | IpNet::V6(_) => owner.parse_arpa_name().ok().map(|ip| ip.addr()),
     }
+}
 
-    async fn find_reverse_authority(&self, ip: IpAddr) -> Option<Arc<InMemoryZoneHandler>> {
-        // Docker's IPAM rejects overlapping subnets, so at most one registered
-        // reverse zone can contain any given IP. HashMap iteration order is
-        // nondeterministic, but that doesn't matter here: there is never more
-        // than one match.
-        self.reverse_zones
-            .read()
-            .await
-            .iter()
-            .find(|&(network, _)| network.contains(&ip))
-            .map(|(_, authority)| Arc::clone(authority))
+/// Moves the PTR records for addresses in `network` between two reverse zones.
+fn move_ptr_records(from: (IpNet, &mut Zone), to: (IpNet, &mut Zone), network: IpNet) {
+    let (from_network, from) = from;
+    let (to_network, to) = to;
+
+    let moved = from
+        .records
+        .keys()
+        .filter(|key| key.record_type == RecordType::PTR)
+        .filter_map(|key| {
+            ptr_address(&key.name, from_network)
+                .filter(|ip| network.contains(ip))
+                .map(|ip| (key.clone(), ip))
+        })
+        .collect::<Vec<(RrKey, IpAddr)>>();
+
+    for (key, ip) in moved {
+        let Some(record_set) = from.records_mut().remove(&key) else {
+            continue;
+        };
+
+        // owner names differ between classless and regular zones
+        let owner = ptr_name(ip, to_network, &to.origin);
+
+        let mut renamed = RecordSet::with_ttl(owner.clone(), RecordType::PTR, record_set.ttl());
+
+        for record in record_set.records_without_rrsigs() {
+            renamed.add_rdata(record.data.clone());
+        }
+
+        to.records_mut().insert(
+            RrKey::new(LowerName::new(&owner), RecordType::PTR),
+            Arc::new(renamed),
+        );
     }
+}
 
-    async fn upsert(&self, name: &Name, address: IpAddr) {
-        let rdata: RData = RData::from(address);
-        let record_type = rdata.record_type();
-        let reverse: Name = address.into();
+impl Zones {
+    /// Rebuilds the forward zone's overrides after `manual` changed.
+    fn rebuild_overrides(&mut self) {
+        let mut overrides = BTreeMap::new();
 
-        {
-            let mut lock = self.forward.records_mut().await;
+        for record in self.manual.values() {
+            let rdata = RData::from(record.address);
+            let record_type = rdata.record_type();
 
             append_to_record_set(
-                &mut lock,
-                RrKey::new(LowerName::new(name), record_type),
-                Cow::Borrowed(name),
+                &mut overrides,
+                RrKey::new(LowerName::new(&record.name), record_type),
+                Cow::Borrowed(&record.name),
                 record_type,
                 rdata,
             );
         }
 
-        let Some(reverse_authority) = self.find_reverse_authority(address).await else {
+        self.forward.overrides = overrides;
+        self.forward.snapshot = None;
+    }
+
+    /// The addresses `name` resolves to in the forward zone, manual records included.
+    fn addresses(&self, name: &Name) -> Vec<IpAddr> {
+        [RecordType::A, RecordType::AAAA]
+            .into_iter()
+            .filter_map(|record_type| {
+                let key = RrKey::new(LowerName::new(name), record_type);
+
+                self.forward
+                    .overrides
+                    .get(&key)
+                    .or_else(|| self.forward.records.get(&key))
+            })
+            .flat_map(|record_set| record_set.records_without_rrsigs())
+            .filter_map(|record| record.data.ip_addr())
+            .collect()
+    }
+
+    /// The most specific reverse zone containing `ip`, that's the one resolvers will ask.
+    fn find_reverse_zone(&mut self, ip: IpAddr) -> Option<(IpNet, &mut Zone)> {
+        let network = self.reverse.longest_match(IpNet::from(ip))?;
+
+        self.reverse
+            .get_mut(&network)
+            .map(|reverse_zone| (network, &mut reverse_zone.zone))
+    }
+
+    /// Registers the reverse zone for `network`, returns whether anything changed.
+    fn add_reverse_zone(&mut self, network: IpNet, origin: Name) -> bool {
+        if let Some(reverse_zone) = self.reverse.get_mut(&network) {
+            reverse_zone.references += 1;
+
+            return false;
+        }
+
+        let classless_parent = is_classless(network).then(|| classless_parent(network));
+
+        if let Some((parent_network, ref parent_origin)) = classless_parent {
+            self.add_reverse_zone(parent_network, parent_origin.clone());
+        }
+
+        let mut zone = Zone::new(origin);
+
+        // the enclosing zone held the PTR records for this network so far,
+        // but queries for them will be sent to the new zone from now on
+        if let Some(parent_network) = self.reverse.longest_match(network)
+            && let Some(parent) = self.reverse.get_mut(&parent_network)
+        {
+            move_ptr_records(
+                (parent_network, &mut parent.zone),
+                (network, &mut zone),
+                network,
+            );
+
+            // RFC 2317: the parent points every address at its name in the classless zone
+            if let Some((classless_parent_network, _)) = classless_parent
+                && classless_parent_network == parent_network
+                && let IpNet::V4(net) = network
+            {
+                for ip in net.hosts().map(IpAddr::V4) {
+                    let owner = Name::from(ip);
+
+                    append_to_record_set(
+                        parent.zone.records_mut(),
+                        RrKey::new(LowerName::new(&owner), RecordType::CNAME),
+                        Cow::Owned(owner),
+                        RecordType::CNAME,
+                        RData::CNAME(CNAME(ptr_name(ip, network, &zone.origin))),
+                    );
+                }
+            }
+        }
+
+        self.reverse.insert(
+            network,
+            ReverseZone {
+                zone,
+                references: 1,
+            },
+        );
+
+        true
+    }
+
+    /// Releases the reverse zone for `network`, returns whether anything changed.
+    fn remove_reverse_zone(&mut self, network: IpNet) -> bool {
+        let Some(reverse_zone) = self.reverse.get_mut(&network) else {
+            return false;
+        };
+
+        reverse_zone.references -= 1;
+
+        if reverse_zone.references > 0 {
+            return false;
+        }
+
+        let Some(ReverseZone { mut zone, .. }) = self.reverse.remove(&network) else {
+            return false;
+        };
+
+        // hand the PTR records back to the enclosing zone, if any
+        if let Some(parent_network) = self.reverse.longest_match(network)
+            && let Some(parent) = self.reverse.get_mut(&parent_network)
+        {
+            if is_classless(network)
+                && let IpNet::V4(net) = network
+            {
+                for ip in net.hosts().map(IpAddr::V4) {
+                    parent.zone.records_mut().remove(&RrKey::new(
+                        LowerName::new(&Name::from(ip)),
+                        RecordType::CNAME,
+                    ));
+                }
+            }
+
+            move_ptr_records(
+                (network, &mut zone),
+                (parent_network, &mut parent.zone),
+                network,
+            );
+        }
+
+        if is_classless(network) {
+            let (parent_network, _) = classless_parent(network);
+
+            self.remove_reverse_zone(parent_network);
+        }
+
+        true
+    }
+
+    fn build_catalog(&mut self) -> Catalog {
+        let mut catalog = Catalog::new();
+
+        catalog.upsert(
+            LowerName::new(&self.forward.origin),
+            vec![self.forward.snapshot()],
+        );
+
+        for &mut ReverseZone { ref mut zone, .. } in self.reverse.values_mut() {
+            catalog.upsert(LowerName::new(&zone.origin), vec![zone.snapshot()]);
+        }
+
+        catalog
+    }
+
+    /// Records that `daemon` has `name` at `address`, returns whether the record is new.
+    fn claim(&mut self, daemon: &str, name: &Name, address: IpAddr) -> bool {
+        let owners = self
+            .owners
+            .entry((LowerName::new(name), address))
+            .or_default();
+
+        owners.insert(Box::from(daemon)) && owners.len() == 1
+    }
+
+    /// Drops `daemon`'s claim, returns whether it was the last one. Fails if it had none.
+    fn release(&mut self, daemon: &str, name: &Name, address: IpAddr) -> Result<bool, ()> {
+        let key = (LowerName::new(name), address);
+
+        let Some(owners) = self.owners.get_mut(&key) else {
+            return Err(());
+        };
+
+        if !owners.remove(daemon) {
+            return Err(());
+        }
+
+        if owners.is_empty() {
+            self.owners.remove(&key);
+
+            return Ok(true);
+        }
+
+        Ok(false)
+    }
+
+    fn upsert(&mut self, name: &Name, address: IpAddr) {
+        let rdata: RData = RData::from(address);
+        let record_type = rdata.record_type();
+
+        append_to_record_set(
+            self.forward.records_mut(),
+            RrKey::new(LowerName::new(name), record_type),
+            Cow::Borrowed(name),
+            record_type,
+            rdata,
+        );
+
+        self.upsert_ptr(name, address);
+    }
+
+    fn upsert_ptr(&mut self, name: &Name, address: IpAddr) {
+        let Some((network, reverse_zone)) = self.find_reverse_zone(address) else {
             event!(
                 Level::WARN,
                 %address,
@@ -121,10 +615,10 @@ impl AuthorityWrapper {
             return;
         };
 
-        let mut lock = reverse_authority.records_mut().await;
+        let reverse = ptr_name(address, network, &reverse_zone.origin);
 
         append_to_record_set(
-            &mut lock,
+            reverse_zone.records_mut(),
             RrKey::new(LowerName::new(&reverse), RecordType::PTR),
             Cow::Owned(reverse),
             RecordType::PTR,
@@ -132,75 +626,76 @@ impl AuthorityWrapper {
         );
     }
 
-    pub async fn add(&self, name: &Name, address: IpAddr) {
-        self.upsert(name, address).await;
+    /// Moves `daemon`'s addresses for `old_name` to `new_name`, other daemons keep theirs.
+    #[instrument(skip_all, fields(%daemon, %old_name, %new_name))]
+    fn rename_records(&mut self, daemon: &str, old_name: &Name, new_name: &Name) -> Result<(), ()> {
+        let old_key = LowerName::new(old_name);
 
-        event!(Level::INFO, %name, %address, "Added record");
-    }
+        let addresses = self
+            .owners
+            .iter()
+            .filter(|&(&(ref name, _), owners)| *name == old_key && owners.contains(daemon))
+            .map(|(&(_, address), _)| address)
+            .collect::<Vec<_>>();
 
-    #[instrument(skip_all, fields(old_name = %old_key.name, %new_name, r#type = %old_key.record_type))]
-    async fn rename_records(&self, old_key: &RrKey, new_name: &Name) -> Result<(), ()> {
-        let ips: Vec<IpAddr> = {
-            let mut records = self.forward.records_mut().await;
+        if addresses.is_empty() {
+            return Err(());
+        }
 
-            let Some(record_set) = records.remove(old_key) else {
-                return Err(());
-            };
+        // checked before anything moves, so a failure leaves the zones as they were
+        let moves = addresses
+            .into_iter()
+            .map(|address| {
+                let last = self
+                    .owners
+                    .get(&(old_key.clone(), address))
+                    .is_some_and(|owners| owners.len() == 1);
 
-            record_set
-                .records_without_rrsigs()
-                .filter_map(|r| r.data.ip_addr())
-                .collect()
+                (address, last)
+            })
+            .collect::<Vec<_>>();
 
-            // forward lock released before touching reverse zones
-        };
+        if moves
+            .iter()
+            .any(|&(address, last)| last && !self.has_record(old_name, address))
+        {
+            return Err(());
+        }
 
-        for ip in ips {
-            if let Some(reverse_authority) = self.find_reverse_authority(ip).await {
-                remove_from_ptr_set(
-                    &mut *reverse_authority.records_mut().await,
-                    ip,
-                    &old_key.name,
-                );
+        for (address, last) in moves {
+            self.release(daemon, old_name, address)?;
+
+            if last {
+                self.remove_record(old_name, address)?;
             }
 
-            self.upsert(new_name, ip).await;
+            if self.claim(daemon, new_name, address) {
+                self.upsert(new_name, address);
+            }
 
-            event!(Level::INFO, %ip, "Renamed record");
+            event!(Level::INFO, %address, "Renamed record");
         }
 
         Ok(())
     }
 
-    pub async fn rename(&self, old_name: &str, new_name: &str) -> Result<(), Report> {
-        let new_name_parsed: Name = new_name.parse()?;
-        let old_key: LowerName = old_name.strip_prefix('/').unwrap_or(old_name).parse()?;
-
-        let old_key_a = RrKey::new(old_key.clone(), RecordType::A);
-        let a_result = self.rename_records(&old_key_a, &new_name_parsed).await;
-
-        let old_key_aaaa = RrKey::new(old_key, RecordType::AAAA);
-        let aaaa_result = self.rename_records(&old_key_aaaa, &new_name_parsed).await;
-
-        if let (Err(()), Err(())) = (a_result, aaaa_result) {
-            event!(
-                Level::WARN,
-                %old_name,
-                %new_name,
-                "No A or AAAA records found"
-            );
-        }
+    fn has_record(&self, name: &Name, ip: IpAddr) -> bool {
+        let rdata = RData::from(ip);
+        let key = RrKey::new(LowerName::new(name), rdata.record_type());
 
-        Ok(())
+        self.forward.records.get(&key).is_some_and(|record_set| {
+            record_set
+                .records_without_rrsigs()
+                .any(|record| record.data == rdata)
+        })
     }
 
-    async fn remove_record(&self, name: &Name, ip: IpAddr) -> Result<(), ()> {
+    fn remove_record(&mut self, name: &Name, ip: IpAddr) -> Result<(), ()> {
         let record_type = RData::from(ip).record_type();
         let key = RrKey::new(LowerName::new(name), record_type);
 
         {
-            let mut records = self.forward.records_mut().await;
-            let Entry::Occupied(mut entry) = records.entry(key) else {
+            let Entry::Occupied(mut entry) = self.forward.records_mut().entry(key) else {
                 return Err(());
             };
 
@@ -219,22 +714,1057 @@ impl AuthorityWrapper {
             }
         }
 
-        if let Some(reverse_authority) = self.find_reverse_authority(ip).await {
-            remove_from_ptr_set(
-                &mut *reverse_authority.records_mut().await,
-                ip,
-                &LowerName::new(name),
-            );
+        if let Some((network, reverse_zone)) = self.find_reverse_zone(ip) {
+            let reverse = ptr_name(ip, network, &reverse_zone.origin);
+
+            remove_from_ptr_set(reverse_zone.records_mut(), reverse, &LowerName::new(name));
+        }
+
+        Ok(())
+    }
+}
+
+impl AuthorityWrapper {
+    pub fn new(domain: Name) -> Self {
+        let mut zones = Zones {
+            forward: Zone::new(domain),
+            reverse: PrefixTrie::new(),
+            manual: BTreeMap::new(),
+            owners: BTreeMap::new(),
+        };
+
+        let catalog = Arc::new(ArcSwap::from_pointee(zones.build_catalog()));
+
+        Self {
+            zones: Mutex::new(zones),
+            catalog,
+            published: watch::Sender::new(()),
+            audit_log: None,
+        }
+    }
+
+    /// Records every record change to `audit_log`.
+    pub fn with_audit_log(mut self, audit_log: AuditLog) -> Self {
+        self.audit_log = Some(audit_log);
+
+        self
+    }
+
+    /// The catalog to answer queries from, always the latest published snapshot.
+    ///
+    /// Readers load it without locking, changes are never visible half-applied.
+    pub fn catalog(&self) -> Arc<ArcSwap<Catalog>> {
+        Arc::clone(&self.catalog)
+    }
+
+    /// Changes every time a catalog is published.
+    pub fn subscribe(&self) -> watch::Receiver<()> {
+        self.published.subscribe()
+    }
+
+    /// Every record the forward zone serves, ordered by name and type.
+    pub async fn forward_records(&self) -> Vec<Record> {
+        let zones = self.lock_zones().await;
+
+        served_records(&zones.forward.records, &zones.forward.overrides)
+            .values()
+            .flat_map(|record_set| record_set.records_without_rrsigs())
+            .cloned()
+            .collect()
+    }
+
+    /// The forward zone, then every reverse zone, as they are served.
+    pub async fn zone_files(&self) -> Vec<ZoneFile> {
+        let mut zones = self.lock_zones().await;
+        let Zones {
+            ref forward,
+            ref mut reverse,
+            ..
+        } = *zones;
+
+        std::iter::once(forward.zone_file())
+            .chain(
+                reverse
+                    .values_mut()
+                    .map(|&mut ReverseZone { ref zone, .. }| zone.zone_file()),
+            )
+            .collect()
+    }
+
+    /// In a span of its own, so a trace tells waiting on other changes apart from making ours.
+    #[instrument(level = "debug", skip_all)]
+    async fn lock_zones(&self) -> MutexGuard<'_, Zones> {
+        self.zones.lock().await
+    }
+
+    /// Swaps in a catalog built from the current state. Only zones that changed since
+    /// the previous one are rebuilt, the others are shared between both.
+    fn publish(&self, zones: &mut Zones) {
+        self.catalog.store(Arc::new(zones.build_catalog()));
+        self.published.send_replace(());
+    }
+
+    /// Makes `change`, and records it in the audit log when it succeeds.
+    ///
+    /// Called with the zones locked, so entries are recorded in the order the changes are made.
+    /// An entry is about a name's A and AAAA records, the PTR records that follow them aren't
+    /// entries of their own, they're derived from those.
+    fn audited(
+        &self,
+        zones: &mut Zones,
+        context: AuditContext<'_>,
+        change: impl FnOnce(&mut Zones) -> Result<(), ()>,
+    ) -> Result<(), ()> {
+        let Some(ref audit_log) = self.audit_log else {
+            return change(zones);
+        };
+
+        let before = RecordState::new(context.before, zones.addresses(context.before));
+
+        change(zones)?;
+
+        let after = RecordState::new(context.after, zones.addresses(context.after));
+
+        audit_log.record(AuditEntry::new(
+            context.action,
+            context.address,
+            context.trigger.cloned(),
+            before,
+            after,
+        ));
+
+        Ok(())
+    }
+
+    pub async fn manual_records(&self) -> Vec<ManualRecord> {
+        self.lock_zones().await.manual.values().cloned().collect()
+    }
+
+    /// Adds `record`, or updates its expiry when it's already there.
+    pub async fn add_manual_record(&self, record: ManualRecord) -> Result<(), Report> {
+        let mut zones = self.lock_zones().await;
+
+        if !zones.forward.origin.zone_of(&record.name) {
+            return Err(Report::msg(format!(
+                "{} is not in {}",
+                record.name, zones.forward.origin
+            )));
         }
 
-        event!(Level::INFO, %name, %ip, "Removed record");
+        event!(Level::INFO, name = %record.name, address = %record.address, expires = ?record.expires, "Added manual record");
+
+        let (name, address) = (record.name.clone(), record.address);
+
+        let _added = self.audited(
+            &mut zones,
+            AuditContext {
+                action: Action::Add,
+                address: Some(address),
+                trigger: Some(&Trigger::admin("admin:add")),
+                before: &name,
+                after: &name,
+            },
+            |zones| {
+                zones
+                    .manual
+                    .insert((LowerName::new(&name), address), record);
+                zones.rebuild_overrides();
+
+                Ok(())
+            },
+        );
+
+        self.publish(&mut zones);
 
         Ok(())
     }
 
-    pub async fn remove_address(&self, name: &Name, ip: IpAddr) {
-        if self.remove_record(name, ip).await.is_err() {
-            event!(Level::WARN, %name, %ip, "No record found to remove");
+    /// Returns whether there was such a record.
+    pub async fn remove_manual_record(&self, name: &Name, address: IpAddr) -> bool {
+        let mut zones = self.lock_zones().await;
+
+        if self
+            .remove_manual(&mut zones, name, address, "admin:remove")
+            .is_err()
+        {
+            return false;
+        }
+
+        event!(Level::INFO, %name, %address, "Removed manual record");
+
+        self.publish(&mut zones);
+
+        true
+    }
+
+    /// Removes the manual records that expired by `now`.
+    pub async fn expire_manual_records(&self, now: SystemTime) {
+        let mut zones = self.lock_zones().await;
+
+        let expired = zones
+            .manual
+            .values()
+            .filter(|record| record.expires.is_some_and(|expires| expires <= now))
+            .map(|record| (record.name.clone(), record.address))
+            .collect::<Vec<_>>();
+
+        if expired.is_empty() {
+            return;
+        }
+
+        for (name, address) in expired {
+            event!(Level::INFO, %name, %address, "Manual record expired");
+
+            let _removed = self.remove_manual(&mut zones, &name, address, "expired");
+        }
+
+        self.publish(&mut zones);
+    }
+
+    /// Removes a manual record, audited as `event`. Fails when there's no such record.
+    fn remove_manual(
+        &self,
+        zones: &mut Zones,
+        name: &Name,
+        address: IpAddr,
+        event: &str,
+    ) -> Result<(), ()> {
+        self.audited(
+            zones,
+            AuditContext {
+                action: Action::Remove,
+                address: Some(address),
+                trigger: Some(&Trigger::admin(event)),
+                before: name,
+                after: name,
+            },
+            |zones| {
+                zones
+                    .manual
+                    .remove(&(LowerName::new(name), address))
+                    .ok_or(())?;
+                zones.rebuild_overrides();
+
+                Ok(())
+            },
+        )
+    }
+
+    pub async fn add_reverse_zone(&self, network: IpNet, origin: Name) {
+        let mut zones = self.lock_zones().await;
+
+        if zones.add_reverse_zone(network, origin) {
+            self.publish(&mut zones);
+        }
+    }
+
+    pub async fn remove_reverse_zone(&self, network: &IpNet) {
+        let mut zones = self.lock_zones().await;
+
+        if zones.remove_reverse_zone(*network) {
+            self.publish(&mut zones);
+        }
+    }
+
+    /// Adds the PTR records for static records, creating a /24 (IPv4) or /64 (IPv6) reverse zone
+    /// for each of them. Their forward records are answered by the request handler.
+    ///
+    /// The zones stay for the lifetime of the process, networks that overlap them
+    /// take over the part they cover.
+    ///
+    /// Audited with the static addresses of the name, its forward records aren't in the zones.
+    pub async fn add_static_records(&self, records: &[RawRecord]) {
+        if records.is_empty() {
+            return;
+        }
+
+        let mut zones = self.lock_zones().await;
+
+        for (index, record) in records.iter().enumerate() {
+            let network = match record.addr {
+                IpAddr::V4(address) => IpNet::V4(
+                    Ipv4Net::new(address, STATIC_PREFIX_LEN_V4)
+                        .expect("Valid IPv4 prefix length")
+                        .trunc(),
+                ),
+                IpAddr::V6(address) => IpNet::V6(
+                    Ipv6Net::new(address, STATIC_PREFIX_LEN_V6)
+                        .expect("Valid IPv6 prefix length")
+                        .trunc(),
+                ),
+            };
+
+            let Some(origin) = zone_name(network) else {
+                event!(Level::WARN, %network, "Failed to compute reverse zone name for static record, skipping");
+                continue;
+            };
+
+            zones.add_reverse_zone(network, origin);
+            zones.upsert_ptr(&record.name, record.addr);
+
+            event!(Level::INFO, name = %record.name, address = %record.addr, "Added static PTR record");
+
+            if let Some(ref audit_log) = self.audit_log {
+                let before = records
+                    .iter()
+                    .take(index)
+                    .filter(|earlier| earlier.name == record.name)
+                    .map(|earlier| earlier.addr)
+                    .collect::<Vec<_>>();
+                let after = before.iter().copied().chain([record.addr]).collect();
+
+                audit_log.record(AuditEntry::new(
+                    Action::Add,
+                    Some(record.addr),
+                    Some(Trigger::admin("static")),
+                    RecordState::new(&record.name, before),
+                    RecordState::new(&record.name, after),
+                ));
+            }
+        }
+
+        self.publish(&mut zones);
+    }
+
+    /// Applies all changes, then publishes them at once.
+    ///
+    /// Queries see either none or all of them, never e.g. a container's A record without its AAAA.
+    #[instrument(skip_all, fields(changes = change_set.changes.len()))]
+    pub async fn apply(&self, change_set: ChangeSet) {
+        if change_set.changes.is_empty() {
+            return;
+        }
+
+        let mut zones = self.lock_zones().await;
+        let trigger = change_set.trigger.as_ref();
+        let daemon = change_set.daemon();
+
+        for change in &change_set.changes {
+            match *change {
+                Change::Add { ref name, address } => {
+                    if !zones.claim(daemon, name, address) {
+                        event!(Level::DEBUG, %name, %address, "Record is already served");
+                        continue;
+                    }
+
+                    let added = self.audited(
+                        &mut zones,
+                        AuditContext {
+                            action: Action::Add,
+                            address: Some(address),
+                            trigger,
+                            before: name,
+                            after: name,
+                        },
+                        |zones| {
+                            zones.upsert(name, address);
+
+                            Ok(())
+                        },
+                    );
+
+                    if added.is_ok() {
+                        event!(Level::INFO, %name, %address, "Added record");
+                    }
+                },
+                Change::Remove { ref name, address } => {
+                    let removed = match zones.release(daemon, name, address) {
+                        Ok(true) => self.audited(
+                            &mut zones,
+                            AuditContext {
+                                action: Action::Remove,
+                                address: Some(address),
+                                trigger,
+                                before: name,
+                                after: name,
+                            },
+                            |zones| zones.remove_record(name, address),
+                        ),
+                        Ok(false) => {
+                            event!(Level::DEBUG, %name, ip = %address, "Record is still served for another daemon");
+                            continue;
+                        },
+                        Err(()) => Err(()),
+                    };
+
+                    if removed.is_err() {
+                        event!(Level::WARN, %name, ip = %address, "No record found to remove");
+                    } else {
+                        event!(Level::INFO, %name, ip = %address, "Removed record");
+                    }
+                },
+            }
+        }
+
+        self.publish(&mut zones);
+    }
+
+    /// Renames the records the trigger's daemon has for `old_name`.
+    pub async fn rename(
+        &self,
+        old_name: &str,
+        new_name: &str,
+        trigger: Trigger,
+    ) -> Result<(), Report> {
+        let new_name_parsed: Name = new_name.parse()?;
+        let old_name_parsed: Name = old_name.strip_prefix('/').unwrap_or(old_name).parse()?;
+
+        let mut zones = self.lock_zones().await;
+
+        let renamed = self.audited(
+            &mut zones,
+            AuditContext {
+                action: Action::Rename,
+                address: None,
+                trigger: Some(&trigger),
+                before: &old_name_parsed,
+                after: &new_name_parsed,
+            },
+            |zones| zones.rename_records(&trigger.daemon, &old_name_parsed, &new_name_parsed),
+        );
+
+        if renamed.is_err() {
+            event!(
+                Level::WARN,
+                %old_name,
+                %new_name,
+                "No A or AAAA records found"
+            );
+        } else {
+            self.publish(&mut zones);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    use hickory_server::proto::rr::rdata::CNAME;
+    use hickory_server::proto::rr::{Label, LowerName, Name, RData, RecordType, RrKey};
+    use pretty_assertions::assert_eq;
+    use serde_json::{Value as JsonValue, json};
+    use tokio_util::sync::CancellationToken;
+
+    use crate::audit::{Action, AuditLog, AuditWriter, RecordState, Trigger};
+    use crate::config::RawRecord;
+    use crate::table::{AuthorityWrapper, ChangeSet, ManualRecord, Zone, append_to_record_set};
+
+    const ADDRESS: IpAddr = IpAddr::V4(Ipv4Addr::new(172, 17, 0, 2));
+    const ADDRESS_V6: IpAddr = IpAddr::V6(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 2));
+    /// What a manual record pins a name to instead.
+    const OVERRIDE: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 9));
+
+    #[tokio::test]
+    async fn snapshot_is_not_affected_by_later_changes() {
+        let name: Name = "web.docker.".parse().unwrap();
+        let key = RrKey::new(LowerName::new(&name), RecordType::A);
+
+        let mut zone = Zone::new("docker.".parse().unwrap());
+        let before = zone.snapshot();
+
+        append_to_record_set(
+            zone.records_mut(),
+            key.clone(),
+            Cow::Borrowed(&name),
+            RecordType::A,
+            RData::from(ADDRESS),
+        );
+
+        let after = zone.snapshot();
+
+        assert_eq!(before.records().await.get(&key), None);
+        assert_eq!(
+            after
+                .records()
+                .await
+                .get(&key)
+                .map(|set| set.records_without_rrsigs().count()),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn unchanged_zone_reuses_snapshot() {
+        let mut zone = Zone::new("docker.".parse().unwrap());
+
+        let first = zone.snapshot();
+        let second = zone.snapshot();
+
+        assert!(Arc::ptr_eq(&first, &second));
+    }
+
+    #[tokio::test]
+    async fn reverse_zones_are_published() {
+        let authority_wrapper = AuthorityWrapper::new("docker.".parse().unwrap());
+        let catalog = authority_wrapper.catalog();
+
+        let network = "172.17.0.0/16".parse().unwrap();
+        let zone_name: LowerName = "17.172.in-addr.arpa.".parse().unwrap();
+
+        let before = catalog.load_full();
+
+        authority_wrapper
+            .add_reverse_zone(network, Name::from(&zone_name))
+            .await;
+
+        assert!(before.find(&zone_name).is_none());
+        assert!(catalog.load().find(&zone_name).is_some());
+
+        authority_wrapper.remove_reverse_zone(&network).await;
+
+        assert!(catalog.load().find(&zone_name).is_none());
+    }
+
+    #[tokio::test]
+    async fn every_zone_is_exported() {
+        let authority_wrapper = AuthorityWrapper::new("docker.".parse().unwrap());
+
+        authority_wrapper
+            .add_reverse_zone(
+                "172.17.0.0/16".parse().unwrap(),
+                "17.172.in-addr.arpa.".parse().unwrap(),
+            )
+            .await;
+
+        let mut change_set = ChangeSet::default();
+        change_set.add(&"web.docker.".parse().unwrap(), ADDRESS);
+        authority_wrapper.apply(change_set).await;
+
+        let zone_files = authority_wrapper
+            .zone_files()
+            .await
+            .into_iter()
+            .map(|zone_file| zone_file.contents)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            zone_files,
+            [
+                "$ORIGIN docker.\n\
+                 docker. 3600 IN SOA docker. docker. 0 0 0 0 0\n\
+                 web.docker. 5 IN A 172.17.0.2\n",
+                "$ORIGIN 17.172.in-addr.arpa.\n\
+                 17.172.in-addr.arpa. 3600 IN SOA 17.172.in-addr.arpa. 17.172.in-addr.arpa. 0 0 0 0 0\n\
+                 2.0.17.172.in-addr.arpa. 5 IN PTR web.docker.\n",
+            ]
+        );
+    }
+
+    async fn has_ptr(authority_wrapper: &AuthorityWrapper, network: &str, ip: IpAddr) -> bool {
+        let key = RrKey::new(LowerName::new(&Name::from(ip)), RecordType::PTR);
+
+        authority_wrapper
+            .zones
+            .lock()
+            .await
+            .reverse
+            .get_mut(&network.parse().unwrap())
+            .is_some_and(|reverse_zone| reverse_zone.zone.records.contains_key(&key))
+    }
+
+    #[tokio::test]
+    async fn ptr_records_follow_most_specific_zone() {
+        let authority_wrapper = AuthorityWrapper::new("docker.".parse().unwrap());
+        let name: Name = "web.docker.".parse().unwrap();
+
+        authority_wrapper
+            .add_reverse_zone(
+                "172.17.0.0/16".parse().unwrap(),
+                "17.172.in-addr.arpa.".parse().unwrap(),
+            )
+            .await;
+        let mut change_set = ChangeSet::default();
+        change_set.add(&name, ADDRESS);
+        authority_wrapper.apply(change_set).await;
+
+        assert!(has_ptr(&authority_wrapper, "172.17.0.0/16", ADDRESS).await);
+
+        authority_wrapper
+            .add_reverse_zone(
+                "172.17.0.0/24".parse().unwrap(),
+                "0.17.172.in-addr.arpa.".parse().unwrap(),
+            )
+            .await;
+
+        assert!(!has_ptr(&authority_wrapper, "172.17.0.0/16", ADDRESS).await);
+        assert!(has_ptr(&authority_wrapper, "172.17.0.0/24", ADDRESS).await);
+
+        authority_wrapper
+            .remove_reverse_zone(&"172.17.0.0/24".parse().unwrap())
+            .await;
+
+        assert!(has_ptr(&authority_wrapper, "172.17.0.0/16", ADDRESS).await);
+    }
+
+    #[tokio::test]
+    async fn shared_reverse_zone_outlives_first_removal() {
+        let authority_wrapper = AuthorityWrapper::new("docker.".parse().unwrap());
+        let catalog = authority_wrapper.catalog();
+
+        let network = "172.17.0.0/16".parse().unwrap();
+        let zone_name: LowerName = "17.172.in-addr.arpa.".parse().unwrap();
+
+        for _ in 0..2 {
+            authority_wrapper
+                .add_reverse_zone(network, Name::from(&zone_name))
+                .await;
+        }
+
+        authority_wrapper.remove_reverse_zone(&network).await;
+
+        assert!(catalog.load().find(&zone_name).is_some());
+
+        authority_wrapper.remove_reverse_zone(&network).await;
+
+        assert!(catalog.load().find(&zone_name).is_none());
+    }
+
+    #[tokio::test]
+    async fn change_set_is_published_at_once() {
+        let authority_wrapper = AuthorityWrapper::new("docker.".parse().unwrap());
+        let catalog = authority_wrapper.catalog();
+        let name: Name = "web.docker.".parse().unwrap();
+
+        let before = catalog.load_full();
+
+        authority_wrapper.apply(ChangeSet::default()).await;
+
+        assert!(Arc::ptr_eq(&before, &catalog.load_full()));
+
+        let mut change_set = ChangeSet::default();
+        change_set.add(&name, ADDRESS);
+        change_set.add(&name, ADDRESS_V6);
+        authority_wrapper.apply(change_set).await;
+
+        let forward = authority_wrapper.zones.lock().await.forward.snapshot();
+        let records = forward.records().await;
+
+        for record_type in [RecordType::A, RecordType::AAAA] {
+            assert!(records.contains_key(&RrKey::new(LowerName::new(&name), record_type)));
+        }
+    }
+
+    #[tokio::test]
+    async fn classless_zone_is_delegated_from_parent() {
+        let authority_wrapper = AuthorityWrapper::new("docker.".parse().unwrap());
+        let catalog = authority_wrapper.catalog();
+
+        let network = "192.168.1.64/26".parse().unwrap();
+        let parent_name: Name = "1.168.192.in-addr.arpa.".parse().unwrap();
+        let origin = parent_name
+            .prepend_label(Label::from_raw_bytes(b"64/26").unwrap())
+            .unwrap();
+
+        authority_wrapper
+            .add_reverse_zone(network, origin.clone())
+            .await;
+
+        let mut change_set = ChangeSet::default();
+        change_set.add(
+            &"web.docker.".parse().unwrap(),
+            IpAddr::V4(Ipv4Addr::new(192, 168, 1, 65)),
+        );
+        authority_wrapper.apply(change_set).await;
+
+        let target = origin
+            .prepend_label(Label::from_raw_bytes(b"65").unwrap())
+            .unwrap();
+
+        {
+            let mut zones = authority_wrapper.zones.lock().await;
+
+            let parent = &zones
+                .reverse
+                .get_mut(&"192.168.1.0/24".parse().unwrap())
+                .unwrap()
+                .zone;
+
+            let cname = parent
+                .records
+                .get(&RrKey::new(
+                    "65.1.168.192.in-addr.arpa.".parse().unwrap(),
+                    RecordType::CNAME,
+                ))
+                .unwrap();
+
+            assert_eq!(
+                cname.records_without_rrsigs().next().unwrap().data,
+                RData::CNAME(CNAME(target.clone()))
+            );
+
+            let child = &zones.reverse.get_mut(&network).unwrap().zone;
+
+            assert!(
+                child
+                    .records
+                    .contains_key(&RrKey::new(LowerName::new(&target), RecordType::PTR))
+            );
+        }
+
+        authority_wrapper.remove_reverse_zone(&network).await;
+
+        assert!(catalog.load().find(&LowerName::new(&origin)).is_none());
+        assert!(catalog.load().find(&LowerName::new(&parent_name)).is_none());
+    }
+
+    #[tokio::test]
+    async fn rename_leaves_other_daemons_records() {
+        let authority_wrapper = AuthorityWrapper::new("docker.".parse().unwrap());
+
+        let name: Name = "web.docker.".parse().unwrap();
+
+        for daemon in ["build1", "build2"] {
+            let mut change_set = ChangeSet::new(Trigger::new(daemon, "abc123", "container:start"));
+            change_set.add(&name, ADDRESS);
+            authority_wrapper.apply(change_set).await;
         }
+
+        authority_wrapper
+            .rename(
+                "/web.docker.",
+                "api.docker.",
+                Trigger::new("build1", "abc123", "container:rename"),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            authority_wrapper
+                .forward_records()
+                .await
+                .into_iter()
+                .filter(|record| record.record_type() == RecordType::A)
+                .map(|record| record.name.to_string())
+                .collect::<Vec<_>>(),
+            ["api.docker.", "web.docker."]
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_rename_changes_nothing() {
+        let authority_wrapper = AuthorityWrapper::new("docker.".parse().unwrap());
+
+        let name: Name = "web.docker.".parse().unwrap();
+
+        let mut change_set = ChangeSet::new(Trigger::new("build1", "abc123", "container:start"));
+        change_set.add(&name, ADDRESS);
+        change_set.add(&name, ADDRESS_V6);
+        authority_wrapper.apply(change_set).await;
+
+        // claimed, but no longer served
+        authority_wrapper
+            .zones
+            .lock()
+            .await
+            .remove_record(&name, ADDRESS_V6)
+            .unwrap();
+
+        authority_wrapper
+            .rename(
+                "/web.docker.",
+                "api.docker.",
+                Trigger::new("build1", "abc123", "container:rename"),
+            )
+            .await
+            .unwrap();
+
+        let zones = authority_wrapper.zones.lock().await;
+
+        assert_eq!(zones.addresses(&name), [ADDRESS]);
+        assert_eq!(
+            zones.addresses(&"api.docker.".parse().unwrap()),
+            Vec::<IpAddr>::new()
+        );
+        assert_eq!(zones.owners.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn static_records_are_audited() {
+        let path = std::env::temp_dir().join(format!(
+            "docker-dns-rs-{}-static-audit.log",
+            std::process::id()
+        ));
+        let _unused = std::fs::remove_file(&path);
+
+        let (audit_log, receiver) = AuditLog::new();
+        let writer = AuditWriter::open(path.clone()).await.unwrap();
+
+        let authority_wrapper =
+            AuthorityWrapper::new("docker.".parse().unwrap()).with_audit_log(audit_log);
+
+        authority_wrapper
+            .add_static_records(&[
+                RawRecord {
+                    name: "static.docker.".parse().unwrap(),
+                    addr: ADDRESS,
+                },
+                RawRecord {
+                    name: "static.docker.".parse().unwrap(),
+                    addr: ADDRESS_V6,
+                },
+            ])
+            .await;
+
+        let cancellation_token = CancellationToken::new();
+        cancellation_token.cancel();
+        writer.run(receiver, cancellation_token).await;
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let entries = contents
+            .lines()
+            .map(|line| {
+                let mut entry = serde_json::from_str::<JsonValue>(line).unwrap();
+                entry.as_object_mut().unwrap().remove("timestamp").unwrap();
+
+                entry
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            entries,
+            [
+                json!({
+                    "action": "add",
+                    "address": ADDRESS,
+                    "event": "static",
+                    "before": { "name": "static.docker.", "addresses": [] },
+                    "after": { "name": "static.docker.", "addresses": [ADDRESS] },
+                }),
+                json!({
+                    "action": "add",
+                    "address": ADDRESS_V6,
+                    "event": "static",
+                    "before": { "name": "static.docker.", "addresses": [ADDRESS] },
+                    "after": { "name": "static.docker.", "addresses": [ADDRESS, ADDRESS_V6] },
+                }),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn changes_are_audited() {
+        let (audit_log, mut receiver) = AuditLog::new();
+        let authority_wrapper =
+            AuthorityWrapper::new("docker.".parse().unwrap()).with_audit_log(audit_log);
+
+        let name: Name = "web.docker.".parse().unwrap();
+        let new_name: Name = "api.docker.".parse().unwrap();
+        let trigger = Trigger::new("local", "abc123", "container:start");
+
+        let mut change_set = ChangeSet::new(trigger.clone());
+        change_set.add(&name, ADDRESS);
+        change_set.add(&name, ADDRESS_V6);
+        // nothing to remove, so nothing to audit
+        change_set.remove(&new_name, ADDRESS);
+        authority_wrapper.apply(change_set).await;
+
+        authority_wrapper
+            .rename(
+                "/web.docker.",
+                "api.docker.",
+                Trigger::new("local", "abc123", "container:rename"),
+            )
+            .await
+            .unwrap();
+
+        let entries = std::iter::from_fn(|| receiver.try_recv().ok())
+            .map(|entry| {
+                (
+                    entry.action,
+                    entry.address,
+                    entry.trigger,
+                    entry.before,
+                    entry.after,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            entries,
+            [
+                (
+                    Action::Add,
+                    Some(ADDRESS),
+                    Some(trigger.clone()),
+                    RecordState::new(&name, vec![]),
+                    RecordState::new(&name, vec![ADDRESS]),
+                ),
+                (
+                    Action::Add,
+                    Some(ADDRESS_V6),
+                    Some(trigger),
+                    RecordState::new(&name, vec![ADDRESS]),
+                    RecordState::new(&name, vec![ADDRESS, ADDRESS_V6]),
+                ),
+                (
+                    Action::Rename,
+                    None,
+                    Some(Trigger::new("local", "abc123", "container:rename")),
+                    RecordState::new(&name, vec![ADDRESS, ADDRESS_V6]),
+                    RecordState::new(&new_name, vec![ADDRESS, ADDRESS_V6]),
+                ),
+            ]
+        );
+    }
+
+    async fn served(authority_wrapper: &AuthorityWrapper) -> Vec<String> {
+        authority_wrapper
+            .forward_records()
+            .await
+            .into_iter()
+            .filter(|record| record.name.to_string() == "web.docker.")
+            .map(|record| record.data.to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn manual_record_overrides_until_it_expires() {
+        let authority_wrapper = AuthorityWrapper::new("docker.".parse().unwrap());
+        let name: Name = "web.docker.".parse().unwrap();
+
+        let mut change_set = ChangeSet::default();
+        change_set.add(&name, ADDRESS);
+        authority_wrapper.apply(change_set).await;
+
+        let now = SystemTime::now();
+
+        authority_wrapper
+            .add_manual_record(ManualRecord {
+                name: name.clone(),
+                address: OVERRIDE,
+                expires: Some(now + Duration::from_secs(60)),
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(served(&authority_wrapper).await, ["10.0.0.9"]);
+
+        // Docker reconciliation doesn't touch the manual layer
+        let mut change_set = ChangeSet::default();
+        change_set.remove(&name, ADDRESS);
+        change_set.add(&name, ADDRESS);
+        authority_wrapper.apply(change_set).await;
+
+        assert_eq!(served(&authority_wrapper).await, ["10.0.0.9"]);
+
+        authority_wrapper
+            .expire_manual_records(now + Duration::from_secs(59))
+            .await;
+
+        assert_eq!(served(&authority_wrapper).await, ["10.0.0.9"]);
+
+        authority_wrapper
+            .expire_manual_records(now + Duration::from_secs(60))
+            .await;
+
+        assert_eq!(served(&authority_wrapper).await, ["172.17.0.2"]);
+        assert_eq!(authority_wrapper.manual_records().await, []);
+    }
+
+    #[tokio::test]
+    async fn manual_record_outside_the_zone_is_rejected() {
+        let authority_wrapper = AuthorityWrapper::new("docker.".parse().unwrap());
+
+        let error = authority_wrapper
+            .add_manual_record(ManualRecord {
+                name: "example.com.".parse().unwrap(),
+                address: OVERRIDE,
+                expires: None,
+            })
+            .await
+            .unwrap_err();
+
+        assert_eq!(error.to_string(), "example.com. is not in docker.");
+        assert_eq!(authority_wrapper.manual_records().await, []);
+    }
+
+    #[tokio::test]
+    async fn manual_records_are_audited() {
+        let (audit_log, mut receiver) = AuditLog::new();
+        let authority_wrapper =
+            AuthorityWrapper::new("docker.".parse().unwrap()).with_audit_log(audit_log);
+        let name: Name = "web.docker.".parse().unwrap();
+
+        let mut change_set = ChangeSet::default();
+        change_set.add(&name, ADDRESS);
+        authority_wrapper.apply(change_set).await;
+
+        let now = SystemTime::now();
+
+        for address in [OVERRIDE, ADDRESS_V6] {
+            authority_wrapper
+                .add_manual_record(ManualRecord {
+                    name: name.clone(),
+                    address,
+                    expires: (address == OVERRIDE).then(|| now + Duration::from_secs(60)),
+                })
+                .await
+                .unwrap();
+        }
+
+        assert!(
+            authority_wrapper
+                .remove_manual_record(&name, ADDRESS_V6)
+                .await
+        );
+        authority_wrapper
+            .expire_manual_records(now + Duration::from_secs(60))
+            .await;
+
+        let entries = std::iter::from_fn(|| receiver.try_recv().ok())
+            .skip(1)
+            .map(|entry| {
+                (
+                    entry.action,
+                    entry.address,
+                    entry.trigger.map(|trigger| trigger.event),
+                    entry.before.addresses,
+                    entry.after.addresses,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        // a manual record hides the container's records of the same type only
+        assert_eq!(
+            entries,
+            [
+                (
+                    Action::Add,
+                    Some(OVERRIDE),
+                    Some(Box::from("admin:add")),
+                    vec![ADDRESS],
+                    vec![OVERRIDE],
+                ),
+                (
+                    Action::Add,
+                    Some(ADDRESS_V6),
+                    Some(Box::from("admin:add")),
+                    vec![OVERRIDE],
+                    vec![OVERRIDE, ADDRESS_V6],
+                ),
+                (
+                    Action::Remove,
+                    Some(ADDRESS_V6),
+                    Some(Box::from("admin:remove")),
+                    vec![OVERRIDE, ADDRESS_V6],
+                    vec![OVERRIDE],
+                ),
+                (
+                    Action::Remove,
+                    Some(OVERRIDE),
+                    Some(Box::from("expired")),
+                    vec![OVERRIDE],
+                    vec![ADDRESS],
+                ),
+            ]
+        );
     }
 }
@@ -0,0 +1,83 @@
+use std::fmt::Write as _;
+
+use hickory_server::proto::rr::{Name, Record, RecordType};
+
+/// A zone in the RFC 1035 master file format.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ZoneFile {
+    pub origin: Name,
+    pub contents: String,
+}
+
+impl ZoneFile {
+    /// Owner names are written out in full, so the file doesn't depend on `$ORIGIN` to be read
+    /// correctly. The SOA record comes first, as RFC 1035 requires.
+    pub fn new<'r, I>(origin: Name, records: I) -> Self
+    where
+        I: IntoIterator<Item = &'r Record>,
+    {
+        let (soa, others): (Vec<_>, Vec<_>) = records
+            .into_iter()
+            .partition(|record| record.record_type() == RecordType::SOA);
+
+        let mut contents = format!("$ORIGIN {origin}\n");
+
+        for record in soa.into_iter().chain(others) {
+            writeln!(contents, "{record}").expect("Writing to a String never fails");
+        }
+
+        Self { origin, contents }
+    }
+
+    /// `<origin>zone`, classless zones get a `-` instead of their `/`.
+    pub fn file_name(&self) -> String {
+        let origin = self.origin.to_ascii().replace("\\/", "-").replace('\\', "");
+
+        format!("{origin}zone")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use hickory_server::proto::rr::{Name, RData, Record};
+    use ipnet::IpNet;
+    use pretty_assertions::assert_eq;
+
+    use crate::table::master_file::ZoneFile;
+    use crate::table::{soa_record, zone_name};
+
+    #[test]
+    fn soa_comes_first_and_names_are_absolute() {
+        let origin: Name = "docker.".parse().unwrap();
+
+        let records = [
+            Record::from_rdata(
+                "web.docker.".parse().unwrap(),
+                60,
+                RData::from(std::net::IpAddr::V4(Ipv4Addr::new(172, 17, 0, 2))),
+            ),
+            soa_record(origin.clone()),
+        ];
+
+        let zone_file = ZoneFile::new(origin, &records);
+
+        assert_eq!(
+            zone_file.contents,
+            "$ORIGIN docker.\n\
+             docker. 3600 IN SOA docker. docker. 0 0 0 0 0\n\
+             web.docker. 60 IN A 172.17.0.2\n"
+        );
+        assert_eq!(zone_file.file_name(), "docker.zone");
+    }
+
+    #[test]
+    fn classless_zone_file_name_has_no_slash() {
+        let origin = zone_name("192.168.1.0/26".parse::<IpNet>().unwrap()).unwrap();
+
+        let zone_file = ZoneFile::new(origin, &[]);
+
+        assert_eq!(zone_file.file_name(), "0-26.1.168.192.in-addr.arpa.zone");
+    }
+}
@@ -0,0 +1,266 @@
+use ipnet::IpNet;
+
+struct Node<V> {
+    value: Option<V>,
+    children: [Option<Box<Node<V>>>; 2],
+}
+
+impl<V> Node<V> {
+    const fn new() -> Self {
+        Self {
+            value: None,
+            children: [None, None],
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.value.is_none() && self.children.iter().all(Option::is_none)
+    }
+}
+
+/// A binary trie keyed by network, one level per prefix bit.
+///
+/// Lookups walk at most 32 (IPv4) or 128 (IPv6) levels, independent of the number of networks.
+/// Networks may overlap, lookups return the most specific one.
+pub struct PrefixTrie<V> {
+    v4: Node<V>,
+    v6: Node<V>,
+}
+
+/// The address as a left-aligned bit string, and how many of those bits are significant.
+fn to_bits(network: IpNet) -> (u128, u8) {
+    match network {
+        IpNet::V4(net) => (u128::from(net.network().to_bits()) << 96, net.prefix_len()),
+        IpNet::V6(net) => (net.network().to_bits(), net.prefix_len()),
+    }
+}
+
+fn bit(bits: u128, index: u8) -> usize {
+    usize::from((bits >> (127 - index)) & 1 == 1)
+}
+
+impl<V> PrefixTrie<V> {
+    pub const fn new() -> Self {
+        Self {
+            v4: Node::new(),
+            v6: Node::new(),
+        }
+    }
+
+    fn root(&self, network: IpNet) -> &Node<V> {
+        match network {
+            IpNet::V4(_) => &self.v4,
+            IpNet::V6(_) => &self.v6,
+        }
+    }
+
+    fn root_mut(&mut self, network: IpNet) -> &mut Node<V> {
+        match network {
+            IpNet::V4(_) => &mut self.v4,
+            IpNet::V6(_) => &mut self.v6,
+        }
+    }
+
+    /// Inserts `value` for `network`, returning the value it replaced.
+    pub fn insert(&mut self, network: IpNet, value: V) -> Option<V> {
+        let (bits, prefix_len) = to_bits(network);
+
+        let mut node = self.root_mut(network);
+
+        for index in 0..prefix_len {
+            node = node.children[bit(bits, index)].get_or_insert_with(|| Box::new(Node::new()));
+        }
+
+        node.value.replace(value)
+    }
+
+    pub fn get_mut(&mut self, network: &IpNet) -> Option<&mut V> {
+        let (bits, prefix_len) = to_bits(*network);
+
+        let mut node = self.root_mut(*network);
+
+        for index in 0..prefix_len {
+            node = node.children[bit(bits, index)].as_deref_mut()?;
+        }
+
+        node.value.as_mut()
+    }
+
+    /// Removes the value for exactly `network`, pruning the branches that are left empty.
+    pub fn remove(&mut self, network: &IpNet) -> Option<V> {
+        fn remove<V>(node: &mut Node<V>, bits: u128, index: u8, prefix_len: u8) -> Option<V> {
+            if index == prefix_len {
+                return node.value.take();
+            }
+
+            let child = &mut node.children[bit(bits, index)];
+
+            let removed = remove(child.as_deref_mut()?, bits, index + 1, prefix_len);
+
+            if child.as_deref().is_some_and(Node::is_empty) {
+                *child = None;
+            }
+
+            removed
+        }
+
+        let (bits, prefix_len) = to_bits(*network);
+
+        remove(self.root_mut(*network), bits, 0, prefix_len)
+    }
+
+    /// The most specific network that contains all of `network`, including `network` itself.
+    ///
+    /// Pass a host network (`/32`, `/128`) to look up an address.
+    pub fn longest_match(&self, network: IpNet) -> Option<IpNet> {
+        let (bits, prefix_len) = to_bits(network);
+
+        let mut node = self.root(network);
+        let mut longest = node.value.as_ref().map(|_| 0);
+
+        for index in 0..prefix_len {
+            let Some(child) = node.children[bit(bits, index)].as_deref() else {
+                break;
+            };
+
+            node = child;
+
+            if node.value.is_some() {
+                longest = Some(index + 1);
+            }
+        }
+
+        longest.map(|len| {
+            IpNet::new(network.addr(), len)
+                .expect("Shorter than a valid prefix length")
+                .trunc()
+        })
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut V> {
+        let mut stack = vec![&mut self.v4, &mut self.v6];
+
+        std::iter::from_fn(move || {
+            while let Some(node) = stack.pop() {
+                let &mut Node {
+                    ref mut value,
+                    ref mut children,
+                } = node;
+
+                stack.extend(children.iter_mut().filter_map(Option::as_deref_mut));
+
+                if let Some(value) = value.as_mut() {
+                    return Some(value);
+                }
+            }
+
+            None
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ipnet::IpNet;
+    use pretty_assertions::assert_eq;
+
+    use crate::table::prefix_trie::PrefixTrie;
+
+    fn net(s: &str) -> IpNet {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn longest_match_prefers_most_specific() {
+        let mut trie = PrefixTrie::new();
+
+        trie.insert(net("10.0.0.0/8"), 8);
+        trie.insert(net("10.1.0.0/16"), 16);
+        trie.insert(net("10.1.2.0/24"), 24);
+
+        assert_eq!(
+            trie.longest_match(net("10.1.2.3/32")),
+            Some(net("10.1.2.0/24"))
+        );
+        assert_eq!(
+            trie.longest_match(net("10.1.3.3/32")),
+            Some(net("10.1.0.0/16"))
+        );
+        assert_eq!(
+            trie.longest_match(net("10.2.0.1/32")),
+            Some(net("10.0.0.0/8"))
+        );
+        assert_eq!(trie.longest_match(net("11.0.0.1/32")), None);
+    }
+
+    #[test]
+    fn longest_match_of_network() {
+        let mut trie = PrefixTrie::new();
+
+        trie.insert(net("10.0.0.0/8"), ());
+        trie.insert(net("10.1.2.0/24"), ());
+
+        // the /24 is inside the /16, not the other way around
+        assert_eq!(
+            trie.longest_match(net("10.1.0.0/16")),
+            Some(net("10.0.0.0/8"))
+        );
+        assert_eq!(
+            trie.longest_match(net("10.1.2.0/24")),
+            Some(net("10.1.2.0/24"))
+        );
+    }
+
+    #[test]
+    fn families_are_separate() {
+        let mut trie = PrefixTrie::new();
+
+        trie.insert(net("0.0.0.0/0"), 4);
+
+        assert_eq!(trie.longest_match(net("::1/128")), None);
+
+        trie.insert(net("fd00::/64"), 6);
+
+        assert_eq!(
+            trie.longest_match(net("fd00::1/128")),
+            Some(net("fd00::/64"))
+        );
+        assert_eq!(
+            trie.longest_match(net("127.0.0.1/32")),
+            Some(net("0.0.0.0/0"))
+        );
+    }
+
+    #[test]
+    fn remove_keeps_other_networks() {
+        let mut trie = PrefixTrie::new();
+
+        trie.insert(net("10.0.0.0/8"), 8);
+        trie.insert(net("10.1.0.0/16"), 16);
+
+        assert_eq!(trie.remove(&net("10.1.0.0/16")), Some(16));
+        assert_eq!(trie.remove(&net("10.1.0.0/16")), None);
+
+        assert_eq!(
+            trie.longest_match(net("10.1.0.1/32")),
+            Some(net("10.0.0.0/8"))
+        );
+
+        assert_eq!(trie.remove(&net("10.0.0.0/8")), Some(8));
+        assert!(trie.v4.is_empty());
+    }
+
+    #[test]
+    fn values_mut_visits_all() {
+        let mut trie = PrefixTrie::new();
+
+        trie.insert(net("10.0.0.0/8"), 1);
+        trie.insert(net("10.1.0.0/16"), 2);
+        trie.insert(net("fd00::/64"), 3);
+
+        let mut values = trie.values_mut().map(|value| *value).collect::<Vec<_>>();
+        values.sort_unstable();
+
+        assert_eq!(values, vec![1, 2, 3]);
+    }
+}
@@ -2,6 +2,7 @@ use tokio::task::JoinHandle;
 
 use crate::shutdown::Shutdown;
 
+pub mod fs;
 pub mod task;
 
 pub async fn flatten_shutdown_handle(handle: JoinHandle<Shutdown>) -> Shutdown {
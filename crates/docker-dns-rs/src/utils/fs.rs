@@ -0,0 +1,73 @@
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre;
+use color_eyre::eyre::WrapErr as _;
+use tokio::io::AsyncWriteExt as _;
+
+/// Next to the file, renaming across file systems isn't atomic.
+fn temporary_path(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy())
+        .unwrap_or_default();
+
+    path.with_file_name(format!(".{file_name}.tmp"))
+}
+
+/// Fails when `write_atomically` couldn't replace the file as the user we run as, which needs
+/// write access to the file's directory. For `/etc/hosts` that's `/etc`.
+pub fn check_writable(path: &Path) -> Result<(), eyre::Report> {
+    let temporary = temporary_path(path);
+
+    std::fs::File::create(&temporary).wrap_err_with(|| {
+        format!(
+            "Cannot write {}, its directory has to be writable by the user we run as",
+            path.display()
+        )
+    })?;
+
+    std::fs::remove_file(&temporary)
+        .wrap_err_with(|| format!("Failed to remove {}", temporary.display()))
+}
+
+/// Replaces the file through a rename, readers see either the old or the new contents,
+/// never a partial file. A replaced file keeps its permissions.
+pub async fn write_atomically(path: &Path, contents: &[u8]) -> Result<(), eyre::Report> {
+    let temporary = temporary_path(path);
+
+    let write = async {
+        let mut file = tokio::fs::File::create(&temporary).await?;
+        file.write_all(contents).await?;
+
+        // on disk before it's renamed, a crash can't leave an empty file behind
+        file.sync_all().await
+    };
+
+    write
+        .await
+        .wrap_err_with(|| format!("Failed to write {}", temporary.display()))?;
+
+    if let Ok(metadata) = tokio::fs::metadata(path).await {
+        tokio::fs::set_permissions(&temporary, metadata.permissions()).await?;
+    }
+
+    tokio::fs::rename(&temporary, path)
+        .await
+        .wrap_err_with(|| format!("Failed to replace {}", path.display()))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::utils::fs::check_writable;
+
+    #[test]
+    fn unwritable_directory_is_refused() {
+        check_writable(&std::env::temp_dir().join("docker-dns-rs-writable")).unwrap();
+
+        let _report = check_writable(Path::new("/nonexistent/hosts")).unwrap_err();
+    }
+}